@@ -0,0 +1,159 @@
+use crate::*;
+
+type FingerId = ChordId;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryAddChordNodeResult {
+	DidNotAdd,
+	Added,
+	AddedWithEviction(ChordId),
+	CannotAddSelf,
+}
+
+/*
+	ChordMap
+	The finger table of a chord node. Each finger k starts at
+	(n + 2^(k-1)) mod 2^m and points at the first known node succeeding it.
+*/
+pub struct ChordMap {
+	self_id: ChordId,
+
+	// finger start -> finger node
+	finger_map: OrderedMap<FingerId, Option<ChordId>>,
+
+	// finger starts, finger k is at index k - 1
+	finger_vec: Vec<FingerId>,
+}
+
+impl ChordMap {
+	/*
+		Constructors
+	*/
+	pub fn new(self_id: ChordId) -> Self {
+		let mut finger_map = OrderedMap::new();
+		let mut finger_vec = Vec::with_capacity(KEYSPACE_SIZE);
+
+		for k in 1..=KEYSPACE_SIZE {
+			let finger = k_to_finger_index(&self_id, k as u16);
+			finger_map.insert(finger.clone(), None);
+			finger_vec.push(finger);
+		}
+
+		Self {
+			self_id,
+			finger_map,
+			finger_vec,
+		}
+	}
+
+	/*
+		Getters
+	*/
+	pub fn self_id(&self) -> &ChordId {
+		&self.self_id
+	}
+
+	pub fn fingers(&self) -> &Vec<FingerId> {
+		&self.finger_vec
+	}
+
+	// the start of finger k, 1 <= k <= m
+	pub fn finger_start(&self, k: usize) -> &FingerId {
+		&self.finger_vec[k - 1]
+	}
+
+	// the node of finger k, 1 <= k <= m
+	pub fn finger(&self, k: usize) -> Option<&ChordId> {
+		self.finger_map
+			.get(self.finger_start(k))
+			.and_then(|node| node.as_ref())
+	}
+
+	pub fn set_finger(&mut self, k: usize, node: Option<ChordId>) {
+		let start = self.finger_start(k).clone();
+		self.finger_map.insert(start, node);
+	}
+
+	// every distinct node in the table
+	pub fn nodes(&self) -> Vec<ChordId> {
+		self.finger_vec
+			.iter()
+			.filter_map(|start| self.finger_map.get(start).cloned().flatten())
+			.unique()
+			.collect()
+	}
+
+	/*
+		Add/Remove
+	*/
+
+	// point every finger at the node that it succeeds the finger start more
+	// closely than the current finger node
+	pub fn try_add(&mut self, new_node: ChordId) -> TryAddChordNodeResult {
+		if new_node == self.self_id {
+			return TryAddChordNodeResult::CannotAddSelf;
+		}
+
+		let mut result = TryAddChordNodeResult::DidNotAdd;
+		for start in self.finger_vec.iter() {
+			let finger_node = self.finger_map.get(start).cloned().flatten();
+			match finger_node {
+				Some(finger_node) if finger_node == new_node => {}
+				Some(finger_node) => {
+					if &new_node == start
+						|| is_between(&new_node, start, &finger_node)
+					{
+						self.finger_map
+							.insert(start.clone(), Some(new_node.clone()));
+						if !matches!(
+							result,
+							TryAddChordNodeResult::AddedWithEviction(_)
+						) {
+							result = TryAddChordNodeResult::AddedWithEviction(
+								finger_node,
+							);
+						}
+					}
+				}
+				None => {
+					self.finger_map
+						.insert(start.clone(), Some(new_node.clone()));
+					if result == TryAddChordNodeResult::DidNotAdd {
+						result = TryAddChordNodeResult::Added;
+					}
+				}
+			}
+		}
+		result
+	}
+
+	// clear every finger pointing at the node, true if any did
+	pub fn try_remove(&mut self, node: &ChordId) -> bool {
+		let mut removed = false;
+		for start in self.finger_vec.iter() {
+			if self.finger_map.get(start).cloned().flatten().as_ref()
+				== Some(node)
+			{
+				self.finger_map.insert(start.clone(), None);
+				removed = true;
+			}
+		}
+		removed
+	}
+
+	/*
+		Routing
+	*/
+
+	// the highest finger preceding id, or self
+	pub fn closest_preceding_node(&self, id: &ChordId) -> ChordId {
+		for start in self.finger_vec.iter().rev() {
+			if let Some(Some(node)) = self.finger_map.get(start) {
+				if is_between(node, &self.self_id, id) {
+					return node.clone();
+				}
+			}
+		}
+		self.self_id.clone()
+	}
+}
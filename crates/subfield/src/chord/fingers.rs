@@ -3,20 +3,58 @@ use num_bigint::BigUint;
 use num_traits::ToPrimitive;
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-pub type ChordId = PublicKey;
+pub type ChordId = V256;
 
-const KEYSPACE_SIZE: usize = 256;
+pub const KEYSPACE_SIZE: usize = 256;
+const KEYSPACE_BYTES: usize = KEYSPACE_SIZE / 8;
 
 lazy_static! {
 	static ref KEYSPACE_SIZE_BIGINT: BigUint = BigUint::from(256u32);
-    static ref _2_TO_KEYSPACE_SIZE: BigUint = BigUint::from(2u32).pow(KEYSPACE_SIZE as u32);
-	static ref _2K_MINUS_ONE_LOOKUP: Vec<BigUint> = (1..=KEYSPACE_SIZE).map(|k| BigUint::from(2u32).pow(k as u32)).collect();
+	static ref _2_TO_KEYSPACE_SIZE: BigUint = BigUint::from(2u32).pow(KEYSPACE_SIZE as u32);
+	static ref _2K_MINUS_ONE_LOOKUP: Vec<BigUint> = (0..KEYSPACE_SIZE).map(|k| BigUint::from(2u32).pow(k as u32)).collect();
 }
 
+// the start of the kth finger, (n + 2^(k-1)) mod 2^m, 1 <= k <= m
 pub fn k_to_finger_index(n: &ChordId, k: u16) -> ChordId {
 	let _2k_minus_1 = &_2K_MINUS_ONE_LOOKUP[k as usize - 1];
 	let index = (n.bigint() + _2k_minus_1) % _2_TO_KEYSPACE_SIZE.clone();
-	ChordId::new(V256::from_bigint(index))
+	bigint_to_chord_id(index)
+}
+
+// left pad to the keyspace width so ids order numerically
+pub fn bigint_to_chord_id(bigint: BigUint) -> ChordId {
+	let bytes = bigint.to_bytes_be();
+	let mut padded = vec![0u8; KEYSPACE_BYTES.saturating_sub(bytes.len())];
+	padded.extend_from_slice(&bytes);
+	V256::new(0, &padded)
+}
+
+/*
+	Ring Intervals
+*/
+
+// the clockwise distance from one id to another, (to - from) mod 2^m
+pub fn ring_distance(from: &ChordId, to: &ChordId) -> BigUint {
+	(_2_TO_KEYSPACE_SIZE.clone() + to.bigint() - from.bigint())
+		% _2_TO_KEYSPACE_SIZE.clone()
+}
+
+// id ∈ (from, to), the whole ring except from when from == to
+pub fn is_between(id: &ChordId, from: &ChordId, to: &ChordId) -> bool {
+	if from < to {
+		from < id && id < to
+	} else {
+		from < id || id < to
+	}
+}
+
+// id ∈ (from, to], the whole ring when from == to
+pub fn is_between_right_inclusive(
+	id: &ChordId,
+	from: &ChordId,
+	to: &ChordId,
+) -> bool {
+	id == to || is_between(id, from, to)
 }
 
 /*
@@ -30,7 +68,7 @@ impl RemoteChordNode {
 	pub fn new(id: ChordId, ip_address: Either<Ipv4Addr, Ipv6Addr>, ip_port: u16) -> Self {
 		Self { id, ip_address, ip_port }
 	}
-	
+
 	pub fn random() -> Self {
 		let id = ChordId::random();
 		let ip_address = Either::Left(Ipv4Addr::random());
@@ -42,11 +80,11 @@ impl RemoteChordNode {
 
 #[derive(Getters, Setters)]
 pub struct LocalChordNode {
-	
+
 	remote: RemoteChordNode,
-	
+
 	keypair: Keypair,
-	
+
 	pub predecessor: Option<ChordId>,
 	pub successor: Option<ChordId>,
 	// pub fingers: [ChordId; KEYSPACE_SIZE],
@@ -60,18 +98,18 @@ pub struct LocalChordNode {
 
 impl LocalChordNode {
 	pub fn new(keypair: Keypair, ip_address: Either<Ipv4Addr, Ipv6Addr>, ip_port: u16) -> Self {
-		
+
 		let remote = RemoteChordNode {
 			id: keypair.public_key().clone(),
 			ip_address,
 			ip_port,
 		};
-		
+
 		let fingers = Self::build_fingers(&remote.id);
 
 		Self { remote, keypair, predecessor: None, successor: None, fingers }
 	}
-	
+
 	pub fn random() -> Self {
 		let keypair = Keypair::random();
 		let ip_address = Either::Left(Ipv4Addr::random());
@@ -117,4 +155,4 @@ pub fn test_node_creation() {
 	let predecessor = local_node.find_predecessor(&key);
 	assert!(predecessor.unwrap().id < key);
 }
-*/
\ No newline at end of file
+*/
@@ -2,4 +2,10 @@ mod chord_map;
 pub use chord_map::*;
 
 mod fingers;
-pub use fingers::*;
\ No newline at end of file
+pub use fingers::*;
+
+mod node;
+pub use node::*;
+
+mod trait_chord;
+pub use trait_chord::*;
@@ -0,0 +1,408 @@
+use crate::*;
+use std::sync::{Mutex, MutexGuard};
+
+// how many successors are kept to survive successor failures
+pub const SUCCESSOR_LIST_SIZE: usize = 8;
+
+// the most remote nodes a lookup may be forwarded to
+pub const MAX_LOOKUP_HOPS: usize = KEYSPACE_SIZE;
+
+struct ChordState {
+	predecessor: Option<ChordId>,
+	// never empty, the first entry is the successor
+	successors: Vec<ChordId>,
+	fingers: ChordMap,
+	// the last finger fixed by fix_fingers
+	next_finger: usize,
+}
+
+/*
+	ChordNode
+	A chord node reaching the rest of the ring through a ChordTransport.
+	Lookups are iterative, every hop is a find_successor_step on a remote node.
+*/
+pub struct ChordNode<T: ChordTransport> {
+	id: ChordId,
+	transport: T,
+	state: Mutex<ChordState>,
+}
+
+impl<T: ChordTransport> ChordNode<T> {
+	/*
+		Constructors
+	*/
+
+	// a node alone in its own ring
+	pub fn new(id: ChordId, transport: T) -> Self {
+		let state = ChordState {
+			predecessor: None,
+			successors: vec![id.clone()],
+			fingers: ChordMap::new(id.clone()),
+			next_finger: 0,
+		};
+		Self {
+			id,
+			transport,
+			state: Mutex::new(state),
+		}
+	}
+
+	/*
+		Getters
+	*/
+	pub fn transport(&self) -> &T {
+		&self.transport
+	}
+
+	// every distinct node in the finger table
+	pub fn fingers(&self) -> Vec<ChordId> {
+		self.state().fingers.nodes()
+	}
+
+	fn state(&self) -> MutexGuard<'_, ChordState> {
+		self.state.lock().unwrap()
+	}
+
+	/*
+		Lookups
+	*/
+
+	// find the successor of id, counting the remote nodes asked
+	pub async fn lookup(
+		&self,
+		id: &ChordId,
+	) -> Result<ChordLookup, ChordError> {
+		self.lookup_from(self.id.clone(), id).await
+	}
+
+	async fn lookup_from(
+		&self,
+		start: ChordId,
+		id: &ChordId,
+	) -> Result<ChordLookup, ChordError> {
+		let mut hops = 0;
+		let mut next = start;
+
+		loop {
+			let step = if next == self.id {
+				self.find_successor_step(id)
+			} else {
+				hops += 1;
+				if hops > MAX_LOOKUP_HOPS {
+					return Err(ChordError::LookupHopLimitExceeded);
+				}
+				match self.transport.find_successor_step(&next, id).await {
+					Ok(step) => step,
+					Err(e) => {
+						self.forget(&next);
+						return Err(e);
+					}
+				}
+			};
+
+			match step {
+				ChordStep::Found(successor) => {
+					self.learn(&successor);
+					return Ok(ChordLookup { successor, hops });
+				}
+				ChordStep::Forward(node) => next = node,
+			}
+		}
+	}
+
+	/*
+		Membership
+	*/
+
+	// our successors are our successor's successors, up to ourselves
+	async fn successors_through(&self, successor: ChordId) -> Vec<ChordId> {
+		if successor == self.id {
+			return vec![successor];
+		}
+
+		let mut successors = vec![successor.clone()];
+		if let Ok(list) = self.transport.successor_list(&successor).await {
+			successors
+				.extend(list.into_iter().take_while(|node| node != &self.id));
+		}
+		successors
+			.into_iter()
+			.unique()
+			.take(SUCCESSOR_LIST_SIZE)
+			.collect()
+	}
+
+	async fn is_alive(&self, node: &ChordId) -> bool {
+		node == &self.id || self.transport.ping(node).await.is_ok()
+	}
+
+	// add a node to the finger table
+	fn learn(&self, node: &ChordId) {
+		if node != &self.id {
+			self.state().fingers.try_add(node.clone());
+		}
+	}
+
+	// drop every reference to a failed node
+	fn forget(&self, node: &ChordId) {
+		let mut state = self.state();
+		state.fingers.try_remove(node);
+		state.successors.retain(|successor| successor != node);
+		if state.successors.is_empty() {
+			state.successors.push(self.id.clone());
+		}
+		if state.predecessor.as_ref() == Some(node) {
+			state.predecessor = None;
+		}
+	}
+}
+
+#[async_trait]
+impl<T: ChordTransport> ChordTrait for ChordNode<T> {
+	fn id(&self) -> &ChordId {
+		&self.id
+	}
+
+	fn successor(&self) -> ChordId {
+		self.state().successors[0].clone()
+	}
+
+	fn predecessor(&self) -> Option<ChordId> {
+		self.state().predecessor.clone()
+	}
+
+	fn successor_list(&self) -> Vec<ChordId> {
+		self.state().successors.clone()
+	}
+
+	async fn find_successor(
+		&self,
+		id: &ChordId,
+	) -> Result<ChordId, ChordError> {
+		self.lookup(id).await.map(|lookup| lookup.successor)
+	}
+
+	fn find_successor_step(&self, id: &ChordId) -> ChordStep {
+		let successor = self.successor();
+		if is_between_right_inclusive(id, &self.id, &successor) {
+			return ChordStep::Found(successor);
+		}
+
+		match self.closest_preceding_node(id) {
+			node if node == self.id => ChordStep::Found(successor),
+			node => ChordStep::Forward(node),
+		}
+	}
+
+	// the fingers and successors are both candidates
+	fn closest_preceding_node(&self, id: &ChordId) -> ChordId {
+		let state = self.state();
+		let finger = state.fingers.closest_preceding_node(id);
+
+		state
+			.successors
+			.iter()
+			.chain(std::iter::once(&finger))
+			.filter(|node| is_between(node, &self.id, id))
+			.max_by_key(|node| ring_distance(&self.id, node))
+			.cloned()
+			.unwrap_or_else(|| self.id.clone())
+	}
+
+	fn create(&self) {
+		let mut state = self.state();
+		state.predecessor = None;
+		state.successors = vec![self.id.clone()];
+		state.fingers = ChordMap::new(self.id.clone());
+		state.next_finger = 0;
+	}
+
+	async fn join(&self, n: &ChordId) -> Result<(), ChordError> {
+		self.create();
+		// stabilize rejoins through n if the lookup fails, or the successors
+		// found have already failed
+		self.learn(n);
+
+		let successor = self.lookup_from(n.clone(), &self.id).await?.successor;
+		// take the successor's successors right away, in case it fails
+		// before the first stabilize
+		let successors = self.successors_through(successor).await;
+		self.state().successors = successors;
+		Ok(())
+	}
+
+	async fn stabilize(&self) -> Result<(), ChordError> {
+		// the first successor still alive
+		let mut successor = None;
+		for node in self.successor_list() {
+			if node == self.id {
+				break;
+			}
+			if self.is_alive(&node).await {
+				successor = Some(node);
+				break;
+			}
+			self.forget(&node);
+		}
+
+		// every successor has failed, rejoin through the closest live finger
+		if successor.is_none() {
+			let mut fingers = self.fingers();
+			fingers.sort_by_key(|node| ring_distance(&self.id, node));
+			for node in fingers {
+				if self.is_alive(&node).await {
+					successor =
+						Some(self.lookup_from(node, &self.id).await?.successor);
+					break;
+				}
+				self.forget(&node);
+			}
+		}
+		let mut successor = successor.unwrap_or_else(|| self.id.clone());
+
+		// x = successor.predecessor
+		let x = if successor == self.id {
+			self.predecessor()
+		} else {
+			match self.transport.predecessor(&successor).await {
+				Ok(x) => x,
+				Err(e) => {
+					self.forget(&successor);
+					return Err(e);
+				}
+			}
+		};
+
+		if let Some(x) = x {
+			if is_between(&x, &self.id, &successor) && self.is_alive(&x).await {
+				successor = x;
+			}
+		}
+
+		if successor == self.id {
+			self.state().successors = vec![successor];
+			return Ok(());
+		}
+
+		let successors = self.successors_through(successor.clone()).await;
+		self.learn(&successor);
+		self.state().successors = successors;
+
+		self.transport.notify(&successor, &self.id).await
+	}
+
+	fn notify(&self, n: &ChordId) {
+		if n == &self.id {
+			return;
+		}
+
+		{
+			let mut state = self.state();
+			let replace = match &state.predecessor {
+				None => true,
+				Some(predecessor) => is_between(n, predecessor, &self.id),
+			};
+			if replace {
+				state.predecessor = Some(n.clone());
+			}
+		}
+		self.learn(n);
+	}
+
+	// fixes the next finger, and every finger after it sharing its successor
+	async fn fix_fingers(&self) -> Result<(), ChordError> {
+		// alone every lookup resolves to ourselves, keep the fingers so
+		// stabilize can rejoin through them
+		if self.successor() == self.id {
+			return Ok(());
+		}
+
+		let (k, start) = {
+			let mut state = self.state();
+			state.next_finger = state.next_finger % KEYSPACE_SIZE + 1;
+			let k = state.next_finger;
+			(k, state.fingers.finger_start(k).clone())
+		};
+
+		let successor = self.find_successor(&start).await?;
+		let finger = (successor != self.id).then_some(successor);
+
+		let mut state = self.state();
+		state.fingers.set_finger(k, finger.clone());
+
+		let mut next = k;
+		while next < KEYSPACE_SIZE {
+			let start = state.fingers.finger_start(next + 1).clone();
+			let shared = match &finger {
+				Some(finger) => {
+					is_between_right_inclusive(&start, &self.id, finger)
+				}
+				None => true,
+			};
+			if !shared {
+				break;
+			}
+			next += 1;
+			state.fingers.set_finger(next, finger.clone());
+		}
+		state.next_finger = next;
+
+		Ok(())
+	}
+
+	async fn check_predecessor(&self) {
+		if let Some(predecessor) = self.predecessor() {
+			if !self.is_alive(&predecessor).await {
+				self.forget(&predecessor);
+			}
+		}
+	}
+
+	async fn leave(&self) -> Result<(), ChordError> {
+		let predecessor = self.predecessor();
+		let successors = self.successor_list();
+		let successor = successors[0].clone();
+
+		if successor != self.id {
+			self.transport
+				.leave(&successor, &self.id, predecessor.clone(), vec![])
+				.await?;
+		}
+		if let Some(predecessor) = predecessor {
+			if predecessor != self.id {
+				self.transport
+					.leave(&predecessor, &self.id, None, successors)
+					.await?;
+			}
+		}
+
+		self.create();
+		Ok(())
+	}
+
+	fn handle_leave(
+		&self,
+		leaving: &ChordId,
+		predecessor: Option<ChordId>,
+		successors: Vec<ChordId>,
+	) {
+		let was_predecessor = self.predecessor().as_ref() == Some(leaving);
+		let was_successor = &self.successor() == leaving;
+		self.forget(leaving);
+
+		let mut state = self.state();
+		if was_predecessor {
+			state.predecessor = predecessor.filter(|node| node != &self.id);
+		}
+		if was_successor {
+			let successors: Vec<ChordId> = successors
+				.into_iter()
+				.filter(|node| node != leaving)
+				.take_while(|node| node != &self.id)
+				.collect();
+			if !successors.is_empty() {
+				state.successors = successors;
+			}
+		}
+	}
+}
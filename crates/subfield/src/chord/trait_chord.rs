@@ -1,7 +1,65 @@
 use crate::*;
 
+#[derive(
+	Debug, Serialize, Deserialize, Clone, PartialEq, Eq, strum::Display,
+)]
+pub enum ChordError {
+	NodeUnreachable,
+	LookupHopLimitExceeded,
+}
 
-pub trait ChordTrait<ChordNode> {
+// one step of an iterative lookup, either the answer or the next node to ask
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum ChordStep {
+	Found(ChordId),
+	Forward(ChordId),
+}
+
+// the result of a lookup, and the number of remote nodes it was forwarded to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ChordLookup {
+	pub successor: ChordId,
+	pub hops: usize,
+}
+
+// how a chord node reaches the other nodes of the ring
+#[async_trait]
+pub trait ChordTransport: Send + Sync {
+	async fn find_successor_step(
+		&self,
+		node: &ChordId,
+		id: &ChordId,
+	) -> Result<ChordStep, ChordError>;
+
+	async fn predecessor(
+		&self,
+		node: &ChordId,
+	) -> Result<Option<ChordId>, ChordError>;
+
+	async fn successor_list(
+		&self,
+		node: &ChordId,
+	) -> Result<Vec<ChordId>, ChordError>;
+
+	async fn notify(
+		&self,
+		node: &ChordId,
+		candidate: &ChordId,
+	) -> Result<(), ChordError>;
+
+	async fn ping(&self, node: &ChordId) -> Result<(), ChordError>;
+
+	async fn leave(
+		&self,
+		node: &ChordId,
+		leaving: &ChordId,
+		predecessor: Option<ChordId>,
+		successors: Vec<ChordId>,
+	) -> Result<(), ChordError>;
+}
+
+#[async_trait]
+pub trait ChordTrait: Send + Sync {
 	// Pseudocode
 
 	// Definitions for pseudocode
@@ -15,6 +73,11 @@ pub trait ChordTrait<ChordNode> {
 	//     predecessor
 	//         the previous node from the node in question on the identifier ring
 
+	fn id(&self) -> &ChordId;
+	fn successor(&self) -> ChordId;
+	fn predecessor(&self) -> Option<ChordId>;
+	fn successor_list(&self) -> Vec<ChordId>;
+
 	// The pseudocode to find the successor node of an id is given below:
 
 	// // ask node n to find the successor of id
@@ -27,7 +90,11 @@ pub trait ChordTrait<ChordNode> {
 	//         // forward the query around the circle
 	//         n0 := closest_preceding_node(id)
 	//         return n0.find_successor(id)
-	fn find_successor(&self, id: &ChordId) -> ChordId;
+	async fn find_successor(&self, id: &ChordId)
+		-> Result<ChordId, ChordError>;
+
+	// the local half of find_successor, the query is forwarded iteratively
+	fn find_successor_step(&self, id: &ChordId) -> ChordStep;
 
 	// // search the local table for the highest predecessor of id
 	// n.closest_preceding_node(id)
@@ -43,13 +110,13 @@ pub trait ChordTrait<ChordNode> {
 	// n.create()
 	//     predecessor := nil
 	//     successor := n
-	fn create(&self) -> ChordNode;
+	fn create(&self);
 
 	// // join a Chord ring containing node n'.
 	// n.join(n')
 	//     predecessor := nil
 	//     successor := n'.find_successor(n)
-	fn join(&self, n: ChordNode) -> ChordNode;
+	async fn join(&self, n: &ChordId) -> Result<(), ChordError>;
 
 	// // called periodically. n asks the successor
 	// // about its predecessor, verifies if n's immediate
@@ -59,13 +126,13 @@ pub trait ChordTrait<ChordNode> {
 	//     if x ∈ (n, successor) then
 	//         successor := x
 	//     successor.notify(n)
-	fn stabilize(&self) -> ChordNode;
+	async fn stabilize(&self) -> Result<(), ChordError>;
 
 	// // n' thinks it might be our predecessor.
 	// n.notify(n')
 	//     if predecessor is nil or n'∈(predecessor, n) then
 	//         predecessor := n'
-	fn notify(&self, n: ChordNode) -> ChordNode;
+	fn notify(&self, n: &ChordId);
 
 	// // called periodically. refreshes finger table entries.
 	// // next stores the index of the finger to fix
@@ -74,11 +141,23 @@ pub trait ChordTrait<ChordNode> {
 	//     if next > m then
 	//         next := 1
 	//     finger[next] := find_successor(n+2next-1);
-	fn fix_fingers(&self) -> ChordNode;
+	async fn fix_fingers(&self) -> Result<(), ChordError>;
 
 	// // called periodically. checks whether predecessor has failed.
 	// n.check_predecessor()
 	//     if predecessor has failed then
 	//         predecessor := nil
-	fn check_predecessor(&self) -> ChordNode;
-}
\ No newline at end of file
+	async fn check_predecessor(&self);
+
+	// a graceful departure, the leaving node hands its predecessor to its
+	// successor and its successors to its predecessor
+	async fn leave(&self) -> Result<(), ChordError>;
+
+	// a neighbour is leaving the ring
+	fn handle_leave(
+		&self,
+		leaving: &ChordId,
+		predecessor: Option<ChordId>,
+		successors: Vec<ChordId>,
+	);
+}
@@ -1,7 +1,10 @@
 use crate::*;
 use chacha20poly1305::{
-	aead::{generic_array, Aead, AeadCore, KeyInit, OsRng},
-	ChaCha20Poly1305,
+	aead::{
+		generic_array::{self, GenericArray},
+		Aead, AeadCore, KeyInit, OsRng, Payload,
+	},
+	ChaCha20Poly1305, XChaCha20Poly1305,
 };
 
 pub type Plaintext = Vec<u8>;
@@ -9,19 +12,71 @@ pub type Ciphertext = Vec<u8>;
 pub type CipherSecretKey = V256;
 pub type CipherSecretKeyArray = [u8; 32];
 const NONCE_LENGTH: usize = 12;
+const X_NONCE_LENGTH: usize = 24;
 pub type Nonce = Vec<u8>; // [u8; NONCE_LENGTH];
 pub type NonceArray = [u8; NONCE_LENGTH];
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize, strum::Display)]
 pub enum CipherError {
+	#[strum(serialize = "InvalidNonce")]
 	InvalidNonce,
+	// the secret is not 32 bytes
+	#[strum(serialize = "InvalidKey")]
 	InvalidKey,
+	#[strum(serialize = "EncryptionFailed")]
+	EncryptionFailed,
+	// the key is wrong or the ciphertext was altered
+	#[strum(serialize = "DecryptionFailed")]
+	DecryptionFailed,
+	// the stream ended before its last chunk, or was used after it
+	#[strum(serialize = "StreamTruncated")]
+	StreamTruncated,
+	#[strum(serialize = "StreamFinished")]
+	StreamFinished,
+}
+
+impl From<CipherError> for JsValue {
+	fn from(e: CipherError) -> Self {
+		JsValue::from_str(&e.to_string())
+	}
 }
 
+// the AEAD a cipher encrypts with, XChaCha20-Poly1305 has nonces long enough
+// to be picked at random for any number of messages under one key
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum CipherAlgorithm {
+	#[default]
+	ChaCha20Poly1305,
+	XChaCha20Poly1305,
+}
+
+impl CipherAlgorithm {
+	pub fn nonce_length(&self) -> usize {
+		match self {
+			CipherAlgorithm::ChaCha20Poly1305 => NONCE_LENGTH,
+			CipherAlgorithm::XChaCha20Poly1305 => X_NONCE_LENGTH,
+		}
+	}
+}
+
+#[derive(Clone)]
+pub(crate) enum CipherAead {
+	ChaCha(ChaCha20Poly1305),
+	XChaCha(XChaCha20Poly1305),
+}
+
+/*
+	Cipher
+	Encrypts with a random nonce, sent in front of the ciphertext. Associated
+	data is authenticated but not encrypted, so a ciphertext only decrypts
+	alongside the data it was bound to.
+*/
 #[wasm_bindgen]
 pub struct Cipher {
 	secret: V256,
-	cipher: ChaCha20Poly1305,
+	algorithm: CipherAlgorithm,
+	cipher: CipherAead,
 }
 
 impl Cipher {
@@ -29,14 +84,38 @@ impl Cipher {
 	Constructors
 	*/
 
-	pub fn new(secret: CipherSecretKey) -> Cipher {
-		let key: [u8; 32] = secret.data().clone().try_into().unwrap();
-		let cipher = ChaCha20Poly1305::new(&generic_array::GenericArray::<
+	pub fn new(secret: CipherSecretKey) -> Result<Cipher, CipherError> {
+		let key: CipherSecretKeyArray = secret
+			.data()
+			.as_slice()
+			.try_into()
+			.map_err(|_| CipherError::InvalidKey)?;
+		Ok(Cipher::from_array(secret, key, CipherAlgorithm::default()))
+	}
+
+	fn from_array(
+		secret: CipherSecretKey,
+		key: CipherSecretKeyArray,
+		algorithm: CipherAlgorithm,
+	) -> Cipher {
+		let key = generic_array::GenericArray::<
 			u8,
 			generic_array::typenum::U32,
-		>::from(key));
+		>::from(key);
+		let cipher = match algorithm {
+			CipherAlgorithm::ChaCha20Poly1305 => {
+				CipherAead::ChaCha(ChaCha20Poly1305::new(&key))
+			}
+			CipherAlgorithm::XChaCha20Poly1305 => {
+				CipherAead::XChaCha(XChaCha20Poly1305::new(&key))
+			}
+		};
 
-		Cipher { secret, cipher }
+		Cipher {
+			secret,
+			algorithm,
+			cipher,
+		}
 	}
 
 	pub fn random_key() -> CipherSecretKey {
@@ -44,7 +123,14 @@ impl Cipher {
 	}
 
 	pub fn random() -> Cipher {
-		Cipher::new(Cipher::random_key())
+		let key: CipherSecretKeyArray = rand::random();
+		Cipher::from_array(V256::new(0, &key), key, CipherAlgorithm::default())
+	}
+
+	pub fn with_algorithm(self, algorithm: CipherAlgorithm) -> Self {
+		// the key was checked when the cipher was made
+		let key = self.key();
+		Cipher::from_array(self.secret, key, algorithm)
 	}
 
 	/*
@@ -54,43 +140,90 @@ impl Cipher {
 		&self.secret
 	}
 
+	pub fn algorithm(&self) -> CipherAlgorithm {
+		self.algorithm
+	}
+
+	pub(crate) fn key(&self) -> CipherSecretKeyArray {
+		let mut key = [0u8; 32];
+		key.copy_from_slice(self.secret.data());
+		key
+	}
+
+	pub(crate) fn aead(&self) -> &CipherAead {
+		&self.cipher
+	}
+
 	/*
 	Decrypt
 	*/
 	pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, CipherError> {
+		self.decrypt_with_aad(ciphertext, &[])
+	}
+
+	// decrypt a ciphertext bound to the associated data
+	pub fn decrypt_with_aad(
+		&self,
+		ciphertext: &[u8],
+		aad: &[u8],
+	) -> Result<Vec<u8>, CipherError> {
 		// check that the ciphertext is at least as long as the nonce
-		if ciphertext.len() < NONCE_LENGTH {
+		let nonce_length = self.algorithm.nonce_length();
+		if ciphertext.len() < nonce_length {
 			return Err(CipherError::InvalidNonce);
 		}
 
 		// Extract nonce from the ciphertext
-		let nonce: NonceArray = ciphertext[0..NONCE_LENGTH]
-			.try_into()
-			.map_err(|_| CipherError::InvalidNonce)?;
+		let (nonce, msg) = ciphertext.split_at(nonce_length);
+		let payload = Payload { msg, aad };
 
-		// Decryption
-		Ok(self
-			.cipher
-			.decrypt(&nonce.into(), &ciphertext[NONCE_LENGTH..])
-			.unwrap())
+		// Decryption, fails if the key is wrong or the ciphertext or
+		// associated data was altered
+		match &self.cipher {
+			CipherAead::ChaCha(cipher) => {
+				cipher.decrypt(GenericArray::from_slice(nonce), payload)
+			}
+			CipherAead::XChaCha(cipher) => {
+				cipher.decrypt(GenericArray::from_slice(nonce), payload)
+			}
+		}
+		.map_err(|_| CipherError::DecryptionFailed)
 	}
 
 	/*
 	Encrypt
 	*/
-	pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
-		// Generate nonce
-		let nonce: NonceArray =
-			ChaCha20Poly1305::generate_nonce(&mut OsRng).into();
+	pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+		self.encrypt_with_aad(plaintext, &[])
+	}
 
-		// Convert nonce to Vec<u8> using Vec<u8>::copy_from_slice
-		let encrypted_data = self
-			.cipher
-			.encrypt(&nonce.into(), plaintext.to_vec().as_slice())
-			.unwrap();
+	// encrypt the plaintext, binding it to the associated data
+	pub fn encrypt_with_aad(
+		&self,
+		plaintext: &[u8],
+		aad: &[u8],
+	) -> Result<Vec<u8>, CipherError> {
+		let payload = Payload {
+			msg: plaintext,
+			aad,
+		};
+
+		// Generate nonce
+		let (nonce, encrypted_data) = match &self.cipher {
+			CipherAead::ChaCha(cipher) => {
+				let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+				(nonce.to_vec(), cipher.encrypt(&nonce, payload))
+			}
+			CipherAead::XChaCha(cipher) => {
+				let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+				(nonce.to_vec(), cipher.encrypt(&nonce, payload))
+			}
+		};
+		let encrypted_data =
+			encrypted_data.map_err(|_| CipherError::EncryptionFailed)?;
 
 		// Concatenate nonce and encrypted data
-		[&nonce, encrypted_data.as_slice()].concat()
+		Ok([nonce.as_slice(), encrypted_data.as_slice()].concat())
 	}
 }
 
@@ -101,8 +234,8 @@ impl Cipher {
 	*/
 
 	#[wasm_bindgen(constructor)]
-	pub fn _js_new(secret: CipherSecretKey) -> Cipher {
-		Cipher::new(secret)
+	pub fn _js_new(secret: CipherSecretKey) -> Result<Cipher, JsValue> {
+		Ok(Cipher::new(secret)?)
 	}
 
 	#[wasm_bindgen(js_name = "randomKey")]
@@ -115,6 +248,11 @@ impl Cipher {
 		Cipher::random()
 	}
 
+	#[wasm_bindgen(js_name = "withAlgorithm")]
+	pub fn _js_with_algorithm(self, algorithm: CipherAlgorithm) -> Cipher {
+		self.with_algorithm(algorithm)
+	}
+
 	/*
 	Getters
 	*/
@@ -123,15 +261,32 @@ impl Cipher {
 		self.secret().clone()
 	}
 
+	#[wasm_bindgen(getter, js_name = "algorithm")]
+	pub fn _js_algorithm(&self) -> CipherAlgorithm {
+		self.algorithm()
+	}
+
 	/*
 	Decrypt
 	*/
 
 	#[wasm_bindgen(js_name = "decrypt")]
-	pub fn _js_decrypt(&self, ciphertext: Uint8Array) -> Uint8Array {
-		self.decrypt(&ciphertext.to_vec())
-			.map(|plaintext| plaintext.as_slice().into())
-			.unwrap_or_else(|_| panic!("Invalid ciphertext"))
+	pub fn _js_decrypt(
+		&self,
+		ciphertext: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		Ok(self.decrypt(&ciphertext.to_vec())?.as_slice().into())
+	}
+
+	#[wasm_bindgen(js_name = "decryptWithAad")]
+	pub fn _js_decrypt_with_aad(
+		&self,
+		ciphertext: Uint8Array,
+		aad: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		let plaintext =
+			self.decrypt_with_aad(&ciphertext.to_vec(), &aad.to_vec())?;
+		Ok(plaintext.as_slice().into())
 	}
 
 	/*
@@ -139,7 +294,21 @@ impl Cipher {
 	*/
 
 	#[wasm_bindgen(js_name = "encrypt")]
-	pub fn _js_encrypt(&self, plaintext: Uint8Array) -> Uint8Array {
-		self.encrypt(&plaintext.to_vec()).as_slice().into()
+	pub fn _js_encrypt(
+		&self,
+		plaintext: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		Ok(self.encrypt(&plaintext.to_vec())?.as_slice().into())
+	}
+
+	#[wasm_bindgen(js_name = "encryptWithAad")]
+	pub fn _js_encrypt_with_aad(
+		&self,
+		plaintext: Uint8Array,
+		aad: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		let ciphertext =
+			self.encrypt_with_aad(&plaintext.to_vec(), &aad.to_vec())?;
+		Ok(ciphertext.as_slice().into())
 	}
 }
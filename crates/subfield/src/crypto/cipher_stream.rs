@@ -0,0 +1,283 @@
+use crate::*;
+use chacha20poly1305::{
+	aead::{
+		generic_array::GenericArray,
+		stream::{DecryptorBE32, EncryptorBE32},
+	},
+	ChaCha20Poly1305, XChaCha20Poly1305,
+};
+
+// the plaintext in each chunk of a stream but the last, which may be shorter
+pub const CIPHER_STREAM_CHUNK: usize = 64 * 1024;
+// each chunk has 16 bytes of overhead
+const TAG_LENGTH: usize = 16;
+const CIPHER_STREAM_CHUNK_WITH_OVERHEAD: usize =
+	CIPHER_STREAM_CHUNK + TAG_LENGTH;
+// the nonce counter and last chunk flag take 5 bytes of the nonce
+const STREAM_NONCE_OVERHEAD: usize = 5;
+
+enum StreamEncryptor {
+	ChaCha(EncryptorBE32<ChaCha20Poly1305>),
+	XChaCha(EncryptorBE32<XChaCha20Poly1305>),
+}
+
+enum StreamDecryptor {
+	ChaCha(DecryptorBE32<ChaCha20Poly1305>),
+	XChaCha(DecryptorBE32<XChaCha20Poly1305>),
+}
+
+// the random nonce prefix a stream starts with
+fn header_length(algorithm: CipherAlgorithm) -> usize {
+	algorithm.nonce_length() - STREAM_NONCE_OVERHEAD
+}
+
+/*
+	CipherStreamEncryptor
+	Encrypts a blob of any size a piece at a time with the STREAM construction.
+	The plaintext is cut into fixed size chunks, each sealed with a nonce
+	holding its position and whether it is the last, so chunks cannot be
+	reordered, dropped or the stream cut short without failing decryption.
+*/
+#[wasm_bindgen]
+pub struct CipherStreamEncryptor {
+	encryptor: Option<StreamEncryptor>,
+	// sent in front of the first chunk
+	header: Option<Vec<u8>>,
+	buffer: Vec<u8>,
+}
+
+impl CipherStreamEncryptor {
+	pub fn new(cipher: &Cipher) -> Self {
+		let header = arr::random(header_length(cipher.algorithm()));
+		let encryptor = match cipher.aead().clone() {
+			CipherAead::ChaCha(aead) => {
+				StreamEncryptor::ChaCha(EncryptorBE32::from_aead(
+					aead,
+					GenericArray::from_slice(&header),
+				))
+			}
+			CipherAead::XChaCha(aead) => {
+				StreamEncryptor::XChaCha(EncryptorBE32::from_aead(
+					aead,
+					GenericArray::from_slice(&header),
+				))
+			}
+		};
+		Self {
+			encryptor: Some(encryptor),
+			header: Some(header),
+			buffer: vec![],
+		}
+	}
+
+	// encrypt the plaintext, returning the chunks completed so far
+	pub fn update(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+		let encryptor =
+			self.encryptor.as_mut().ok_or(CipherError::StreamFinished)?;
+		self.buffer.extend_from_slice(plaintext);
+
+		let mut ciphertext = self.header.take().unwrap_or_default();
+		// a full chunk is kept back until more follows, it may be the last
+		let mut offset = 0;
+		while self.buffer.len() - offset > CIPHER_STREAM_CHUNK {
+			let chunk = &self.buffer[offset..offset + CIPHER_STREAM_CHUNK];
+			let encrypted = match encryptor {
+				StreamEncryptor::ChaCha(encryptor) => {
+					encryptor.encrypt_next(chunk)
+				}
+				StreamEncryptor::XChaCha(encryptor) => {
+					encryptor.encrypt_next(chunk)
+				}
+			}
+			.map_err(|_| CipherError::EncryptionFailed)?;
+			ciphertext.extend_from_slice(&encrypted);
+			offset += CIPHER_STREAM_CHUNK;
+		}
+		self.buffer.drain(..offset);
+		Ok(ciphertext)
+	}
+
+	// encrypt the last chunk, ending the stream
+	pub fn finish(&mut self) -> Result<Vec<u8>, CipherError> {
+		let encryptor =
+			self.encryptor.take().ok_or(CipherError::StreamFinished)?;
+		let buffer = std::mem::take(&mut self.buffer);
+		let encrypted = match encryptor {
+			StreamEncryptor::ChaCha(encryptor) => {
+				encryptor.encrypt_last(buffer.as_slice())
+			}
+			StreamEncryptor::XChaCha(encryptor) => {
+				encryptor.encrypt_last(buffer.as_slice())
+			}
+		}
+		.map_err(|_| CipherError::EncryptionFailed)?;
+
+		let mut ciphertext = self.header.take().unwrap_or_default();
+		ciphertext.extend_from_slice(&encrypted);
+		Ok(ciphertext)
+	}
+}
+
+/*
+	CipherStreamDecryptor
+	Decrypts a stream made by a CipherStreamEncryptor. Each chunk is
+	authenticated as it is returned, but the stream as a whole only once
+	finish succeeds, until then it may have been cut short.
+*/
+#[wasm_bindgen]
+pub struct CipherStreamDecryptor {
+	cipher: CipherAead,
+	header_length: usize,
+	decryptor: Option<StreamDecryptor>,
+	finished: bool,
+	buffer: Vec<u8>,
+}
+
+impl CipherStreamDecryptor {
+	pub fn new(cipher: &Cipher) -> Self {
+		Self {
+			cipher: cipher.aead().clone(),
+			header_length: header_length(cipher.algorithm()),
+			decryptor: None,
+			finished: false,
+			buffer: vec![],
+		}
+	}
+
+	// decrypt the ciphertext, returning the chunks completed so far
+	pub fn update(
+		&mut self,
+		ciphertext: &[u8],
+	) -> Result<Vec<u8>, CipherError> {
+		if self.finished {
+			return Err(CipherError::StreamFinished);
+		}
+		self.buffer.extend_from_slice(ciphertext);
+		self.read_header();
+		let Some(decryptor) = self.decryptor.as_mut() else {
+			return Ok(vec![]);
+		};
+
+		let mut plaintext = vec![];
+		let mut offset = 0;
+		while self.buffer.len() - offset > CIPHER_STREAM_CHUNK_WITH_OVERHEAD {
+			let chunk = &self.buffer
+				[offset..offset + CIPHER_STREAM_CHUNK_WITH_OVERHEAD];
+			let decrypted = match decryptor {
+				StreamDecryptor::ChaCha(decryptor) => {
+					decryptor.decrypt_next(chunk)
+				}
+				StreamDecryptor::XChaCha(decryptor) => {
+					decryptor.decrypt_next(chunk)
+				}
+			}
+			.map_err(|_| CipherError::DecryptionFailed)?;
+			plaintext.extend_from_slice(&decrypted);
+			offset += CIPHER_STREAM_CHUNK_WITH_OVERHEAD;
+		}
+		self.buffer.drain(..offset);
+		Ok(plaintext)
+	}
+
+	// decrypt the last chunk, failing if the stream was cut short
+	pub fn finish(&mut self) -> Result<Vec<u8>, CipherError> {
+		if self.finished {
+			return Err(CipherError::StreamFinished);
+		}
+		self.finished = true;
+		self.read_header();
+		let decryptor =
+			self.decryptor.take().ok_or(CipherError::StreamTruncated)?;
+		if self.buffer.len() < TAG_LENGTH {
+			return Err(CipherError::StreamTruncated);
+		}
+
+		let buffer = std::mem::take(&mut self.buffer);
+		match decryptor {
+			StreamDecryptor::ChaCha(decryptor) => {
+				decryptor.decrypt_last(buffer.as_slice())
+			}
+			StreamDecryptor::XChaCha(decryptor) => {
+				decryptor.decrypt_last(buffer.as_slice())
+			}
+		}
+		.map_err(|_| CipherError::DecryptionFailed)
+	}
+
+	// make the decryptor once the header has been read
+	fn read_header(&mut self) {
+		if self.decryptor.is_none() && self.buffer.len() >= self.header_length {
+			let header: Vec<u8> =
+				self.buffer.drain(..self.header_length).collect();
+			self.decryptor = Some(match self.cipher.clone() {
+				CipherAead::ChaCha(aead) => {
+					StreamDecryptor::ChaCha(DecryptorBE32::from_aead(
+						aead,
+						GenericArray::from_slice(&header),
+					))
+				}
+				CipherAead::XChaCha(aead) => {
+					StreamDecryptor::XChaCha(DecryptorBE32::from_aead(
+						aead,
+						GenericArray::from_slice(&header),
+					))
+				}
+			});
+		}
+	}
+}
+
+impl Cipher {
+	pub fn stream_encryptor(&self) -> CipherStreamEncryptor {
+		CipherStreamEncryptor::new(self)
+	}
+
+	pub fn stream_decryptor(&self) -> CipherStreamDecryptor {
+		CipherStreamDecryptor::new(self)
+	}
+}
+
+#[wasm_bindgen]
+impl Cipher {
+	#[wasm_bindgen(js_name = "streamEncryptor")]
+	pub fn _js_stream_encryptor(&self) -> CipherStreamEncryptor {
+		self.stream_encryptor()
+	}
+
+	#[wasm_bindgen(js_name = "streamDecryptor")]
+	pub fn _js_stream_decryptor(&self) -> CipherStreamDecryptor {
+		self.stream_decryptor()
+	}
+}
+
+#[wasm_bindgen]
+impl CipherStreamEncryptor {
+	#[wasm_bindgen(js_name = "update")]
+	pub fn _js_update(
+		&mut self,
+		plaintext: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		Ok(self.update(&plaintext.to_vec())?.as_slice().into())
+	}
+
+	#[wasm_bindgen(js_name = "finish")]
+	pub fn _js_finish(&mut self) -> Result<Uint8Array, JsValue> {
+		Ok(self.finish()?.as_slice().into())
+	}
+}
+
+#[wasm_bindgen]
+impl CipherStreamDecryptor {
+	#[wasm_bindgen(js_name = "update")]
+	pub fn _js_update(
+		&mut self,
+		ciphertext: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		Ok(self.update(&ciphertext.to_vec())?.as_slice().into())
+	}
+
+	#[wasm_bindgen(js_name = "finish")]
+	pub fn _js_finish(&mut self) -> Result<Uint8Array, JsValue> {
+		Ok(self.finish()?.as_slice().into())
+	}
+}
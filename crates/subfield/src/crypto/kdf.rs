@@ -0,0 +1,217 @@
+use crate::*;
+use argon2::{Algorithm, Argon2, Params, Version};
+
+// the shortest salt a password key is derived with
+pub const PASSWORD_SALT_MIN_LENGTH: usize = 8;
+const PASSWORD_SALT_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize, strum::Display)]
+pub enum KdfError {
+	#[strum(serialize = "InvalidSalt")]
+	InvalidSalt,
+	#[strum(serialize = "InvalidParams")]
+	InvalidParams,
+	#[strum(serialize = "DerivationFailed")]
+	DerivationFailed,
+}
+
+impl From<KdfError> for JsValue {
+	fn from(e: KdfError) -> Self {
+		JsValue::from_str(&e.to_string())
+	}
+}
+
+// what a derived key is for, keys derived from the same material for
+// different purposes are unrelated
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KdfContext {
+	Encryption,
+	Mac,
+	RecordId,
+	PrivateKey,
+}
+
+impl KdfContext {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			KdfContext::Encryption => "subfield 2024-06-01 encryption key",
+			KdfContext::Mac => "subfield 2024-06-01 mac key",
+			KdfContext::RecordId => "subfield 2024-06-01 record id",
+			KdfContext::PrivateKey => "subfield 2024-06-01 private key",
+		}
+	}
+}
+
+/*
+	Derive
+*/
+// a subkey of the key material for the context, the material must already be
+// uniformly random, like a shared secret or a password key
+pub fn derive_key(context: KdfContext, key_material: &[u8]) -> V256 {
+	V256::new(0, &blake3::derive_key(context.as_str(), key_material))
+}
+
+#[wasm_bindgen(js_name = "deriveKey")]
+pub fn _js_derive_key(context: KdfContext, key_material: Uint8Array) -> V256 {
+	derive_key(context, &key_material.to_vec())
+}
+
+// the cipher key two keypairs share, rather than their raw shared secret
+pub fn derive_shared_cipher_key(
+	keypair: &Keypair,
+	public_key: &PublicKey,
+) -> CipherSecretKey {
+	let shared_secret = keypair.shared_secret(public_key);
+	derive_key(KdfContext::Encryption, shared_secret.data())
+}
+
+#[wasm_bindgen(js_name = "deriveSharedCipherKey")]
+pub fn _js_derive_shared_cipher_key(
+	keypair: &Keypair,
+	public_key: &PublicKey,
+) -> CipherSecretKey {
+	derive_shared_cipher_key(keypair, public_key)
+}
+
+/*
+	PasswordKdfParams
+	The cost of deriving a key from a password with Argon2id, the defaults are
+	the minimum OWASP recommends. Keys derived with different params differ, so
+	they must be kept with the salt.
+*/
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PasswordKdfParams {
+	#[wasm_bindgen(js_name = "memoryKib")]
+	pub memory_kib: u32,
+	pub iterations: u32,
+	pub parallelism: u32,
+}
+
+impl Default for PasswordKdfParams {
+	fn default() -> Self {
+		Self {
+			memory_kib: Params::DEFAULT_M_COST,
+			iterations: Params::DEFAULT_T_COST,
+			parallelism: Params::DEFAULT_P_COST,
+		}
+	}
+}
+
+#[wasm_bindgen]
+impl PasswordKdfParams {
+	#[wasm_bindgen(constructor)]
+	pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Self {
+		Self {
+			memory_kib,
+			iterations,
+			parallelism,
+		}
+	}
+
+	#[wasm_bindgen(js_name = "default")]
+	pub fn _js_default() -> Self {
+		Self::default()
+	}
+}
+
+/*
+	Password
+*/
+pub fn random_salt() -> Vec<u8> {
+	arr::random(PASSWORD_SALT_LENGTH)
+}
+
+#[wasm_bindgen(js_name = "randomSalt")]
+pub fn _js_random_salt() -> Uint8Array {
+	random_salt().as_slice().into()
+}
+
+// the key stretched from a password, subkeys are derived from it for each use
+// so the slow hash is only run once
+pub fn derive_password_key(
+	password: &[u8],
+	salt: &[u8],
+	params: &PasswordKdfParams,
+) -> Result<V256, KdfError> {
+	if salt.len() < PASSWORD_SALT_MIN_LENGTH {
+		return Err(KdfError::InvalidSalt);
+	}
+	let params = Params::new(
+		params.memory_kib,
+		params.iterations,
+		params.parallelism,
+		Some(KEY_LENGTH),
+	)
+	.map_err(|_| KdfError::InvalidParams)?;
+
+	let mut key = [0u8; KEY_LENGTH];
+	Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+		.hash_password_into(password, salt, &mut key)
+		.map_err(|_| KdfError::DerivationFailed)?;
+	Ok(V256::new(0, &key))
+}
+
+pub fn derive_private_key_from_password(
+	password: &[u8],
+	salt: &[u8],
+	params: &PasswordKdfParams,
+) -> Result<PrivateKey, KdfError> {
+	let key = derive_password_key(password, salt, params)?;
+	Ok(PrivateKey::new(derive_key(
+		KdfContext::PrivateKey,
+		key.data(),
+	)))
+}
+
+pub fn derive_cipher_key_from_password(
+	password: &[u8],
+	salt: &[u8],
+	params: &PasswordKdfParams,
+) -> Result<CipherSecretKey, KdfError> {
+	let key = derive_password_key(password, salt, params)?;
+	Ok(derive_key(KdfContext::Encryption, key.data()))
+}
+
+#[wasm_bindgen(js_name = "derivePasswordKey")]
+pub fn _js_derive_password_key(
+	password: &str,
+	salt: Uint8Array,
+	params: Option<PasswordKdfParams>,
+) -> Result<V256, JsValue> {
+	let params = params.unwrap_or_default();
+	let salt = salt.to_vec();
+	Ok(derive_password_key(password.as_bytes(), &salt, &params)?)
+}
+
+#[wasm_bindgen(js_name = "derivePrivateKeyFromPassword")]
+pub fn _js_derive_private_key_from_password(
+	password: &str,
+	salt: Uint8Array,
+	params: Option<PasswordKdfParams>,
+) -> Result<PrivateKey, JsValue> {
+	let params = params.unwrap_or_default();
+	let salt = salt.to_vec();
+	Ok(derive_private_key_from_password(
+		password.as_bytes(),
+		&salt,
+		&params,
+	)?)
+}
+
+#[wasm_bindgen(js_name = "deriveCipherKeyFromPassword")]
+pub fn _js_derive_cipher_key_from_password(
+	password: &str,
+	salt: Uint8Array,
+	params: Option<PasswordKdfParams>,
+) -> Result<CipherSecretKey, JsValue> {
+	let params = params.unwrap_or_default();
+	let salt = salt.to_vec();
+	Ok(derive_cipher_key_from_password(
+		password.as_bytes(),
+		&salt,
+		&params,
+	)?)
+}
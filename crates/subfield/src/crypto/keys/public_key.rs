@@ -228,6 +228,19 @@ impl Libp2pPeerIdable<CryptoKeyError> for PublicKey {
 		let libp2p_public_key = Libp2pPublicKey::from(libp2p_key);
 		Ok(libp2p::PeerId::from_public_key(&libp2p_public_key))
 	}
+
+	// ed25519 peer ids inline the protobuf encoded public key
+	fn from_libp2p_peer_id(
+		peer_id: libp2p::PeerId,
+	) -> Result<Self, CryptoKeyError> {
+		let libp2p_public_key =
+			Libp2pPublicKey::try_decode_protobuf(peer_id.as_ref().digest())
+				.map_err(|_| CryptoKeyError::InvalidPublicKey)?;
+		let libp2p_key = libp2p_public_key
+			.try_into_ed25519()
+			.map_err(|_| CryptoKeyError::InvalidPublicKey)?;
+		Ok(PublicKey::new(V256::new(0, &libp2p_key.to_bytes())))
+	}
 }
 
 
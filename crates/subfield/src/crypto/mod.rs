@@ -1,8 +1,14 @@
 mod cipher;
 pub use cipher::*;
+mod cipher_stream;
+pub use cipher_stream::*;
 mod hash;
 pub use hash::*;
+mod kdf;
+pub use kdf::*;
 mod keys;
 pub use keys::*;
 mod noise;
 pub use noise::*;
+mod noise_stream;
+pub use noise_stream::*;
@@ -1,5 +1,6 @@
-use super::keys::Keypair;
+use super::keys::{Keypair, PublicKey};
 use crate::*;
+use ed25519_dalek::VerifyingKey as DalekEdPublicKey;
 use lazy_static::lazy_static;
 use once_cell::sync::OnceCell;
 pub use snow::{
@@ -13,6 +14,12 @@ pub type NoiseBuffer = Vec<u8>;
 lazy_static! {
 	pub static ref NOISE_PARAMS: NoiseParams =
 		"Noise_NN_25519_ChaChaPoly_BLAKE2s".parse().unwrap();
+	static ref NOISE_XX_PARAMS: NoiseParams =
+		"Noise_XX_25519_ChaChaPoly_BLAKE2s".parse().unwrap();
+	static ref NOISE_IK_PARAMS: NoiseParams =
+		"Noise_IK_25519_ChaChaPoly_BLAKE2s".parse().unwrap();
+	static ref NOISE_KK_PARAMS: NoiseParams =
+		"Noise_KK_25519_ChaChaPoly_BLAKE2s".parse().unwrap();
 }
 
 // each chunk has 16 bytes of overhead
@@ -20,6 +27,9 @@ const CHUNK_SIZE: usize = 1024;
 const OVERHEAD: usize = 16;
 const CHUNK_SIZE_WITHOUT_OVERHEAD: usize = CHUNK_SIZE - OVERHEAD;
 const CHUNK_SIZE_WITH_OVERHEAD: usize = CHUNK_SIZE + OVERHEAD;
+// the largest noise message, and the most data one can carry
+pub const NOISE_MAX_MESSAGE: usize = 65535;
+pub const NOISE_MAX_PLAINTEXT: usize = NOISE_MAX_MESSAGE - OVERHEAD;
 
 #[derive(Debug, Clone, strum::Display)]
 pub enum NoiseError {
@@ -35,6 +45,18 @@ pub enum NoiseError {
 	FailedToEncrypt,
 	#[strum(serialize = "FailedToDecrypt")]
 	FailedToDecrypt,
+	// the pattern needs the remote static key before the handshake
+	#[strum(serialize = "MissingRemoteKey")]
+	MissingRemoteKey,
+	// the remote static key is not the one expected
+	#[strum(serialize = "RemoteKeyMismatch")]
+	RemoteKeyMismatch,
+}
+
+impl From<NoiseError> for JsValue {
+	fn from(e: NoiseError) -> Self {
+		JsValue::from_str(&e.to_string())
+	}
 }
 
 #[wasm_bindgen]
@@ -44,14 +66,71 @@ pub enum NoiseRole {
 	Responder,
 }
 
+// the handshake patterns supported, NN is unauthenticated, XX learns both
+// static keys during the handshake, IK has the initiator know the responder's
+// static key beforehand and KK has both know each other's
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum NoisePattern {
+	#[default]
+	NN,
+	XX,
+	IK,
+	KK,
+}
+
+impl NoisePattern {
+	pub fn params(&self) -> &'static NoiseParams {
+		match self {
+			NoisePattern::NN => &NOISE_PARAMS,
+			NoisePattern::XX => &NOISE_XX_PARAMS,
+			NoisePattern::IK => &NOISE_IK_PARAMS,
+			NoisePattern::KK => &NOISE_KK_PARAMS,
+		}
+	}
+
+	// whether the role must know the remote static key before the handshake
+	pub fn knows_remote(&self, role: &NoiseRole) -> bool {
+		match self {
+			NoisePattern::NN | NoisePattern::XX => false,
+			NoisePattern::IK => role == &NoiseRole::Initiator,
+			NoisePattern::KK => true,
+		}
+	}
+
+	// whether the handshake message at the index carries its sender's static
+	// key, the sender's public key is sent as its payload so the static key
+	// can be bound to a subfield identity
+	fn sends_static(&self, index: usize) -> bool {
+		matches!(
+			(self, index),
+			(NoisePattern::XX, 1)
+				| (NoisePattern::XX, 2)
+				| (NoisePattern::IK, 0)
+		)
+	}
+}
+
 pub type NoiseKeypairString = String;
 
+/*
+	Noise
+	One side of a noise session. The local keypair is the static key of the
+	handshake, and the remote's public key is either known beforehand, or sent
+	with its static key and checked against it. If an expected remote key is
+	given, the handshake fails unless the remote proves it holds it.
+*/
 #[wasm_bindgen]
 pub struct Noise {
 	role: NoiseRole,
+	pattern: NoisePattern,
 	keypair: Keypair,
-	//noise_keypair: NoiseKeypair,
-	handshake: Mutex<HandshakeState>,
+	// the key the remote must hold, if any
+	expected_remote: Option<PublicKey>,
+	remote_public_key: OnceCell<PublicKey>,
+	// the number of handshake messages written and read
+	messages: usize,
+	handshake: Mutex<Option<HandshakeState>>,
 	transport: OnceCell<Mutex<TransportState>>,
 	buffer: Mutex<NoiseBuffer>,
 }
@@ -60,18 +139,54 @@ impl Noise {
 	/*
 	Constructors
 	*/
-	fn new(
+	pub fn new(
 		role: NoiseRole,
+		pattern: NoisePattern,
 		keypair: Keypair,
-		handshake_state: HandshakeState,
-	) -> Self {
-		Noise {
+		remote_public_key: Option<PublicKey>,
+	) -> Result<Noise, NoiseError> {
+		// an unauthenticated handshake cannot check the remote's key
+		if pattern == NoisePattern::NN && remote_public_key.is_some() {
+			return Err(NoiseError::InvalidState);
+		}
+		let local_private_key = keypair.private_key().x().to_bytes();
+		let builder = Builder::new(pattern.params().clone())
+			.local_private_key(&local_private_key);
+
+		let remote_static = match &remote_public_key {
+			Some(remote) => Some(Self::static_key(remote)?),
+			None => None,
+		};
+		let builder = match (pattern.knows_remote(&role), &remote_static) {
+			(true, Some(remote_static)) => {
+				builder.remote_public_key(remote_static)
+			}
+			(true, None) => return Err(NoiseError::MissingRemoteKey),
+			(false, _) => builder,
+		};
+		let handshake = match role {
+			NoiseRole::Initiator => builder.build_initiator(),
+			NoiseRole::Responder => builder.build_responder(),
+		}
+		.map_err(|_| NoiseError::InvalidKey)?;
+
+		let known_remote = OnceCell::new();
+		if let (true, Some(remote)) =
+			(pattern.knows_remote(&role), &remote_public_key)
+		{
+			let _ = known_remote.set(remote.clone());
+		}
+		Ok(Noise {
 			role,
+			pattern,
 			keypair,
-			handshake: Mutex::new(handshake_state),
+			expected_remote: remote_public_key,
+			remote_public_key: known_remote,
+			messages: 0,
+			handshake: Mutex::new(Some(handshake)),
 			transport: OnceCell::new(),
 			buffer: Mutex::new(vec![0u8; CHUNK_SIZE]),
-		}
+		})
 	}
 
 	pub fn initiator() -> Noise {
@@ -80,13 +195,8 @@ impl Noise {
 	}
 
 	pub fn initiator_from_keypair(keypair: Keypair) -> Noise {
-		let state = Builder::new(NOISE_PARAMS.clone())
-			.local_private_key(keypair.private_key().versioned_bytes().data())
-			//.remote_public_key(keypair.public_key().data().data().data_u8())
-			.build_initiator()
-			.unwrap();
-
-		Noise::new(NoiseRole::Initiator, keypair, state)
+		Noise::new(NoiseRole::Initiator, NoisePattern::NN, keypair, None)
+			.unwrap()
 	}
 
 	pub fn responder() -> Noise {
@@ -95,13 +205,8 @@ impl Noise {
 	}
 
 	pub fn responder_from_keypair(keypair: Keypair) -> Noise {
-		let state = Builder::new(NOISE_PARAMS.clone())
-			.local_private_key(keypair.private_key().versioned_bytes().data())
-			//.remote_public_key(keypair.public_key().data().data().data_u8())
-			.build_responder()
-			.unwrap();
-
-		Noise::new(NoiseRole::Responder, keypair, state)
+		Noise::new(NoiseRole::Responder, NoisePattern::NN, keypair, None)
+			.unwrap()
 	}
 
 	/*
@@ -111,37 +216,120 @@ impl Noise {
 		self.keypair.clone()
 	}
 
+	pub fn pattern(&self) -> NoisePattern {
+		self.pattern
+	}
+
+	// the remote's public key, once known and verified
+	pub fn remote_public_key(&self) -> Option<PublicKey> {
+		self.remote_public_key.get().cloned()
+	}
+
+	pub fn is_handshake_finished(&self) -> bool {
+		self.transport.get().is_some()
+	}
+
 	/*
 	Handshake
 	*/
 
-	// write a blank message to the buffer
-	fn encrypt_handshake(&mut self) -> Result<NoiseBuffer, NoiseError> {
+	// write the next handshake message, moving to transport mode if it is the
+	// last
+	pub fn write_handshake(&mut self) -> Result<NoiseBuffer, NoiseError> {
+		if self.is_handshake_finished() || !self.is_my_turn() {
+			return Err(NoiseError::InvalidState);
+		}
+		let payload = match self.pattern.sends_static(self.messages) {
+			true => self.keypair.public_key().to_vec(),
+			false => vec![],
+		};
 		let mut buffer = self.buffer.lock().unwrap();
-		let handshake = self.handshake.get_mut().unwrap();
+		let handshake = self
+			.handshake
+			.get_mut()
+			.unwrap()
+			.as_mut()
+			.ok_or(NoiseError::InvalidState)?;
 		let len = handshake
-			.write_message(&[], &mut buffer)
+			.write_message(&payload, &mut buffer)
 			.map_err(|_| NoiseError::FailedToEncrypt)?;
-		Ok(buffer[..len].to_vec())
+		let message = buffer[..len].to_vec();
+		drop(buffer);
+
+		self.messages += 1;
+		self.into_transport_mode()?;
+		Ok(message)
 	}
 
-	// read a message from the buffer
-	fn decrypt_handshake(
-		&mut self,
-		data: &[u8],
-	) -> Result<NoiseBuffer, NoiseError> {
+	// read the next handshake message, verifying the remote's static key if
+	// it carries one
+	pub fn read_handshake(&mut self, message: &[u8]) -> Result<(), NoiseError> {
+		if self.is_handshake_finished() || self.is_my_turn() {
+			return Err(NoiseError::InvalidState);
+		}
 		let mut buffer = self.buffer.lock().unwrap();
-		let handshake = self.handshake.get_mut().unwrap();
+		let handshake = self
+			.handshake
+			.get_mut()
+			.unwrap()
+			.as_mut()
+			.ok_or(NoiseError::InvalidState)?;
 		let len = handshake
-			.read_message(data, &mut buffer)
+			.read_message(message, &mut buffer)
 			.map_err(|_| NoiseError::FailedToDecrypt)?;
-		Ok(buffer[..len].to_vec())
+		let payload = buffer[..len].to_vec();
+		drop(buffer);
+
+		if self.pattern.sends_static(self.messages) {
+			let remote_static = handshake
+				.get_remote_static()
+				.ok_or(NoiseError::InvalidMessage)?
+				.to_vec();
+			self.verify_remote(&payload, &remote_static)?;
+		}
+		self.messages += 1;
+		self.into_transport_mode()
+	}
+
+	// check the public key sent against the static key it was sent with, and
+	// against the key expected
+	fn verify_remote(
+		&mut self,
+		payload: &[u8],
+		remote_static: &[u8],
+	) -> Result<(), NoiseError> {
+		let remote =
+			PublicKey::from_arr(payload).map_err(|_| NoiseError::InvalidKey)?;
+		if Self::static_key(&remote)? != remote_static {
+			return Err(NoiseError::RemoteKeyMismatch);
+		}
+		if let Some(expected) = &self.expected_remote {
+			if expected != &remote {
+				return Err(NoiseError::RemoteKeyMismatch);
+			}
+		}
+		let _ = self.remote_public_key.set(remote);
+		Ok(())
+	}
+
+	// the noise static key of a subfield public key
+	fn static_key(public_key: &PublicKey) -> Result<[u8; 32], NoiseError> {
+		let bytes: [u8; 32] = public_key
+			.versioned_bytes()
+			.data()
+			.as_slice()
+			.try_into()
+			.map_err(|_| NoiseError::InvalidKey)?;
+		// checked first, the conversion panics on keys that are not points
+		DalekEdPublicKey::from_bytes(&bytes)
+			.map_err(|_| NoiseError::InvalidKey)?;
+		Ok(public_key.x().to_bytes())
 	}
 
 	// Step 1
 	// Initiator: Write the first handshake message
 	pub fn handshake_step_1(&mut self) -> Result<NoiseBuffer, NoiseError> {
-		self.encrypt_handshake()
+		self.write_handshake()
 	}
 
 	// Step 2
@@ -151,37 +339,49 @@ impl Noise {
 		&mut self,
 		message: &[u8],
 	) -> Result<NoiseBuffer, NoiseError> {
-		let _ = self.decrypt_handshake(message);
-		let result = self.encrypt_handshake();
-		self.into_transport_mode();
-		result
+		self.read_handshake(message)?;
+		self.write_handshake()
 	}
 
 	// Step 3
 	// Initiator: Read the second handshake message
+	// Initiator: Write the third handshake message, for patterns that have one
 	pub fn handshake_step_3(
 		&mut self,
 		message: &[u8],
+	) -> Result<Option<NoiseBuffer>, NoiseError> {
+		self.read_handshake(message)?;
+		match self.is_handshake_finished() {
+			true => Ok(None),
+			false => self.write_handshake().map(Some),
+		}
+	}
+
+	// Step 4
+	// Responder: Read the third handshake message, for patterns that have one
+	pub fn handshake_step_4(
+		&mut self,
+		message: &[u8],
 	) -> Result<(), NoiseError> {
-		let _ = self.decrypt_handshake(message)?;
-		self.into_transport_mode();
-		Ok(())
+		self.read_handshake(message)
 	}
 
-	// into transport mode
-	fn into_transport_mode(&mut self) {
+	// into transport mode, once the handshake is finished
+	fn into_transport_mode(&mut self) -> Result<(), NoiseError> {
 		let handshake_option = self.handshake.get_mut().unwrap();
-		// Replace the handshake with a dummy
-		let dummy_handshake_state = Builder::new(NOISE_PARAMS.clone())
-			.local_private_key(self.keypair.private_key().versioned_bytes().data())
-			.build_initiator()
-			.unwrap();
-		let handshake =
-			std::mem::replace(handshake_option, dummy_handshake_state);
-
-		let transport = handshake.into_transport_mode().unwrap();
+		if !handshake_option
+			.as_ref()
+			.is_some_and(|handshake| handshake.is_handshake_finished())
+		{
+			return Ok(());
+		}
+		let handshake = handshake_option.take().unwrap();
+		let transport = handshake
+			.into_transport_mode()
+			.map_err(|_| NoiseError::InvalidState)?;
 
 		let _ = self.transport.set(Mutex::new(transport));
+		Ok(())
 	}
 
 	/*
@@ -192,7 +392,8 @@ impl Noise {
 		let mut encrypted_data = Vec::new();
 
 		let mut buffer = self.buffer.lock().unwrap();
-		let mut transport = self.transport.get().unwrap().lock().unwrap();
+		let transport = self.transport.get().ok_or(NoiseError::InvalidState)?;
+		let mut transport = transport.lock().unwrap();
 
 		for chunk in data.chunks(CHUNK_SIZE_WITHOUT_OVERHEAD) {
 			let len = transport
@@ -210,7 +411,8 @@ impl Noise {
 	pub fn decrypt(&mut self, data: &[u8]) -> Result<NoiseBuffer, NoiseError> {
 		let mut decrypted_data = Vec::new();
 		let mut buffer = self.buffer.lock().unwrap();
-		let mut transport = self.transport.get().unwrap().lock().unwrap();
+		let transport = self.transport.get().ok_or(NoiseError::InvalidState)?;
+		let mut transport = transport.lock().unwrap();
 
 		for chunk in data.chunks(CHUNK_SIZE) {
 			let len = transport
@@ -221,6 +423,74 @@ impl Noise {
 
 		Ok(decrypted_data)
 	}
+
+	/*
+	Messages
+	*/
+
+	// encrypt the data as a single noise message, which is at most
+	// NOISE_MAX_MESSAGE bytes once encrypted
+	pub fn encrypt_message(
+		&mut self,
+		data: &[u8],
+	) -> Result<NoiseBuffer, NoiseError> {
+		if data.len() > NOISE_MAX_PLAINTEXT {
+			return Err(NoiseError::InvalidBuffer);
+		}
+		let transport = self.transport.get().ok_or(NoiseError::InvalidState)?;
+		let mut message = vec![0u8; data.len() + OVERHEAD];
+		let len = transport
+			.lock()
+			.unwrap()
+			.write_message(data, &mut message)
+			.map_err(|_| NoiseError::FailedToEncrypt)?;
+		message.truncate(len);
+		Ok(message)
+	}
+
+	// decrypt a single noise message
+	pub fn decrypt_message(
+		&mut self,
+		message: &[u8],
+	) -> Result<NoiseBuffer, NoiseError> {
+		if message.len() > NOISE_MAX_MESSAGE || message.len() < OVERHEAD {
+			return Err(NoiseError::InvalidMessage);
+		}
+		let transport = self.transport.get().ok_or(NoiseError::InvalidState)?;
+		let mut data = vec![0u8; message.len()];
+		let len = transport
+			.lock()
+			.unwrap()
+			.read_message(message, &mut data)
+			.map_err(|_| NoiseError::FailedToDecrypt)?;
+		data.truncate(len);
+		Ok(data)
+	}
+
+	// whether the next handshake message is written rather than read
+	pub fn is_my_turn(&self) -> bool {
+		// the initiator writes the first message, then they take turns
+		let initiator_turn = self.messages.is_multiple_of(2);
+		initiator_turn == (self.role == NoiseRole::Initiator)
+	}
+
+	/*
+	Rekey
+	*/
+
+	// move the sending key forward, the remote must rekey its incoming key
+	// at the same message
+	pub fn rekey_outgoing(&mut self) -> Result<(), NoiseError> {
+		let transport = self.transport.get().ok_or(NoiseError::InvalidState)?;
+		transport.lock().unwrap().rekey_outgoing();
+		Ok(())
+	}
+
+	pub fn rekey_incoming(&mut self) -> Result<(), NoiseError> {
+		let transport = self.transport.get().ok_or(NoiseError::InvalidState)?;
+		transport.lock().unwrap().rekey_incoming();
+		Ok(())
+	}
 }
 
 #[wasm_bindgen]
@@ -248,6 +518,26 @@ impl Noise {
 		Noise::responder_from_keypair(keypair)
 	}
 
+	#[wasm_bindgen(js_name = "initiatorWithPattern")]
+	pub fn _js_initiator_with_pattern(
+		pattern: NoisePattern,
+		keypair: Keypair,
+		remote_public_key: Option<PublicKey>,
+	) -> Result<Noise, JsValue> {
+		Noise::new(NoiseRole::Initiator, pattern, keypair, remote_public_key)
+			.map_err(JsValue::from)
+	}
+
+	#[wasm_bindgen(js_name = "responderWithPattern")]
+	pub fn _js_responder_with_pattern(
+		pattern: NoisePattern,
+		keypair: Keypair,
+		remote_public_key: Option<PublicKey>,
+	) -> Result<Noise, JsValue> {
+		Noise::new(NoiseRole::Responder, pattern, keypair, remote_public_key)
+			.map_err(JsValue::from)
+	}
+
 	/*
 	Getters
 	*/
@@ -256,46 +546,74 @@ impl Noise {
 		self.keypair()
 	}
 
+	#[wasm_bindgen(getter, js_name = "pattern")]
+	pub fn _js_pattern(&self) -> NoisePattern {
+		self.pattern()
+	}
+
+	#[wasm_bindgen(getter, js_name = "remotePublicKey")]
+	pub fn _js_remote_public_key(&self) -> Option<PublicKey> {
+		self.remote_public_key()
+	}
+
+	#[wasm_bindgen(getter, js_name = "isHandshakeFinished")]
+	pub fn _js_is_handshake_finished(&self) -> bool {
+		self.is_handshake_finished()
+	}
+
 	/*
 	Handshake
 	*/
 	#[wasm_bindgen(js_name = "handshakeStep1")]
-	pub fn _js_handshake_step_1(&mut self) -> Uint8Array {
-		self.handshake_step_1().unwrap().as_slice().into()
+	pub fn _js_handshake_step_1(&mut self) -> Result<Uint8Array, JsValue> {
+		Ok(self.handshake_step_1()?.as_slice().into())
 	}
 
 	#[wasm_bindgen(js_name = "handshakeStep2")]
-	pub fn _js_handshake_step_2(&mut self, message: Uint8Array) -> Uint8Array {
-		self.handshake_step_2(&message.to_vec().as_slice())
-			.unwrap()
-			.as_slice()
-			.into()
+	pub fn _js_handshake_step_2(
+		&mut self,
+		message: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		Ok(self.handshake_step_2(&message.to_vec())?.as_slice().into())
 	}
 
 	#[wasm_bindgen(js_name = "handshakeStep3")]
-	pub fn _js_handshake_step_3(&mut self, message: Uint8Array) {
-		self.handshake_step_3(&message.to_vec().as_slice()).unwrap();
+	pub fn _js_handshake_step_3(
+		&mut self,
+		message: Uint8Array,
+	) -> Result<Option<Uint8Array>, JsValue> {
+		Ok(self
+			.handshake_step_3(&message.to_vec())?
+			.map(|message| message.as_slice().into()))
+	}
+
+	#[wasm_bindgen(js_name = "handshakeStep4")]
+	pub fn _js_handshake_step_4(
+		&mut self,
+		message: Uint8Array,
+	) -> Result<(), JsValue> {
+		Ok(self.handshake_step_4(&message.to_vec())?)
 	}
 
 	/*
 	Encrypt
 	*/
 	#[wasm_bindgen(js_name = "encrypt")]
-	pub fn _js_encrypt(&mut self, data: Uint8Array) -> Uint8Array {
-		self.encrypt(&data.to_vec().as_slice())
-			.unwrap()
-			.as_slice()
-			.into()
+	pub fn _js_encrypt(
+		&mut self,
+		data: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		Ok(self.encrypt(&data.to_vec())?.as_slice().into())
 	}
 
 	/*
 	Decrypt
 	*/
 	#[wasm_bindgen(js_name = "decrypt")]
-	pub fn _js_decrypt(&mut self, data: Uint8Array) -> Uint8Array {
-		self.decrypt(&data.to_vec().as_slice())
-			.unwrap()
-			.as_slice()
-			.into()
+	pub fn _js_decrypt(
+		&mut self,
+		data: Uint8Array,
+	) -> Result<Uint8Array, JsValue> {
+		Ok(self.decrypt(&data.to_vec())?.as_slice().into())
 	}
 }
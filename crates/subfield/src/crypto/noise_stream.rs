@@ -0,0 +1,320 @@
+use crate::*;
+use std::io;
+use std::task::{Context, Poll};
+
+// the length prefix of each frame, a big endian u16
+const LENGTH_PREFIX: usize = 2;
+
+// how many bytes are sent in each direction before its key is moved forward
+pub const NOISE_REKEY_INTERVAL: u64 = 1 << 30;
+
+/*
+	NoiseStream
+	A noise session over a byte stream. The handshake and every message after
+	it are sent as frames, each prefixed with its length, so messages can be
+	told apart however the stream splits or joins them. Writes larger than a
+	noise message are split across frames. Each direction is rekeyed once the
+	bytes sent in it pass the rekey interval, at the same frame on both sides.
+*/
+pub struct NoiseStream<S> {
+	stream: S,
+	noise: Noise,
+	rekey_interval: u64,
+	// the frame being written, and how much of it was written
+	write_frame: Vec<u8>,
+	written: usize,
+	sent: u64,
+	// the frame being read, and the data of the last frame not yet read
+	read_frame: Vec<u8>,
+	read_data: Vec<u8>,
+	read_offset: usize,
+	received: u64,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> NoiseStream<S> {
+	/*
+	Constructors
+	*/
+
+	// run the handshake over the stream
+	pub async fn handshake(
+		mut stream: S,
+		mut noise: Noise,
+	) -> Result<Self, NoiseError> {
+		while !noise.is_handshake_finished() {
+			match noise.is_my_turn() {
+				true => {
+					let message = noise.write_handshake()?;
+					write_frame(&mut stream, &message).await?;
+				}
+				false => {
+					let message = read_frame(&mut stream).await?;
+					noise.read_handshake(&message)?;
+				}
+			}
+		}
+		Ok(Self::new(stream, noise))
+	}
+
+	// a stream over a session whose handshake is finished
+	pub fn new(stream: S, noise: Noise) -> Self {
+		Self {
+			stream,
+			noise,
+			rekey_interval: NOISE_REKEY_INTERVAL,
+			write_frame: vec![],
+			written: 0,
+			sent: 0,
+			read_frame: vec![],
+			read_data: vec![],
+			read_offset: 0,
+			received: 0,
+		}
+	}
+
+	// both sides must use the same interval
+	pub fn with_rekey_interval(mut self, rekey_interval: u64) -> Self {
+		self.rekey_interval = rekey_interval.max(1);
+		self
+	}
+
+	/*
+	Getters
+	*/
+	pub fn noise(&self) -> &Noise {
+		&self.noise
+	}
+
+	pub fn remote_public_key(&self) -> Option<PublicKey> {
+		self.noise.remote_public_key()
+	}
+
+	pub fn into_inner(self) -> S {
+		self.stream
+	}
+
+	/*
+	Rekey
+	*/
+
+	// move the sending key forward now, the remote must call rekey_incoming
+	// after reading the frames written so far
+	pub fn rekey_outgoing(&mut self) -> Result<(), NoiseError> {
+		self.sent = 0;
+		self.noise.rekey_outgoing()
+	}
+
+	pub fn rekey_incoming(&mut self) -> Result<(), NoiseError> {
+		self.received = 0;
+		self.noise.rekey_incoming()
+	}
+
+	/*
+	Frames
+	*/
+
+	// write what is left of the frame being written
+	fn poll_write_frame(
+		&mut self,
+		cx: &mut Context<'_>,
+	) -> Poll<io::Result<()>> {
+		while self.written < self.write_frame.len() {
+			let frame = &self.write_frame[self.written..];
+			match Pin::new(&mut self.stream).poll_write(cx, frame) {
+				Poll::Ready(Ok(0)) => {
+					return Poll::Ready(Err(io::ErrorKind::WriteZero.into()))
+				}
+				Poll::Ready(Ok(len)) => self.written += len,
+				Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+				Poll::Pending => return Poll::Pending,
+			}
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	// read the next frame and decrypt it, or nothing if the stream ended
+	// between frames
+	fn poll_read_frame(
+		&mut self,
+		cx: &mut Context<'_>,
+	) -> Poll<io::Result<Option<Vec<u8>>>> {
+		loop {
+			let wanted = match self.read_frame.len() < LENGTH_PREFIX {
+				true => LENGTH_PREFIX,
+				false => LENGTH_PREFIX + frame_length(&self.read_frame),
+			};
+			if self.read_frame.len() == wanted && wanted > LENGTH_PREFIX {
+				break;
+			}
+			if self.read_frame.len() == wanted {
+				// an empty frame is never written
+				return Poll::Ready(Err(invalid_data(
+					NoiseError::InvalidMessage,
+				)));
+			}
+
+			let start = self.read_frame.len();
+			self.read_frame.resize(wanted, 0);
+			let poll = Pin::new(&mut self.stream)
+				.poll_read(cx, &mut self.read_frame[start..]);
+			let len = match poll {
+				Poll::Ready(Ok(len)) => len,
+				Poll::Ready(Err(e)) => {
+					self.read_frame.truncate(start);
+					return Poll::Ready(Err(e));
+				}
+				Poll::Pending => {
+					self.read_frame.truncate(start);
+					return Poll::Pending;
+				}
+			};
+			self.read_frame.truncate(start + len);
+			if len == 0 {
+				return match start {
+					0 => Poll::Ready(Ok(None)),
+					_ => Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+				};
+			}
+		}
+
+		let frame = std::mem::take(&mut self.read_frame);
+		let data = self
+			.noise
+			.decrypt_message(&frame[LENGTH_PREFIX..])
+			.map_err(invalid_data)?;
+		self.received += frame.len() as u64;
+		if self.received >= self.rekey_interval {
+			self.rekey_incoming().map_err(invalid_data)?;
+		}
+		Poll::Ready(Ok(Some(data)))
+	}
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for NoiseStream<S> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		while this.read_offset == this.read_data.len() {
+			match this.poll_read_frame(cx) {
+				Poll::Ready(Ok(Some(data))) => {
+					this.read_data = data;
+					this.read_offset = 0;
+				}
+				Poll::Ready(Ok(None)) => return Poll::Ready(Ok(0)),
+				Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+				Poll::Pending => return Poll::Pending,
+			}
+		}
+
+		let data = &this.read_data[this.read_offset..];
+		let len = data.len().min(buf.len());
+		buf[..len].copy_from_slice(&data[..len]);
+		this.read_offset += len;
+		Poll::Ready(Ok(len))
+	}
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for NoiseStream<S> {
+	// encrypt as much of the buffer as fits in a frame, the frame is written
+	// on the next write or flush
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		match this.poll_write_frame(cx) {
+			Poll::Ready(Ok(())) => {}
+			poll => return poll.map(|result| result.map(|_| 0)),
+		}
+		if buf.is_empty() {
+			return Poll::Ready(Ok(0));
+		}
+
+		let len = buf.len().min(NOISE_MAX_PLAINTEXT);
+		let message = this
+			.noise
+			.encrypt_message(&buf[..len])
+			.map_err(invalid_data)?;
+		this.write_frame = to_frame(&message);
+		this.written = 0;
+		this.sent += this.write_frame.len() as u64;
+		if this.sent >= this.rekey_interval {
+			this.rekey_outgoing().map_err(invalid_data)?;
+		}
+		Poll::Ready(Ok(len))
+	}
+
+	fn poll_flush(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		match this.poll_write_frame(cx) {
+			Poll::Ready(Ok(())) => Pin::new(&mut this.stream).poll_flush(cx),
+			poll => poll,
+		}
+	}
+
+	fn poll_close(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		match this.poll_write_frame(cx) {
+			Poll::Ready(Ok(())) => Pin::new(&mut this.stream).poll_close(cx),
+			poll => poll,
+		}
+	}
+}
+
+/*
+	Framing
+*/
+fn to_frame(message: &[u8]) -> Vec<u8> {
+	let mut frame = Vec::with_capacity(LENGTH_PREFIX + message.len());
+	frame.extend_from_slice(&(message.len() as u16).to_be_bytes());
+	frame.extend_from_slice(message);
+	frame
+}
+
+fn frame_length(frame: &[u8]) -> usize {
+	u16::from_be_bytes([frame[0], frame[1]]) as usize
+}
+
+async fn write_frame<S: AsyncWrite + Unpin>(
+	stream: &mut S,
+	message: &[u8],
+) -> Result<(), NoiseError> {
+	if message.len() > NOISE_MAX_MESSAGE {
+		return Err(NoiseError::InvalidBuffer);
+	}
+	stream
+		.write_all(&to_frame(message))
+		.await
+		.map_err(|_| NoiseError::InvalidState)?;
+	stream.flush().await.map_err(|_| NoiseError::InvalidState)
+}
+
+async fn read_frame<S: AsyncRead + Unpin>(
+	stream: &mut S,
+) -> Result<Vec<u8>, NoiseError> {
+	let mut prefix = [0u8; LENGTH_PREFIX];
+	stream
+		.read_exact(&mut prefix)
+		.await
+		.map_err(|_| NoiseError::InvalidMessage)?;
+	let mut message = vec![0u8; frame_length(&prefix)];
+	stream
+		.read_exact(&mut message)
+		.await
+		.map_err(|_| NoiseError::InvalidMessage)?;
+	Ok(message)
+}
+
+fn invalid_data(e: NoiseError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
@@ -0,0 +1,474 @@
+use crate::*;
+use futures::channel::oneshot as stop;
+use futures::future::join_all;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ClientError {
+	// the request could not be sent or its response read
+	Network(SubfieldError),
+	// the server could not serve the request
+	Service(SubfieldError),
+	// the server answered with the response of another request
+	UnexpectedResponse,
+	// nothing is stored or subscribed under the key
+	NotFound,
+	// the server refused the request as invalid
+	Invalid,
+	// a newer version of the record is already stored
+	Stale { version: RecordVersion },
+	// the key was deleted at or after the record's version
+	Deleted,
+	RecordError(RecordError),
+	ReadRecordError(ReadRecordError),
+}
+
+// the senders ending the open subscription streams, by partial key hash
+type Subscriptions = Arc<DashMap<String, Vec<stop::Sender<()>>>>;
+
+/*
+	SubfieldClient
+	Makes requests to the subfield servers without assembling them by hand.
+	Record requests go to the known server closest to each routing location,
+	gets are quorum reads across the three of them, and subscriptions follow
+	the ring as it changes.
+*/
+pub struct SubfieldClient<O: StreamOpener = Control> {
+	subscriber: Arc<Subscriber<O>>,
+	table: Arc<RoutingTable>,
+	consistency: ReadConsistency,
+	// ended on unsubscribe, each removed once its stream is dropped
+	subscriptions: Subscriptions,
+}
+
+impl<O: StreamOpener> Clone for SubfieldClient<O> {
+	fn clone(&self) -> Self {
+		Self {
+			subscriber: self.subscriber.clone(),
+			table: self.table.clone(),
+			consistency: self.consistency,
+			subscriptions: self.subscriptions.clone(),
+		}
+	}
+}
+
+impl<O: StreamOpener> SubfieldClient<O> {
+	/*
+	Constructors
+	*/
+	pub fn new(opener: O, table: Arc<RoutingTable>) -> Self {
+		Self {
+			subscriber: Arc::new(Subscriber::new(opener, table.clone())),
+			table,
+			consistency: ReadConsistency::default(),
+			subscriptions: Default::default(),
+		}
+	}
+
+	pub fn with_consistency(mut self, consistency: ReadConsistency) -> Self {
+		self.consistency = consistency;
+		self
+	}
+
+	/*
+	Getters
+	*/
+	pub fn table(&self) -> &Arc<RoutingTable> {
+		&self.table
+	}
+
+	/*
+	System
+	*/
+
+	// the round trip time to the peer
+	pub async fn ping(
+		&self,
+		peer: PeerId,
+	) -> Result<chrono::Duration, ClientError> {
+		let sent_at = Utc::now();
+		let request = SubfieldRequest::new(
+			RoutingKey::random(),
+			SubfieldRequestBody::Ping(PingRequest { timestamp: sent_at }),
+		);
+		match self.request(peer, request).await? {
+			SubfieldResponse::Ping(Ok(_)) => Ok(Utc::now() - sent_at),
+			SubfieldResponse::Ping(Err(e)) => Err(e.into()),
+			_ => Err(ClientError::UnexpectedResponse),
+		}
+	}
+
+	pub async fn echo(
+		&self,
+		peer: PeerId,
+		message: &str,
+	) -> Result<String, ClientError> {
+		let request = SubfieldRequest::new(
+			RoutingKey::random(),
+			SubfieldRequestBody::Echo(EchoRequest {
+				message: message.to_string(),
+			}),
+		);
+		match self.request(peer, request).await? {
+			SubfieldResponse::Echo(Ok(success)) => Ok(success.message),
+			SubfieldResponse::Echo(Err(e)) => Err(e.into()),
+			_ => Err(ClientError::UnexpectedResponse),
+		}
+	}
+
+	/*
+	Records
+	*/
+
+	// the newest verified record under the key
+	pub async fn get(&self, key: &CompleteKey) -> Result<Record, ClientError> {
+		let peer = *self.table.local_peer_id();
+		let reader = RecordReader::new(Arc::new(self.clone()), peer)
+			.with_consistency(self.consistency);
+		Ok(reader.read(key).await?.record)
+	}
+
+	// put the record at each of its routing locations, signed by the key's
+	// signer or cosigner
+	pub async fn put(
+		&self,
+		record: &Record,
+		keypair: &Keypair,
+	) -> Result<(), ClientError> {
+		let requests = record.to_put_record_requests(&record.key, keypair)?;
+		let responses = join_all(requests.map(|request| async {
+			match self.routed(request).await? {
+				SubfieldResponse::PutRecord(Ok(_)) => Ok(()),
+				SubfieldResponse::PutRecord(Err(e)) => Err(e.into()),
+				_ => Err(ClientError::UnexpectedResponse),
+			}
+		}))
+		.await;
+		responses.into_iter().collect()
+	}
+
+	// delete the version of the record under the key, and every one before
+	// it, at each of its routing locations. The record need not be found
+	pub async fn delete(
+		&self,
+		key: &CompleteKey,
+		version: RecordVersion,
+		keypair: &Keypair,
+	) -> Result<(), ClientError> {
+		let requests = Deletion::new(key.clone(), version)
+			.to_delete_record_requests(keypair)?;
+		let responses = join_all(requests.map(|request| async {
+			match self.routed(request).await? {
+				SubfieldResponse::DeleteRecord(Ok(_)) => Ok(()),
+				SubfieldResponse::DeleteRecord(Err(e)) => Err(e.into()),
+				_ => Err(ClientError::UnexpectedResponse),
+			}
+		}))
+		.await;
+		responses.into_iter().collect()
+	}
+
+	/*
+	Pubsub
+	*/
+
+	// every verified record put or published under a key matching the partial
+	// key, until unsubscribed
+	pub fn subscribe(
+		&self,
+		key: PartialKey,
+	) -> Result<impl futures::Stream<Item = Record>, ClientError> {
+		let hash = key.hash().to_string();
+		let routing_key = RoutingKey::from_partial_key(key)
+			.map_err(|_| ClientError::Invalid)?;
+		let (sender, stopped) = stop::channel();
+		self.subscriptions
+			.entry(hash.clone())
+			.or_default()
+			.push(sender);
+		let unsubscribed = Unsubscribed {
+			stopped,
+			subscriptions: self.subscriptions.clone(),
+			hash,
+		};
+
+		let events = self.subscriber.subscribe_until(routing_key, unsubscribed);
+		Ok(events.filter_map(|event| async move {
+			match event {
+				SubscriberEvent::Record(event)
+				| SubscriberEvent::Message(event) => {
+					Record::from_get_record_response(Ok(event.record)).ok()
+				}
+				SubscriberEvent::Gap => None,
+			}
+		}))
+	}
+
+	// end the subscriptions made to the partial key, each stream asks the
+	// server serving it to end it and then ends
+	pub fn unsubscribe(&self, key: &PartialKey) -> Result<(), ClientError> {
+		let Some((_, senders)) =
+			self.subscriptions.remove(&key.hash().to_string())
+		else {
+			return Err(ClientError::NotFound);
+		};
+		for sender in senders {
+			let _ = sender.send(());
+		}
+		Ok(())
+	}
+
+	/*
+	Requests
+	*/
+
+	// send the request to the known server closest to its routing key
+	async fn routed(
+		&self,
+		request: SubfieldRequest,
+	) -> Result<SubfieldResponse, SubfieldError> {
+		let routing_field = request.routing_key.get_routing_field()?;
+		let peer = self
+			.table
+			.closest_known_peer(&routing_field)
+			.ok_or(SubfieldError::NoConnectedPeers)?;
+		self.request(peer, request).await
+	}
+
+	// send the request and read its first response
+	async fn request(
+		&self,
+		peer: PeerId,
+		request: SubfieldRequest,
+	) -> Result<SubfieldResponse, SubfieldError> {
+		let stream = self.subscriber.opener().open(peer).await?;
+		let mut stream = client_stream(stream);
+		send_request(&mut stream, request).await?;
+		recv_response(&mut stream).await
+	}
+}
+
+// completes once the subscription is unsubscribed, and removes it from the
+// client's subscriptions when its stream is dropped
+struct Unsubscribed {
+	stopped: stop::Receiver<()>,
+	subscriptions: Subscriptions,
+	hash: String,
+}
+
+impl Future for Unsubscribed {
+	type Output = ();
+
+	fn poll(
+		mut self: Pin<&mut Self>,
+		cx: &mut std::task::Context<'_>,
+	) -> std::task::Poll<()> {
+		self.stopped.poll_unpin(cx).map(|_| ())
+	}
+}
+
+impl Drop for Unsubscribed {
+	fn drop(&mut self) {
+		// cancels this subscription's sender, so it is the one removed
+		self.stopped.close();
+		if let Some(mut senders) = self.subscriptions.get_mut(&self.hash) {
+			senders.retain(|sender| !sender.is_canceled());
+		}
+		self.subscriptions
+			.remove_if(&self.hash, |_, senders| senders.is_empty());
+	}
+}
+
+// the client reads through a RecordReader as its record handler, the peer
+// passed in is ignored
+#[async_trait]
+impl<O: StreamOpener> RecordHandler for SubfieldClient<O> {
+	async fn get_record(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: GetRecordRequest,
+	) -> GetRecordResponse {
+		let request = SubfieldRequest::new(
+			routing_key,
+			SubfieldRequestBody::GetRecord(request),
+		);
+		match self.routed(request).await {
+			Ok(SubfieldResponse::GetRecord(response)) => response,
+			Ok(_) => Err(GetRecordFailure::ServiceError(
+				SubfieldError::UnexpectedResponseType,
+			)),
+			Err(e) => Err(GetRecordFailure::ServiceError(e)),
+		}
+	}
+
+	async fn put_record(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: PutRecordRequest,
+	) -> PutRecordResponse {
+		let request = SubfieldRequest::new(
+			routing_key,
+			SubfieldRequestBody::PutRecord(request),
+		);
+		match self.routed(request).await {
+			Ok(SubfieldResponse::PutRecord(response)) => response,
+			Ok(_) => Err(PutRecordFailure::ServiceError(
+				SubfieldError::UnexpectedResponseType,
+			)),
+			Err(e) => Err(PutRecordFailure::ServiceError(e)),
+		}
+	}
+
+	async fn delete_record(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: DeleteRecordRequest,
+	) -> DeleteRecordResponse {
+		let request = SubfieldRequest::new(
+			routing_key,
+			SubfieldRequestBody::DeleteRecord(request),
+		);
+		match self.routed(request).await {
+			Ok(SubfieldResponse::DeleteRecord(response)) => response,
+			Ok(_) => Err(DeleteRecordFailure::ServiceError(
+				SubfieldError::UnexpectedResponseType,
+			)),
+			Err(e) => Err(DeleteRecordFailure::ServiceError(e)),
+		}
+	}
+
+	// every page of the listing is read before the stream is returned
+	async fn list_records(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: ListRecordsRequest,
+	) -> Result<ListRecordsStream, ListRecordsFailure> {
+		let service_error = ListRecordsFailure::ServiceError;
+		let routing_field =
+			routing_key.get_routing_field().map_err(service_error)?;
+		let peer = self
+			.table
+			.closest_known_peer(&routing_field)
+			.ok_or(service_error(SubfieldError::NoConnectedPeers))?;
+		let stream = self
+			.subscriber
+			.opener()
+			.open(peer)
+			.await
+			.map_err(service_error)?;
+		let mut stream = client_stream(stream);
+		let request = SubfieldRequest::new(
+			routing_key,
+			SubfieldRequestBody::ListRecords(request),
+		);
+		send_request(&mut stream, request)
+			.await
+			.map_err(service_error)?;
+
+		let mut responses = vec![];
+		loop {
+			match recv_response(&mut stream).await.map_err(service_error)? {
+				SubfieldResponse::ListRecords(Ok(
+					ListRecordsSuccess::End { next },
+				)) => {
+					responses.push(Ok(ListRecordsSuccess::End { next }));
+					return Ok(stream::iter(responses).boxed());
+				}
+				SubfieldResponse::ListRecords(Ok(success)) => {
+					responses.push(Ok(success))
+				}
+				SubfieldResponse::ListRecords(Err(e)) => return Err(e),
+				_ => {
+					return Err(service_error(
+						SubfieldError::UnexpectedResponseType,
+					))
+				}
+			}
+		}
+	}
+}
+
+/*
+	Errors
+*/
+impl From<SubfieldError> for ClientError {
+	fn from(e: SubfieldError) -> Self {
+		ClientError::Network(e)
+	}
+}
+
+impl From<RecordError> for ClientError {
+	fn from(e: RecordError) -> Self {
+		ClientError::RecordError(e)
+	}
+}
+
+impl From<ReadRecordError> for ClientError {
+	fn from(e: ReadRecordError) -> Self {
+		match e {
+			ReadRecordError::Unknown => ClientError::NotFound,
+			e => ClientError::ReadRecordError(e),
+		}
+	}
+}
+
+impl From<PingFailure> for ClientError {
+	fn from(e: PingFailure) -> Self {
+		match e {
+			PingFailure::Unknown => ClientError::NotFound,
+			PingFailure::Invalid => ClientError::Invalid,
+			PingFailure::ServiceError(e) => ClientError::Service(e),
+		}
+	}
+}
+
+impl From<EchoFailure> for ClientError {
+	fn from(e: EchoFailure) -> Self {
+		match e {
+			EchoFailure::Unknown => ClientError::NotFound,
+			EchoFailure::Invalid => ClientError::Invalid,
+			EchoFailure::ServiceError(e) => ClientError::Service(e),
+		}
+	}
+}
+
+impl From<PutRecordFailure> for ClientError {
+	fn from(e: PutRecordFailure) -> Self {
+		match e {
+			PutRecordFailure::Unknown => ClientError::NotFound,
+			PutRecordFailure::Invalid => ClientError::Invalid,
+			PutRecordFailure::NoPeersConnected => {
+				ClientError::Service(SubfieldError::NoConnectedPeers)
+			}
+			PutRecordFailure::ServiceError(e) => ClientError::Service(e),
+			PutRecordFailure::RecordError(e) => ClientError::RecordError(e),
+			PutRecordFailure::Deleted { .. } => ClientError::Deleted,
+			PutRecordFailure::Stale { version } => {
+				ClientError::Stale { version }
+			}
+		}
+	}
+}
+
+impl From<DeleteRecordFailure> for ClientError {
+	fn from(e: DeleteRecordFailure) -> Self {
+		match e {
+			DeleteRecordFailure::Unknown => ClientError::NotFound,
+			DeleteRecordFailure::Invalid => ClientError::Invalid,
+			DeleteRecordFailure::ServiceError(e) => ClientError::Service(e),
+		}
+	}
+}
+
+impl From<UnsubscribeFailure> for ClientError {
+	fn from(e: UnsubscribeFailure) -> Self {
+		match e {
+			UnsubscribeFailure::Unknown => ClientError::NotFound,
+			UnsubscribeFailure::Invalid => ClientError::Invalid,
+			UnsubscribeFailure::ServiceError(e) => ClientError::Service(e),
+		}
+	}
+}
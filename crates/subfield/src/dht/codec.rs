@@ -0,0 +1,200 @@
+use crate::*;
+use asynchronous_codec::{Decoder, Encoder, Framed};
+use std::{io, marker::PhantomData};
+use thiserror::Error;
+
+// the largest message body we will read or write, excluding the length prefix
+pub const MAX_FRAME_SIZE: usize = 4 * MB as usize;
+
+#[derive(Debug, Error)]
+pub enum SubfieldCodecError {
+	#[error("io error: {0}")]
+	Io(#[from] io::Error),
+	#[error("frame of {0} bytes exceeds the maximum frame size")]
+	FrameTooLarge(usize),
+	#[error("failed to serialize frame")]
+	SerializationFailed,
+	#[error("failed to deserialize frame")]
+	DeserializationFailed,
+}
+
+/*
+	SubfieldCodec
+	Each frame is an unsigned-varint length prefix followed by a bincode body
+*/
+pub struct SubfieldCodec<In, Out> {
+	max_frame_size: usize,
+	_phantom: PhantomData<(In, Out)>,
+}
+
+impl<In, Out> SubfieldCodec<In, Out> {
+	pub fn new(max_frame_size: usize) -> Self {
+		Self {
+			max_frame_size,
+			_phantom: PhantomData,
+		}
+	}
+
+	pub fn max_frame_size(&self) -> usize {
+		self.max_frame_size
+	}
+}
+
+impl<In, Out> Default for SubfieldCodec<In, Out> {
+	fn default() -> Self {
+		Self::new(MAX_FRAME_SIZE)
+	}
+}
+
+impl<In: Serialize, Out> Encoder for SubfieldCodec<In, Out> {
+	type Item<'a> = In;
+	type Error = SubfieldCodecError;
+
+	fn encode(
+		&mut self,
+		item: Self::Item<'_>,
+		dst: &mut BytesMut,
+	) -> Result<(), Self::Error> {
+		let body = serialize(&item)
+			.map_err(|_| SubfieldCodecError::SerializationFailed)?;
+
+		if body.len() > self.max_frame_size {
+			return Err(SubfieldCodecError::FrameTooLarge(body.len()));
+		}
+
+		let mut prefix_buffer = unsigned_varint::encode::usize_buffer();
+		let prefix =
+			unsigned_varint::encode::usize(body.len(), &mut prefix_buffer);
+
+		dst.reserve(prefix.len() + body.len());
+		dst.extend_from_slice(prefix);
+		dst.extend_from_slice(&body);
+		Ok(())
+	}
+}
+
+impl<In, Out: DeserializeOwned> Decoder for SubfieldCodec<In, Out> {
+	type Item = Out;
+	type Error = SubfieldCodecError;
+
+	fn decode(
+		&mut self,
+		src: &mut BytesMut,
+	) -> Result<Option<Self::Item>, Self::Error> {
+		let (body_len, remaining_len) =
+			match unsigned_varint::decode::usize(src) {
+				Ok((body_len, remaining)) => (body_len, remaining.len()),
+				// the length prefix has not fully arrived yet
+				Err(unsigned_varint::decode::Error::Insufficient) => {
+					return Ok(None)
+				}
+				Err(e) => {
+					return Err(
+						io::Error::new(io::ErrorKind::InvalidData, e).into()
+					)
+				}
+			};
+
+		if body_len > self.max_frame_size {
+			return Err(SubfieldCodecError::FrameTooLarge(body_len));
+		}
+
+		let prefix_len = src.len() - remaining_len;
+
+		// the body has not fully arrived yet
+		if remaining_len < body_len {
+			src.reserve(body_len - remaining_len);
+			return Ok(None);
+		}
+
+		src.advance(prefix_len);
+		let body = src.split_to(body_len);
+
+		deserialize(&body)
+			.map(Some)
+			.map_err(|_| SubfieldCodecError::DeserializationFailed)
+	}
+}
+
+/*
+	Framed Streams
+*/
+
+// the dialing side, writes requests and reads responses
+pub type SubfieldRequestCodec =
+	SubfieldCodec<SubfieldRequest, SubfieldResponse>;
+pub type SubfieldClientStream<S = Stream> = Framed<S, SubfieldRequestCodec>;
+
+// the listening side, reads requests and writes responses
+pub type SubfieldResponseCodec =
+	SubfieldCodec<SubfieldResponse, SubfieldRequest>;
+pub type SubfieldServerStream<S = Stream> = Framed<S, SubfieldResponseCodec>;
+
+pub fn client_stream<S: AsyncRead + AsyncWrite>(
+	stream: S,
+) -> SubfieldClientStream<S> {
+	Framed::new(stream, SubfieldRequestCodec::default())
+}
+
+pub fn server_stream<S: AsyncRead + AsyncWrite>(
+	stream: S,
+) -> SubfieldServerStream<S> {
+	Framed::new(stream, SubfieldResponseCodec::default())
+}
+
+/*
+	Helpers
+*/
+
+fn map_write_error(e: SubfieldCodecError) -> SubfieldError {
+	match e {
+		SubfieldCodecError::SerializationFailed => {
+			SubfieldError::SerializationFailed
+		}
+		_ => SubfieldError::FailedToWriteStream,
+	}
+}
+
+fn map_read_error(e: SubfieldCodecError) -> SubfieldError {
+	match e {
+		SubfieldCodecError::DeserializationFailed => {
+			SubfieldError::DeserializationFailed
+		}
+		_ => SubfieldError::FailedToReadStream,
+	}
+}
+
+pub async fn send_request<S: AsyncRead + AsyncWrite + Unpin>(
+	stream: &mut SubfieldClientStream<S>,
+	request: SubfieldRequest,
+) -> Result<(), SubfieldError> {
+	stream.send(request).await.map_err(map_write_error)
+}
+
+pub async fn recv_response<S: AsyncRead + AsyncWrite + Unpin>(
+	stream: &mut SubfieldClientStream<S>,
+) -> Result<SubfieldResponse, SubfieldError> {
+	match stream.next().await {
+		Some(Ok(response)) => Ok(response),
+		Some(Err(e)) => Err(map_read_error(e)),
+		// the remote closed the stream before responding
+		None => Err(SubfieldError::FailedToReadStream),
+	}
+}
+
+pub async fn recv_request<S: AsyncRead + AsyncWrite + Unpin>(
+	stream: &mut SubfieldServerStream<S>,
+) -> Result<SubfieldRequest, SubfieldError> {
+	match stream.next().await {
+		Some(Ok(request)) => Ok(request),
+		Some(Err(e)) => Err(map_read_error(e)),
+		None => Err(SubfieldError::FailedToReadStream),
+	}
+}
+
+pub async fn send_response<S: AsyncRead + AsyncWrite + Unpin>(
+	stream: &mut SubfieldServerStream<S>,
+	response: SubfieldResponse,
+) -> Result<(), SubfieldError> {
+	stream.send(response).await.map_err(map_write_error)
+}
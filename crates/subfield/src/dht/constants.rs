@@ -1,3 +1,3 @@
 use libp2p::StreamProtocol;
 
-pub const SUBFIELD_PROTOCOL: StreamProtocol = StreamProtocol::new("subfield/1.0.0");
\ No newline at end of file
+pub const SUBFIELD_PROTOCOL: StreamProtocol = StreamProtocol::new("/subfield/1.0.0");
\ No newline at end of file
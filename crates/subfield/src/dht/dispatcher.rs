@@ -0,0 +1,277 @@
+use crate::*;
+use futures::future::{self, Either as FutureEither};
+use futures::stream::SelectAll;
+
+/*
+	Dispatcher
+	Reads SubfieldRequests from inbound streams and routes them to the handlers.
+	Oneshot streams are closed after one response, streaming ones stay open
+	until the remote closes them.
+*/
+#[derive(Clone)]
+pub struct Dispatcher {
+	system: Arc<dyn SystemHandler>,
+	record: Arc<dyn RecordHandler>,
+	pubsub: Arc<dyn PubsubHandler>,
+}
+
+impl Dispatcher {
+	/*
+	Constructors
+	*/
+	pub fn new(
+		system: Arc<dyn SystemHandler>,
+		record: Arc<dyn RecordHandler>,
+		pubsub: Arc<dyn PubsubHandler>,
+	) -> Self {
+		Self {
+			system,
+			record,
+			pubsub,
+		}
+	}
+
+	/*
+	Serving
+	*/
+
+	// serve every inbound stream until the incoming streams are closed
+	pub async fn run(&self, incoming: IncomingStreams) {
+		incoming
+			.for_each_concurrent(None, |(peer, stream)| async move {
+				if let Err(e) = self.serve_stream(peer, stream).await {
+					tracing::debug!(%peer, "Failed to serve stream: {e}");
+				}
+			})
+			.await
+	}
+
+	// serve a single inbound stream
+	pub async fn serve_stream<S: AsyncRead + AsyncWrite + Unpin + Send>(
+		&self,
+		peer: PeerId,
+		stream: S,
+	) -> Result<(), SubfieldError> {
+		let mut stream = server_stream(stream);
+		let request = recv_request(&mut stream).await?;
+		self.serve_request(peer, stream, request).await
+	}
+
+	// serve a stream whose first request has already been read
+	pub async fn serve_request<S: AsyncRead + AsyncWrite + Unpin + Send>(
+		&self,
+		peer: PeerId,
+		mut stream: SubfieldServerStream<S>,
+		request: SubfieldRequest,
+	) -> Result<(), SubfieldError> {
+		if request.is_oneshot() {
+			let response = self.handle_oneshot(peer, request).await;
+			send_response(&mut stream, response).await?;
+			return stream
+				.close()
+				.await
+				.map_err(|_| SubfieldError::FailedToCloseStream);
+		}
+
+		self.serve_streaming(peer, stream, request).await
+	}
+
+	// answer a oneshot request
+	pub async fn handle_oneshot(
+		&self,
+		peer: PeerId,
+		request: SubfieldRequest,
+	) -> SubfieldResponse {
+		let routing_key = request.routing_key;
+		match request.body {
+			SubfieldRequestBody::Ping(req) => {
+				SubfieldResponse::Ping(self.system.ping(peer, req).await)
+			}
+			SubfieldRequestBody::Echo(req) => {
+				SubfieldResponse::Echo(self.system.echo(peer, req).await)
+			}
+			SubfieldRequestBody::GetRecord(req) => SubfieldResponse::GetRecord(
+				self.record.get_record(peer, routing_key, req).await,
+			),
+			SubfieldRequestBody::PutRecord(req) => SubfieldResponse::PutRecord(
+				self.record.put_record(peer, routing_key, req).await,
+			),
+			SubfieldRequestBody::DeleteRecord(req) => {
+				SubfieldResponse::DeleteRecord(
+					self.record.delete_record(peer, routing_key, req).await,
+				)
+			}
+			SubfieldRequestBody::ListRecords(_) => {
+				SubfieldResponse::ListRecords(Err(ListRecordsFailure::Invalid))
+			}
+			SubfieldRequestBody::Subscribe(_) => {
+				SubfieldResponse::Subscribe(Err(SubscribeFailure::Invalid))
+			}
+			SubfieldRequestBody::Unsubscribe(_) => {
+				SubfieldResponse::Unsubscribe(Err(UnsubscribeFailure::Invalid))
+			}
+			SubfieldRequestBody::Publish(req) => SubfieldResponse::Publish(
+				self.pubsub.publish(peer, routing_key, req).await,
+			),
+		}
+	}
+
+	// keep a streaming stream open, answering further requests on it,
+	// listing records and forwarding the events of every subscription made
+	// on it
+	async fn serve_streaming<S: AsyncRead + AsyncWrite + Unpin + Send>(
+		&self,
+		peer: PeerId,
+		mut stream: SubfieldServerStream<S>,
+		request: SubfieldRequest,
+	) -> Result<(), SubfieldError> {
+		let mut subscriptions: SelectAll<KeyedSubscription> = SelectAll::new();
+		let mut next_request = Some(request);
+
+		loop {
+			if let Some(request) = next_request.take() {
+				let routing_key = request.routing_key.clone();
+				let response = match request.body {
+					SubfieldRequestBody::ListRecords(req) => {
+						self.list_records(peer, &mut stream, routing_key, req)
+							.await?;
+						continue;
+					}
+					SubfieldRequestBody::Subscribe(req) => {
+						let hash = req.key.hash();
+						match self.pubsub.subscribe(peer, req).await {
+							Ok(subscription) => {
+								subscriptions.push(KeyedSubscription {
+									hash,
+									events: subscription.events,
+								});
+								SubfieldResponse::Subscribe(Ok(
+									SubscribeSuccess::Subscribed {
+										lease: subscription.lease,
+									},
+								))
+							}
+							Err(failure) => {
+								SubfieldResponse::Subscribe(Err(failure))
+							}
+						}
+					}
+					SubfieldRequestBody::Unsubscribe(req) => {
+						SubfieldResponse::Unsubscribe(
+							self.unsubscribe(
+								peer,
+								&mut stream,
+								&mut subscriptions,
+								req,
+							)
+							.await?,
+						)
+					}
+					_ => self.handle_oneshot(peer, request).await,
+				};
+				send_response(&mut stream, response).await?;
+			}
+
+			// wait for either the next request or the next subscription event
+			if subscriptions.is_empty() {
+				match stream.next().await {
+					Some(Ok(request)) => next_request = Some(request),
+					// the remote closed the stream
+					_ => return Ok(()),
+				}
+			} else {
+				let next =
+					match future::select(stream.next(), subscriptions.next())
+						.await
+					{
+						FutureEither::Left((request, _)) => {
+							Either::Left(request)
+						}
+						FutureEither::Right((event, _)) => Either::Right(event),
+					};
+
+				match next {
+					Either::Left(Some(Ok(request))) => {
+						next_request = Some(request)
+					}
+					Either::Left(_) => return Ok(()),
+					Either::Right(Some(event)) => {
+						send_response(
+							&mut stream,
+							SubfieldResponse::Subscribe(event),
+						)
+						.await?
+					}
+					// every subscription has ended
+					Either::Right(None) => {}
+				}
+			}
+		}
+	}
+
+	// end the subscriptions made to the key on this stream alone, sending the
+	// events already queued for them first
+	async fn unsubscribe<S: AsyncRead + AsyncWrite + Unpin + Send>(
+		&self,
+		peer: PeerId,
+		stream: &mut SubfieldServerStream<S>,
+		subscriptions: &mut SelectAll<KeyedSubscription>,
+		request: UnsubscribeRequest,
+	) -> Result<UnsubscribeResponse, SubfieldError> {
+		let hash = request.key.hash();
+		let (ended, kept): (Vec<_>, Vec<_>) =
+			std::mem::replace(subscriptions, SelectAll::new())
+				.into_iter()
+				.partition(|subscription| subscription.hash == hash);
+		*subscriptions = kept.into_iter().collect();
+		if ended.is_empty() {
+			return Ok(Err(UnsubscribeFailure::Unknown));
+		}
+
+		for mut subscription in ended {
+			while let Some(Some(event)) = subscription.next().now_or_never() {
+				send_response(stream, SubfieldResponse::Subscribe(event))
+					.await?;
+			}
+		}
+		Ok(self.pubsub.unsubscribe(peer, request).await)
+	}
+
+	// send every response of a listing, the failure alone if it fails
+	async fn list_records<S: AsyncRead + AsyncWrite + Unpin + Send>(
+		&self,
+		peer: PeerId,
+		stream: &mut SubfieldServerStream<S>,
+		routing_key: RoutingKey,
+		request: ListRecordsRequest,
+	) -> Result<(), SubfieldError> {
+		let mut responses =
+			match self.record.list_records(peer, routing_key, request).await {
+				Ok(responses) => responses,
+				Err(failure) => stream::iter(vec![Err(failure)]).boxed(),
+			};
+
+		while let Some(response) = responses.next().await {
+			send_response(stream, SubfieldResponse::ListRecords(response))
+				.await?;
+		}
+		Ok(())
+	}
+}
+
+// a subscription's events, with the hash of the partial key it was made to
+struct KeyedSubscription {
+	hash: V256,
+	events: SubscriptionStream,
+}
+
+impl futures::Stream for KeyedSubscription {
+	type Item = SubscribeResponse;
+
+	fn poll_next(
+		mut self: Pin<&mut Self>,
+		cx: &mut std::task::Context<'_>,
+	) -> std::task::Poll<Option<Self::Item>> {
+		self.events.poll_next_unpin(cx)
+	}
+}
@@ -0,0 +1,145 @@
+use crate::*;
+use futures::future::{self, Either as FutureEither};
+
+/*
+	Forwarder
+	Hands client requests off to the known server closest to their routing
+	key, proxying the responses back. Requests are answered by the local
+	dispatcher once no known server is closer.
+*/
+#[derive(Clone)]
+pub struct Forwarder {
+	table: Arc<RoutingTable>,
+	dispatcher: Dispatcher,
+	control: Control,
+}
+
+impl Forwarder {
+	/*
+	Constructors
+	*/
+	pub fn new(
+		table: Arc<RoutingTable>,
+		dispatcher: Dispatcher,
+		control: Control,
+	) -> Self {
+		Self {
+			table,
+			dispatcher,
+			control,
+		}
+	}
+
+	/*
+	Getters
+	*/
+	pub fn table(&self) -> &Arc<RoutingTable> {
+		&self.table
+	}
+
+	pub fn dispatcher(&self) -> &Dispatcher {
+		&self.dispatcher
+	}
+
+	/*
+	Serving
+	*/
+
+	// serve every inbound stream until the incoming streams are closed
+	pub async fn run(&self, incoming: IncomingStreams) {
+		incoming
+			.for_each_concurrent(None, |(peer, stream)| async move {
+				if let Err(e) = self.serve_stream(peer, stream).await {
+					tracing::debug!(%peer, "Failed to forward stream: {e}");
+				}
+			})
+			.await
+	}
+
+	// serve a single inbound stream, locally or through the closest server
+	pub async fn serve_stream<S: AsyncRead + AsyncWrite + Unpin + Send>(
+		&self,
+		peer: PeerId,
+		stream: S,
+	) -> Result<(), SubfieldError> {
+		let mut stream = server_stream(stream);
+		let request = recv_request(&mut stream).await?;
+
+		let error = match self.table.route(&request) {
+			Ok(Route::Local) => {
+				return self
+					.dispatcher
+					.serve_request(peer, stream, request)
+					.await
+			}
+			Ok(Route::Forward(next)) => {
+				match self
+					.control
+					.clone()
+					.open_stream(next, SUBFIELD_PROTOCOL)
+					.await
+				{
+					Ok(upstream) => {
+						tracing::debug!(%peer, %next, "Forwarding request");
+						return self.proxy(stream, upstream, request).await;
+					}
+					Err(_) => SubfieldError::FailedToOpenStream,
+				}
+			}
+			Err(e) => e,
+		};
+
+		send_response(&mut stream, request.error_response(error)).await?;
+		stream
+			.close()
+			.await
+			.map_err(|_| SubfieldError::FailedToCloseStream)
+	}
+
+	// relay a request to an opened upstream stream and every frame after it
+	// in both directions, until either side closes
+	pub async fn proxy<
+		S: AsyncRead + AsyncWrite + Unpin + Send,
+		U: AsyncRead + AsyncWrite + Unpin + Send,
+	>(
+		&self,
+		mut stream: SubfieldServerStream<S>,
+		upstream: U,
+		mut request: SubfieldRequest,
+	) -> Result<(), SubfieldError> {
+		let mut upstream = client_stream(upstream);
+		let is_oneshot = request.is_oneshot();
+
+		request.hops.push(self.table.local_id().clone());
+		send_request(&mut upstream, request).await?;
+
+		if is_oneshot {
+			let response = recv_response(&mut upstream).await?;
+			send_response(&mut stream, response).await?;
+			return stream
+				.close()
+				.await
+				.map_err(|_| SubfieldError::FailedToCloseStream);
+		}
+
+		loop {
+			let next = match future::select(stream.next(), upstream.next())
+				.await
+			{
+				FutureEither::Left((request, _)) => Either::Left(request),
+				FutureEither::Right((response, _)) => Either::Right(response),
+			};
+
+			match next {
+				Either::Left(Some(Ok(request))) => {
+					send_request(&mut upstream, request).await?
+				}
+				Either::Right(Some(Ok(response))) => {
+					send_response(&mut stream, response).await?
+				}
+				// either side closed the stream
+				_ => return Ok(()),
+			}
+		}
+	}
+}
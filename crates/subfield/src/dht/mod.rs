@@ -8,12 +8,25 @@
 
 
 mod behaviour;
+mod client;
+mod codec;
 mod constants;
 mod control;
+mod dispatcher;
+mod forwarder;
 mod handler;
+mod routing_table;
 mod shared;
+mod subscriber;
 mod upgrade;
 mod events;
 
 pub use behaviour::{AlreadyRegistered, Behaviour};
+pub use client::*;
+pub use codec::*;
+pub use constants::*;
 pub use control::{Control, IncomingStreams, OpenStreamError};
+pub use dispatcher::*;
+pub use forwarder::*;
+pub use routing_table::*;
+pub use subscriber::*;
@@ -0,0 +1,167 @@
+use crate::*;
+
+// the most servers a request may be forwarded through
+pub const MAX_HOPS: usize = 8;
+
+// where a request should be served
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+	Local,
+	Forward(PeerId),
+}
+
+/*
+	RoutingTable
+	The servers known to the local server, keyed by peer id. Servers are
+	compared to routing keys by the xor distance of their public keys.
+*/
+pub struct RoutingTable {
+	local_peer_id: PeerId,
+	local_id: V256,
+	peers: DashMap<PeerId, V256>,
+	max_hops: usize,
+}
+
+impl RoutingTable {
+	/*
+	Constructors
+	*/
+	pub fn new(local_peer_id: PeerId) -> Result<Self, SubfieldError> {
+		Ok(Self {
+			local_peer_id,
+			local_id: peer_id_to_v256(local_peer_id)?,
+			peers: DashMap::new(),
+			max_hops: MAX_HOPS,
+		})
+	}
+
+	pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+		self.max_hops = max_hops;
+		self
+	}
+
+	/*
+	Getters
+	*/
+	pub fn local_peer_id(&self) -> &PeerId {
+		&self.local_peer_id
+	}
+
+	pub fn local_id(&self) -> &V256 {
+		&self.local_id
+	}
+
+	pub fn max_hops(&self) -> usize {
+		self.max_hops
+	}
+
+	pub fn len(&self) -> usize {
+		self.peers.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.peers.is_empty()
+	}
+
+	/*
+	Peers
+	*/
+	pub fn add_peer(&self, peer: PeerId) -> Result<(), SubfieldError> {
+		if peer == self.local_peer_id {
+			return Ok(());
+		}
+		self.peers.insert(peer, peer_id_to_v256(peer)?);
+		Ok(())
+	}
+
+	pub fn remove_peer(&self, peer: &PeerId) {
+		self.peers.remove(peer);
+	}
+
+	pub fn contains_peer(&self, peer: &PeerId) -> bool {
+		self.peers.contains_key(peer)
+	}
+
+	/*
+	Routing
+	*/
+
+	// the known peer closest to the key, skipping the excluded servers,
+	// or SelfIsClosest if no peer is closer than the local server
+	pub fn closest_peer(
+		&self,
+		key: &V256,
+		exclude: &[V256],
+	) -> Result<PeerId, SubfieldError> {
+		let local_distance = self.local_id.xor_distance(key);
+
+		self.peers
+			.iter()
+			.filter(|entry| !exclude.contains(entry.value()))
+			.map(|entry| (entry.value().xor_distance(key), *entry.key()))
+			.filter(|(distance, _)| distance < &local_distance)
+			.min_by(|(a, _), (b, _)| a.cmp(b))
+			.map(|(_, peer)| peer)
+			.ok_or(SubfieldError::SelfIsClosest)
+	}
+
+	// the known peer closest to the key however close the local server is,
+	// for clients that serve no requests themselves
+	pub fn closest_known_peer(&self, key: &V256) -> Option<PeerId> {
+		self.peers
+			.iter()
+			.map(|entry| (entry.value().xor_distance(key), *entry.key()))
+			.min_by(|(a, _), (b, _)| a.cmp(b))
+			.map(|(_, peer)| peer)
+	}
+
+	// the count servers closest to the key, the local server among the
+	// candidates, closest first
+	pub fn closest_peers(&self, key: &V256, count: usize) -> Vec<PeerId> {
+		let local = (self.local_id.xor_distance(key), self.local_peer_id);
+		self.peers
+			.iter()
+			.map(|entry| (entry.value().xor_distance(key), *entry.key()))
+			.chain(std::iter::once(local))
+			.sorted_by(|(a, _), (b, _)| a.cmp(b))
+			.take(count)
+			.map(|(_, peer)| peer)
+			.collect()
+	}
+
+	// decide whether to answer a request locally or forward it
+	pub fn route(
+		&self,
+		request: &SubfieldRequest,
+	) -> Result<Route, SubfieldError> {
+		if !request.is_routed() {
+			return Ok(Route::Local);
+		}
+
+		if request.hops.contains(&self.local_id) {
+			return Err(SubfieldError::RoutingLoop);
+		}
+
+		let routing_field = request.routing_key.get_routing_field()?;
+
+		match self.closest_peer(&routing_field, &request.hops) {
+			Ok(_) if request.hops.len() >= self.max_hops => {
+				Err(SubfieldError::HopLimitExceeded)
+			}
+			Ok(peer) => Ok(Route::Forward(peer)),
+			Err(SubfieldError::SelfIsClosest) => Ok(Route::Local),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+/*
+	Helpers
+*/
+
+// servers are identified by the public key inlined in their peer id
+pub fn peer_id_to_v256(peer: PeerId) -> Result<V256, SubfieldError> {
+	PublicKey::from_libp2p_peer_id(peer)
+		.map(|public_key| public_key.versioned_bytes().clone())
+		.map_err(|_| SubfieldError::InvalidPeerId)
+}
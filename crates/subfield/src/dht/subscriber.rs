@@ -0,0 +1,422 @@
+use crate::*;
+use futures::future::{self, Either as FutureEither, LocalBoxFuture};
+use futures::stream::LocalBoxStream;
+use std::collections::VecDeque;
+use std::task::Poll;
+use std::time::Duration;
+
+// how long to wait before trying again when no server accepts a subscription
+pub const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+// the events of a subscription that outlives the servers serving it
+pub type SubscriberStream = LocalBoxStream<'static, SubscriberEvent>;
+
+#[derive(Debug, Clone)]
+pub enum SubscriberEvent {
+	// a record was put under a key matching the subscription's key
+	Record(Box<SubscriptionEvent>),
+	// a record was published under a key matching the subscription's key
+	Message(Box<SubscriptionEvent>),
+	// the subscription was made again after its stream closed or moved to
+	// another server, events may have been missed
+	Gap,
+}
+
+/*
+	StreamOpener
+	Opens a subfield stream to a peer, so subscriptions can be made without a
+	swarm.
+*/
+#[async_trait]
+pub trait StreamOpener: Send + Sync + 'static {
+	type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+	async fn open(&self, peer: PeerId) -> Result<Self::Stream, SubfieldError>;
+}
+
+#[async_trait]
+impl StreamOpener for Control {
+	type Stream = Stream;
+
+	async fn open(&self, peer: PeerId) -> Result<Self::Stream, SubfieldError> {
+		self.clone()
+			.open_stream(peer, SUBFIELD_PROTOCOL)
+			.await
+			.map_err(|_| SubfieldError::FailedToOpenStream)
+	}
+}
+
+/*
+	Subscriber
+	Keeps subscriptions open as the ring changes. Each subscription is made
+	through the known server closest to its routing key, renewed before its
+	lease runs out, and made again through whichever server is then closest if
+	its stream closes. A gap event marks every point where events may have been
+	missed. A subscription is ended over the stream it was made on.
+*/
+pub struct Subscriber<O: StreamOpener> {
+	opener: Arc<O>,
+	table: Arc<RoutingTable>,
+	lease: u64,
+	retry_delay: Duration,
+}
+
+impl<O: StreamOpener> Subscriber<O> {
+	/*
+	Constructors
+	*/
+	pub fn new(opener: O, table: Arc<RoutingTable>) -> Self {
+		Self {
+			opener: Arc::new(opener),
+			table,
+			lease: SUBSCRIPTION_MAX_LEASE,
+			retry_delay: RESUBSCRIBE_DELAY,
+		}
+	}
+
+	// the lease in seconds asked for, servers may grant less
+	pub fn with_lease(mut self, lease: u64) -> Self {
+		self.lease = lease.clamp(1, SUBSCRIPTION_MAX_LEASE);
+		self
+	}
+
+	pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+		self.retry_delay = retry_delay;
+		self
+	}
+
+	/*
+	Getters
+	*/
+	pub fn opener(&self) -> &Arc<O> {
+		&self.opener
+	}
+
+	pub fn lease(&self) -> u64 {
+		self.lease
+	}
+
+	/*
+	Subscribing
+	*/
+
+	// the events under the routing key's partial key, until the stream is
+	// dropped
+	pub fn subscribe(&self, routing_key: RoutingKey) -> SubscriberStream {
+		self.subscribe_until(routing_key, future::pending())
+	}
+
+	// the events under the routing key's partial key until the future
+	// completes, when the server serving them is asked to end the
+	// subscription and the stream ends
+	pub fn subscribe_until(
+		&self,
+		routing_key: RoutingKey,
+		until: impl Future<Output = ()> + 'static,
+	) -> SubscriberStream {
+		let state = SubscriberState {
+			opener: self.opener.clone(),
+			table: self.table.clone(),
+			request: SubscribeRequest {
+				key: routing_key.to_partial_key(),
+				lease: self.lease,
+			},
+			routing_key,
+			retry_delay: self.retry_delay,
+			active: None,
+			pending: VecDeque::new(),
+			draining: None,
+			renewed: HashSet::new(),
+			subscribed: false,
+			until: Some(until.boxed_local()),
+		};
+		stream::unfold(state, |mut state| async move {
+			let event = state.next_event().await?;
+			Some((event, state))
+		})
+		.boxed_local()
+	}
+}
+
+// a subscription accepted by a server, renewed once the timer fires
+struct ActiveSubscription<S> {
+	peer: PeerId,
+	stream: SubfieldClientStream<S>,
+	renew: LocalBoxFuture<'static, ()>,
+}
+
+struct SubscriberState<O: StreamOpener> {
+	opener: Arc<O>,
+	table: Arc<RoutingTable>,
+	routing_key: RoutingKey,
+	request: SubscribeRequest,
+	retry_delay: Duration,
+	active: Option<ActiveSubscription<O::Stream>>,
+	// the gaps to emit before reading the streams again
+	pending: VecDeque<SubscriberEvent>,
+	// the stream replaced by the last renewal, read until its server confirms
+	// the unsubscribe
+	draining: Option<SubfieldClientStream<O::Stream>>,
+	// the record hashes of the events emitted while both streams were read,
+	// dropped if the other stream sends them again
+	renewed: HashSet<V256>,
+	// whether a subscription was made before, so the next one is a gap
+	subscribed: bool,
+	// ends the subscription once it completes, none once it has
+	until: Option<LocalBoxFuture<'static, ()>>,
+}
+
+// what a subscription woke up for
+enum Wake {
+	Response(Box<Option<Result<SubfieldResponse, SubfieldCodecError>>>),
+	Drained(Box<Option<Result<SubfieldResponse, SubfieldCodecError>>>),
+	Renew,
+	Until,
+}
+
+impl<O: StreamOpener> SubscriberState<O> {
+	async fn next_event(&mut self) -> Option<SubscriberEvent> {
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Some(event);
+			}
+			let mut until = self.until.take()?;
+			if self.active.is_none() {
+				let subscribing = self.subscribe_closest().boxed_local();
+				// a subscription accepted as until fires is still ended below
+				let active = match future::select(subscribing, &mut until).await
+				{
+					FutureEither::Left((active, _)) => active,
+					FutureEither::Right(_) => return None,
+				};
+				self.until = Some(until);
+				self.active = Some(active);
+				self.renewed.clear();
+				if self.subscribed {
+					self.pending.push_back(SubscriberEvent::Gap);
+				}
+				self.subscribed = true;
+				continue;
+			}
+
+			let active = self.active.as_mut()?;
+			let draining = &mut self.draining;
+			let wake = future::poll_fn(|cx| {
+				if until.poll_unpin(cx).is_ready() {
+					return Poll::Ready(Wake::Until);
+				}
+				// the old stream first, its events were sent earlier
+				if let Some(Poll::Ready(response)) =
+					draining.as_mut().map(|stream| stream.poll_next_unpin(cx))
+				{
+					return Poll::Ready(Wake::Drained(Box::new(response)));
+				}
+				if let Poll::Ready(response) = active.stream.poll_next_unpin(cx)
+				{
+					return Poll::Ready(Wake::Response(Box::new(response)));
+				}
+				match active.renew.poll_unpin(cx) {
+					Poll::Ready(_) => Poll::Ready(Wake::Renew),
+					Poll::Pending => Poll::Pending,
+				}
+			})
+			.await;
+			let response = match wake {
+				Wake::Response(response) => *response,
+				Wake::Drained(response) => {
+					self.until = Some(until);
+					match response.map(|response| response.map(Self::to_event))
+					{
+						Some(Ok(Ok(Some(event)))) => {
+							if !self.is_repeat(&event) {
+								return Some(event);
+							}
+						}
+						// the renewal confirmed before the unsubscribe
+						Some(Ok(Ok(None))) => {}
+						// the unsubscribe confirmed, or the stream closed
+						_ => self.draining = None,
+					}
+					continue;
+				}
+				Wake::Renew => {
+					self.until = Some(until);
+					self.renew().await;
+					continue;
+				}
+				Wake::Until => {
+					self.unsubscribe().await;
+					return None;
+				}
+			};
+			self.until = Some(until);
+
+			match response.map(|response| response.map(Self::to_event)) {
+				Some(Ok(Ok(Some(event)))) => {
+					if !self.is_repeat(&event) {
+						return Some(event);
+					}
+				}
+				// the server confirmed a renewal
+				Some(Ok(Ok(None))) => {}
+				// the stream closed or failed, subscribe again
+				_ => self.active = None,
+			}
+		}
+	}
+
+	// the event of a subscription response, or an error if the server ended
+	// the subscription
+	fn to_event(
+		response: SubfieldResponse,
+	) -> Result<Option<SubscriberEvent>, SubfieldError> {
+		match response {
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Record(
+				event,
+			))) => Ok(Some(SubscriberEvent::Record(event))),
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Message(
+				event,
+			))) => Ok(Some(SubscriberEvent::Message(event))),
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed {
+				..
+			})) => Ok(None),
+			SubfieldResponse::Subscribe(Err(_)) => {
+				Err(SubfieldError::RequestFailed)
+			}
+			_ => Err(SubfieldError::UnexpectedResponseType),
+		}
+	}
+
+	// the hash of the record an event carries, the same whichever server
+	// sent it
+	fn event_hash(event: &SubscriberEvent) -> Option<V256> {
+		match event {
+			SubscriberEvent::Record(event)
+			| SubscriberEvent::Message(event) => {
+				Some(crypto::hash(&event.record.record_bytes))
+			}
+			SubscriberEvent::Gap => None,
+		}
+	}
+
+	// whether the event was sent by both servers while the lease was
+	// renewed and already emitted, remembered while the old stream is read
+	fn is_repeat(&mut self, event: &SubscriberEvent) -> bool {
+		let Some(hash) = Self::event_hash(event) else {
+			return false;
+		};
+		if self.renewed.remove(&hash) {
+			return true;
+		}
+		if self.draining.is_some() {
+			self.renewed.insert(hash);
+		}
+		false
+	}
+
+	// subscribe again before the lease runs out, the old stream is kept until
+	// the new subscription is accepted, then unsubscribed and read until its
+	// server confirms. Events sent while both are open arrive on both, and
+	// are only emitted once
+	async fn renew(&mut self) {
+		let Some(mut old) = self.active.take() else {
+			return;
+		};
+		match self.try_subscribe().await {
+			Ok(active) => {
+				self.renewed.clear();
+				if active.peer != old.peer {
+					self.pending.push_back(SubscriberEvent::Gap);
+				}
+				self.active = Some(active);
+				self.draining = match self.unsubscribe_on(&mut old.stream).await
+				{
+					true => Some(old.stream),
+					false => None,
+				};
+			}
+			Err(e) => {
+				tracing::debug!("Failed to renew subscription: {e:?}");
+				old.renew = sleep(self.retry_delay).boxed_local();
+				self.active = Some(old);
+			}
+		}
+	}
+
+	// ask the server serving the subscription to end it, on the stream it
+	// was made on, and close that stream
+	async fn unsubscribe(&mut self) {
+		if let Some(mut active) = self.active.take() {
+			self.unsubscribe_on(&mut active.stream).await;
+		}
+	}
+
+	// send the unsubscribe on a subscription's stream, whether it was sent
+	async fn unsubscribe_on(
+		&self,
+		stream: &mut SubfieldClientStream<O::Stream>,
+	) -> bool {
+		let request = SubfieldRequest::new(
+			self.routing_key.clone(),
+			SubfieldRequestBody::Unsubscribe(UnsubscribeRequest {
+				key: self.request.key.clone(),
+			}),
+		);
+		match send_request(stream, request).await {
+			Ok(()) => true,
+			Err(e) => {
+				tracing::debug!("Failed to unsubscribe: {e:?}");
+				false
+			}
+		}
+	}
+
+	// subscribe through the closest known server, waiting until one accepts
+	async fn subscribe_closest(&self) -> ActiveSubscription<O::Stream> {
+		loop {
+			match self.try_subscribe().await {
+				Ok(active) => return active,
+				Err(e) => {
+					tracing::debug!("Failed to subscribe: {e:?}");
+					sleep(self.retry_delay).await;
+				}
+			}
+		}
+	}
+
+	async fn try_subscribe(
+		&self,
+	) -> Result<ActiveSubscription<O::Stream>, SubfieldError> {
+		let routing_field = self.routing_key.get_routing_field()?;
+		let peer = self
+			.table
+			.closest_known_peer(&routing_field)
+			.ok_or(SubfieldError::NoConnectedPeers)?;
+
+		let mut stream = client_stream(self.opener.open(peer).await?);
+		let request = SubfieldRequest::new(
+			self.routing_key.clone(),
+			SubfieldRequestBody::Subscribe(self.request.clone()),
+		);
+		send_request(&mut stream, request).await?;
+		match recv_response(&mut stream).await? {
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed {
+				lease,
+			})) => {
+				// renew once three quarters of the lease have passed, a
+				// server cannot grant more than the max lease
+				let lease = lease.clamp(1, SUBSCRIPTION_MAX_LEASE);
+				Ok(ActiveSubscription {
+					peer,
+					stream,
+					renew: sleep(Duration::from_millis(lease * 750))
+						.boxed_local(),
+				})
+			}
+			SubfieldResponse::Subscribe(Err(_)) => {
+				Err(SubfieldError::RequestFailed)
+			}
+			_ => Err(SubfieldError::UnexpectedResponseType),
+		}
+	}
+}
@@ -17,8 +17,8 @@ mod misc;
 mod protocol;
 mod dht;
 
-// mod chord;
-// mod store;
+mod chord;
+mod store;
 // mod swarm;
 // #[cfg(feature = "client")]
 // mod client;
@@ -31,8 +31,8 @@ pub mod prelude {
 	pub use crate::misc::*;
 	pub use crate::protocol::*;
 	pub use crate::dht::*;
-	// pub use crate::chord::*;
-	// pub use crate::store::*;
+	pub use crate::chord::*;
+	pub use crate::store::*;
 	// pub use crate::swarm::*;
 	// #[cfg(feature = "client")]
 	// pub use crate::client::*;
@@ -17,3 +17,6 @@ pub use ordered_map::*;
 
 mod randomable;
 pub use randomable::*;
+
+mod timers;
+pub use timers::*;
@@ -0,0 +1,33 @@
+use crate::*;
+use std::time::Duration;
+
+/*
+   Sleep
+*/
+#[cfg(any(feature = "server", test))]
+pub async fn sleep(duration: Duration) {
+	tokio::time::sleep(duration).await
+}
+
+#[cfg(not(any(feature = "server", test)))]
+pub async fn sleep(duration: Duration) {
+	gloo::timers::future::sleep(duration).await
+}
+
+// a sleep that can be held across threads, the browser's timer fires it
+// through a channel
+#[cfg(any(feature = "server", test))]
+pub fn sleep_send(duration: Duration) -> impl Future<Output = ()> + Send {
+	tokio::time::sleep(duration)
+}
+
+#[cfg(not(any(feature = "server", test)))]
+pub fn sleep_send(duration: Duration) -> impl Future<Output = ()> + Send {
+	let (sender, receiver) = futures::channel::oneshot::channel();
+	let millis = duration.as_millis().min(u32::MAX as u128) as u32;
+	gloo::timers::callback::Timeout::new(millis, move || {
+		let _ = sender.send(());
+	})
+	.forget();
+	receiver.map(|_| ())
+}
@@ -53,5 +53,5 @@ pub trait Libp2pPublicKeyable<E>: Sized {
 
 pub trait Libp2pPeerIdable<E>: Sized {
 	fn to_libp2p_peer_id(&self) -> Result<libp2p::PeerId, E>;
-	// fn from_libp2p_peer_id(peer_id: libp2p::PeerId) -> Result<Self, E>;
+	fn from_libp2p_peer_id(peer_id: libp2p::PeerId) -> Result<Self, E>;
 }
@@ -14,6 +14,9 @@ pub enum SubfieldError {
 	NoConnectedPeers,
 	NoLocalPeer,
 	SelfIsClosest,
+	HopLimitExceeded,
+	RoutingLoop,
+	InvalidPeerId,
 	RequestTimeout,
 	RequestFailed,
 	UnexpectedResponseType,
@@ -26,4 +29,5 @@ pub enum SubfieldError {
 	SerializationFailed,
 	DeserializationFailed,
 	EchoFailure,
+	StoreFailed,
 }
@@ -4,6 +4,10 @@ use crate::*;
 pub struct SubfieldRequest {
 	pub routing_key: RoutingKey,
 	pub body: SubfieldRequestBody,
+	// the ids of the servers that have forwarded this request, in order
+	pub hops: Vec<V256>,
+	// sent to a chosen replica, answered by the server that receives it
+	pub direct: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -16,17 +20,51 @@ pub enum SubfieldRequestBody {
 	GetRecord(GetRecordRequest),       // oneshot
 	PutRecord(PutRecordRequest),       // oneshot
 	DeleteRecord(DeleteRecordRequest), // oneshot
+	ListRecords(ListRecordsRequest),   // streaming
 
 	// Pubsub
 	Subscribe(SubscribeRequest),     // streaming
 	Unsubscribe(UnsubscribeRequest), // streaming
+	Publish(PublishRequest),         // oneshot
 }
 
 impl SubfieldRequest {
+	pub fn new(routing_key: RoutingKey, body: SubfieldRequestBody) -> Self {
+		Self {
+			routing_key,
+			body,
+			hops: Vec::new(),
+			direct: false,
+		}
+	}
+
+	// a request between replicas, never forwarded
+	pub fn new_direct(
+		routing_key: RoutingKey,
+		body: SubfieldRequestBody,
+	) -> Self {
+		Self {
+			direct: true,
+			..Self::new(routing_key, body)
+		}
+	}
+
+	// client requests are forwarded to the server closest to their routing key,
+	// system and direct requests are always answered by the server that
+	// receives them
+	pub fn is_routed(&self) -> bool {
+		!self.direct
+			&& !matches!(
+				self.body,
+				SubfieldRequestBody::Ping(_) | SubfieldRequestBody::Echo(_)
+			)
+	}
+
 	pub fn is_streaming(&self) -> bool {
 		matches!(
 			self.body,
-			SubfieldRequestBody::Subscribe(_)
+			SubfieldRequestBody::ListRecords(_)
+				| SubfieldRequestBody::Subscribe(_)
 				| SubfieldRequestBody::Unsubscribe(_)
 		)
 	}
@@ -34,6 +72,45 @@ impl SubfieldRequest {
 	pub fn is_oneshot(&self) -> bool {
 		!self.is_streaming()
 	}
+
+	// the failure response matching this request's type
+	pub fn error_response(&self, error: SubfieldError) -> SubfieldResponse {
+		match self.body {
+			SubfieldRequestBody::Ping(_) => {
+				SubfieldResponse::Ping(Err(PingFailure::ServiceError(error)))
+			}
+			SubfieldRequestBody::Echo(_) => {
+				SubfieldResponse::Echo(Err(EchoFailure::ServiceError(error)))
+			}
+			SubfieldRequestBody::GetRecord(_) => SubfieldResponse::GetRecord(
+				Err(GetRecordFailure::ServiceError(error)),
+			),
+			SubfieldRequestBody::PutRecord(_) => SubfieldResponse::PutRecord(
+				Err(PutRecordFailure::ServiceError(error)),
+			),
+			SubfieldRequestBody::DeleteRecord(_) => {
+				SubfieldResponse::DeleteRecord(Err(
+					DeleteRecordFailure::ServiceError(error),
+				))
+			}
+			SubfieldRequestBody::ListRecords(_) => {
+				SubfieldResponse::ListRecords(Err(
+					ListRecordsFailure::ServiceError(error),
+				))
+			}
+			SubfieldRequestBody::Subscribe(_) => SubfieldResponse::Subscribe(
+				Err(SubscribeFailure::ServiceError(error)),
+			),
+			SubfieldRequestBody::Unsubscribe(_) => {
+				SubfieldResponse::Unsubscribe(Err(
+					UnsubscribeFailure::ServiceError(error),
+				))
+			}
+			SubfieldRequestBody::Publish(_) => SubfieldResponse::Publish(Err(
+				PublishFailure::ServiceError(error),
+			)),
+		}
+	}
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -46,17 +123,21 @@ pub enum SubfieldResponse {
 	GetRecord(GetRecordResponse),       // oneshot
 	PutRecord(PutRecordResponse),       // oneshot
 	DeleteRecord(DeleteRecordResponse), // oneshot
+	ListRecords(ListRecordsResponse),   // streaming
 
 	// Pubsub
 	Subscribe(SubscribeResponse),     // streaming
 	Unsubscribe(UnsubscribeResponse), // streaming
+	Publish(PublishResponse),         // oneshot
 }
 
 impl SubfieldResponse {
 	pub fn is_streaming(&self) -> bool {
 		matches!(
 			self,
-			SubfieldResponse::Subscribe(_) | SubfieldResponse::Unsubscribe(_)
+			SubfieldResponse::ListRecords(_)
+				| SubfieldResponse::Subscribe(_)
+				| SubfieldResponse::Unsubscribe(_)
 		)
 	}
 
@@ -1,19 +1,52 @@
 use crate::*;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+// how concurrent puts of a record are combined is decided by its type
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum RecordType {
+	// the higher version replaces the other
 	Simple = 0,
+	// signed entries, merged into their union
+	Log = 1,
+	// a signed count per writer, merged by keeping the highest of each
+	Counter = 2,
+	// chunk hashes of a large value, the higher version replaces the other
+	Manifest = 3,
+}
+
+impl RecordType {
+	pub fn is_mergeable(&self) -> bool {
+		matches!(self, RecordType::Log | RecordType::Counter)
+	}
+}
+
+// which signatures a record needs to be accepted, part of the signed record
+// so a replica cannot be given a record with a weaker mode
+#[derive(
+	Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default,
+)]
+pub enum SignatureMode {
+	// the signer or the cosigner
+	#[default]
+	Single,
+	// both the signer and the cosigner
+	Dual,
 }
 
+// incremented on every update of a record
+pub type RecordVersion = u64;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Record {
 	pub record_type: RecordType,
 	pub key: CompleteKey,
+	pub signature_mode: SignatureMode,
 
 	pub is_encrypted: bool,
 	hash_seed: VersionedBytes,
 	data: VersionedBytes,
 
+	// replicas keep the highest version of a key
+	pub version: RecordVersion,
 	pub created_at: DateTimeUtc,
 	pub updated_at: DateTimeUtc,
 }
@@ -24,82 +57,692 @@ pub enum RecordError {
 	DeserializationError,
 	KeyIncomplete,
 	KeyMismatch,
-	KeypairNotSigner,
+	KeypairNotSignerOrCosigner,
 	InvalidSignature,
+	// the record's signature mode needs both signatures
+	MissingCosignature,
+	Encrypted,
+	NotEncrypted,
+	DecryptionFailed,
+	CipherError(CipherError),
+	// only a signer and a cosigner signature can be joined
+	SignatureRoleMismatch,
+	RecordTypeMismatch,
+	// only single signed, unencrypted logs and counters are merged
+	NotMergeable,
+	// a counter's counts add up to more than it can hold
+	CountOverflow,
 	GetRecordFailure(GetRecordFailure),
 	CryptoKeyError(CryptoKeyError),
 	SubfieldError(SubfieldError),
 }
 
 impl Record {
+	pub fn builder(key: CompleteKey) -> RecordBuilder {
+		RecordBuilder::new(key)
+	}
+
+	pub fn new(record_type: RecordType, key: CompleteKey, data: &[u8]) -> Self {
+		Record::builder(key)
+			.record_type(record_type)
+			.data(data)
+			.build()
+	}
+
+	// a record only the key's signer and cosigner can read, the keypair is
+	// either one of them
+	pub fn new_encrypted(
+		record_type: RecordType,
+		key: CompleteKey,
+		data: &[u8],
+		keypair: &Keypair,
+	) -> Result<Self, RecordError> {
+		Record::builder(key)
+			.record_type(record_type)
+			.data(data)
+			.build_encrypted(keypair)
+	}
+
+	/*
+		Data
+	*/
+
+	// the stored data, the ciphertext if the record is encrypted
+	pub fn data(&self) -> &[u8] {
+		self.data.data()
+	}
+
+	// the data deserialized into the type it was built with
+	pub fn value<T: DeserializeOwned>(&self) -> Result<T, RecordError> {
+		if self.is_encrypted {
+			return Err(RecordError::Encrypted);
+		}
+		deserialize(self.data()).map_err(|_| RecordError::DeserializationError)
+	}
+
+	// replace the data with a serialized value as the next version
+	pub fn set_value<T: Serialize>(
+		&mut self,
+		value: &T,
+	) -> Result<(), RecordError> {
+		if self.is_encrypted {
+			return Err(RecordError::Encrypted);
+		}
+		let data =
+			serialize(value).map_err(|_| RecordError::SerializationError)?;
+		self.update(&data);
+		Ok(())
+	}
+
+	/*
+		Content addressing
+	*/
+
+	// the seed is hashed in with the data so equal data in different records
+	// does not share a content hash, unless the seed is left as zeros with
+	// RecordBuilder::content_addressed
+	pub fn hash_seed(&self) -> &V256 {
+		&self.hash_seed
+	}
+
+	pub fn content_hash(&self) -> VHash {
+		crypto::hash(&[self.hash_seed.data().as_slice(), self.data()].concat())
+	}
+
+	pub fn verify_content(&self, content_hash: &VHash) -> bool {
+		&self.content_hash() == content_hash
+	}
+
+	// replace the data as the next version of the record
+	pub fn update(&mut self, data: &[u8]) {
+		self.data = VersionedBytes::new(0, data);
+		self.version += 1;
+		self.updated_at = Utc::now();
+	}
+
+	/*
+		Encryption
+	*/
+
+	// the key shared by the signer and cosigner, bound to the record's key
+	fn cipher(
+		key: &CompleteKey,
+		keypair: &Keypair,
+	) -> Result<Cipher, RecordError> {
+		let public_key = keypair.public_key().versioned_bytes();
+		let other = if public_key == &key.signer {
+			&key.cosigner
+		} else if public_key == &key.cosigner {
+			&key.signer
+		} else {
+			return Err(RecordError::KeypairNotSignerOrCosigner);
+		};
+
+		let shared_secret =
+			keypair.shared_secret(&PublicKey::new(other.clone()));
+		let secret = crypto::hash(
+			&[shared_secret.data().as_slice(), key.hash().data()].concat(),
+		);
+		Cipher::new(secret).map_err(RecordError::CipherError)
+	}
+
+	pub fn decrypt(&self, keypair: &Keypair) -> Result<Vec<u8>, RecordError> {
+		if !self.is_encrypted {
+			return Err(RecordError::NotEncrypted);
+		}
+		Self::cipher(&self.key, keypair)?
+			.decrypt(self.data())
+			.map_err(|_| RecordError::DecryptionFailed)
+	}
+
+	pub fn decrypt_value<T: DeserializeOwned>(
+		&self,
+		keypair: &Keypair,
+	) -> Result<T, RecordError> {
+		deserialize(&self.decrypt(keypair)?)
+			.map_err(|_| RecordError::DeserializationError)
+	}
+
+	// replace the encrypted data as the next version of the record
+	pub fn update_encrypted(
+		&mut self,
+		data: &[u8],
+		keypair: &Keypair,
+	) -> Result<(), RecordError> {
+		if !self.is_encrypted {
+			return Err(RecordError::NotEncrypted);
+		}
+		let ciphertext = Self::cipher(&self.key, keypair)?
+			.encrypt(data)
+			.map_err(RecordError::CipherError)?;
+		self.update(&ciphertext);
+		Ok(())
+	}
+
+	/*
+		Signing
+	*/
+
+	// sign as the key's signer or cosigner, whichever the keypair is
+	pub fn sign(
+		&self,
+		keypair: &Keypair,
+	) -> Result<RecordSignature, RecordError> {
+		let record_bytes =
+			serialize(self).map_err(|_| RecordError::SerializationError)?;
+		let public_key = keypair.public_key().versioned_bytes();
+		if public_key == &self.key.signer {
+			Ok(RecordSignature::Signer(keypair.sign(&record_bytes)))
+		} else if public_key == &self.key.cosigner {
+			Ok(RecordSignature::Cosigner(keypair.sign(&record_bytes)))
+		} else {
+			Err(RecordError::KeypairNotSignerOrCosigner)
+		}
+	}
+
+	// a put request for each of the key's routing locations, signed by the
+	// key's signer or cosigner
 	pub fn to_put_record_requests(
 		&self,
 		key: &CompleteKey,
 		keypair: &Keypair,
 	) -> Result<[SubfieldRequest; 3], RecordError> {
-		// The keypair's public key must be equal to the key's signer
-		if keypair.public_key().versioned_bytes() != &key.signer {
-			return Err(RecordError::KeypairNotSigner);
+		if key != &self.key {
+			return Err(RecordError::KeyMismatch);
 		}
+		self.to_signed_put_record_requests(self.sign(keypair)?)
+	}
 
-		let record_bytes = serialize(self).unwrap();
-
-		let signature = keypair.sign(&record_bytes);
-
-		let partial_key = PartialKey::from_complete(key.clone());
+	// put requests carrying a signature made with sign, or two of them joined
+	// with RecordSignature::join for a dual signed record
+	pub fn to_signed_put_record_requests(
+		&self,
+		signature: RecordSignature,
+	) -> Result<[SubfieldRequest; 3], RecordError> {
+		let record_bytes =
+			serialize(self).map_err(|_| RecordError::SerializationError)?;
+		signature.verify(&self.key, &record_bytes)?;
+		self.check_signature_mode(&signature)?;
 
-		let put_record_requests = [
-			SubfieldRequest {
-				routing_key: RoutingKey::Signer(partial_key.clone()),
-				body: SubfieldRequestBody::PutRecord(PutRecordRequest {
-					record_bytes: record_bytes.clone(),
-					signature: signature.clone(),
-				}),
-			},
-			SubfieldRequest {
-				routing_key: RoutingKey::Cosigner(partial_key.clone()),
-				body: SubfieldRequestBody::PutRecord(PutRecordRequest {
+		let request = |routing_key| {
+			SubfieldRequest::new(
+				routing_key,
+				SubfieldRequestBody::PutRecord(PutRecordRequest {
 					record_bytes: record_bytes.clone(),
 					signature: signature.clone(),
 				}),
-			},
-			SubfieldRequest {
-				routing_key: RoutingKey::Tangent(partial_key.clone()),
-				body: SubfieldRequestBody::PutRecord(PutRecordRequest {
+			)
+		};
+		Ok([
+			request(self.key.to_signer_routing_key()),
+			request(self.key.to_cosigner_routing_key()),
+			request(self.key.to_tangent_routing_key()),
+		])
+	}
+
+	// publish requests for each of the key's routing locations, delivered to
+	// the subscribers of the key and never stored. Servers only accept them
+	// for the max age after the record was last updated
+	pub fn to_publish_requests(
+		&self,
+		keypair: &Keypair,
+	) -> Result<[SubfieldRequest; 3], RecordError> {
+		let signature = self.sign(keypair)?;
+		self.check_signature_mode(&signature)?;
+		let record_bytes =
+			serialize(self).map_err(|_| RecordError::SerializationError)?;
+
+		let request = |routing_key| {
+			SubfieldRequest::new(
+				routing_key,
+				SubfieldRequestBody::Publish(PublishRequest {
 					record_bytes: record_bytes.clone(),
 					signature: signature.clone(),
 				}),
-			},
-		];
+			)
+		};
+		Ok([
+			request(self.key.to_signer_routing_key()),
+			request(self.key.to_cosigner_routing_key()),
+			request(self.key.to_tangent_routing_key()),
+		])
+	}
+
+	/*
+		Verification
+	*/
+
+	// the record under the key, if the bytes are signed as its mode requires.
+	// A merged record is refused, only a replica makes one
+	pub fn from_signed_bytes(
+		key: &CompleteKey,
+		record_bytes: &[u8],
+		signature: &RecordSignature,
+	) -> Result<Record, RecordError> {
+		let record: Record = deserialize(record_bytes)
+			.map_err(|_| RecordError::DeserializationError)?;
+
+		// the key it is stored under must be the same as the internal key
+		if key != &record.key {
+			return Err(RecordError::KeyMismatch);
+		}
+
+		signature.verify(key, record_bytes)?;
+		record.check_signature_mode(signature)?;
+		record.verify_entries()?;
+		Ok(record)
+	}
+
+	// the record a replica served for the key, either signed or merged from
+	// signed puts that merge again to exactly its bytes
+	pub fn from_served_bytes(
+		key: &CompleteKey,
+		record_bytes: &[u8],
+		signature: &RecordSignature,
+	) -> Result<Record, RecordError> {
+		let RecordSignature::Merged(puts) = signature else {
+			return Record::from_signed_bytes(key, record_bytes, signature);
+		};
+		// a merge of a single put is that put
+		if puts.len() < 2 {
+			return Err(RecordError::InvalidSignature);
+		}
+
+		let mut merged: Option<Record> = None;
+		for put in puts {
+			let record = Record::from_signed_bytes(
+				key,
+				&put.record_bytes,
+				&put.signature,
+			)?;
+			merged = Some(match merged {
+				Some(merged) => merged.merge(&record)?,
+				None => record,
+			});
+		}
+		let merged = merged.ok_or(RecordError::InvalidSignature)?;
+		let merged_bytes =
+			serialize(&merged).map_err(|_| RecordError::SerializationError)?;
+		match merged_bytes == record_bytes {
+			true => Ok(merged),
+			false => Err(RecordError::InvalidSignature),
+		}
+	}
+
+	fn check_signature_mode(
+		&self,
+		signature: &RecordSignature,
+	) -> Result<(), RecordError> {
+		match (self.signature_mode, signature) {
+			(SignatureMode::Dual, RecordSignature::Dual { .. }) => Ok(()),
+			(SignatureMode::Dual, _) => Err(RecordError::MissingCosignature),
+			(SignatureMode::Single, _) => Ok(()),
+		}
+	}
+
+	/*
+		Merging
+	*/
 
-		Ok(put_record_requests)
+	// whether puts of the record are merged rather than replaced, only
+	// single signed, unencrypted logs and counters can be
+	pub fn is_mergeable(&self) -> bool {
+		self.record_type.is_mergeable()
+			&& self.signature_mode == SignatureMode::Single
+			&& !self.is_encrypted
+	}
+
+	// combine two puts of a log or counter by the rule of their type, in
+	// either order and any number of times with the same result
+	pub fn merge(&self, other: &Record) -> Result<Record, RecordError> {
+		if self.key != other.key {
+			return Err(RecordError::KeyMismatch);
+		}
+		if self.record_type != other.record_type {
+			return Err(RecordError::RecordTypeMismatch);
+		}
+		if !self.is_mergeable() || !other.is_mergeable() {
+			return Err(RecordError::NotMergeable);
+		}
+
+		let data = match self.record_type {
+			RecordType::Log => merge_log(&self.key, self.data(), other.data())?,
+			RecordType::Counter => {
+				merge_counter(&self.key, self.data(), other.data())?
+			}
+			RecordType::Simple | RecordType::Manifest => {
+				return Err(RecordError::NotMergeable)
+			}
+		};
+		Ok(Record {
+			record_type: self.record_type,
+			key: self.key.clone(),
+			signature_mode: SignatureMode::Single,
+			is_encrypted: false,
+			hash_seed: self.hash_seed.clone().min(other.hash_seed.clone()),
+			data: VersionedBytes::new(0, &data),
+			version: self.version.max(other.version),
+			created_at: self.created_at.min(other.created_at),
+			updated_at: self.updated_at.max(other.updated_at),
+		})
+	}
+
+	// delete requests for this version of the record and every one before it,
+	// signed by its signer or cosigner
+	pub fn to_delete_record_requests(
+		&self,
+		keypair: &Keypair,
+	) -> Result<[SubfieldRequest; 3], RecordError> {
+		Deletion::new(self.key.clone(), self.version)
+			.to_delete_record_requests(keypair)
 	}
 
 	pub fn from_get_record_response(
 		get_record_response: GetRecordResponse,
 	) -> Result<Record, RecordError> {
-		let success = get_record_response
-			.map_err(|failure| RecordError::GetRecordFailure(failure))?;
+		let success =
+			get_record_response.map_err(RecordError::GetRecordFailure)?;
 
 		let key = success
 			.routing_key
 			.to_complete_key()
-			.map_err(|e| RecordError::SubfieldError(e))?;
-
-		// verify signature
-		let public_key = PublicKey::new(key.signer);
-		match public_key.verify(&success.record_bytes, &success.signature) {
-			Ok(is_valid) => {
-				if !is_valid {
-					return Err(RecordError::InvalidSignature);
-				}
+			.map_err(RecordError::SubfieldError)?;
+
+		Record::from_served_bytes(
+			&key,
+			&success.record_bytes,
+			&success.signature,
+		)
+	}
+}
+
+/*
+	RecordBuilder
+	Builds the first version of a record, with both timestamps set when it is
+	built. The record type defaults to Simple, the signature mode to Single and
+	the version to 0.
+*/
+#[derive(Debug, Clone)]
+pub struct RecordBuilder {
+	key: CompleteKey,
+	record_type: RecordType,
+	signature_mode: SignatureMode,
+	hash_seed: V256,
+	data: Vec<u8>,
+	version: RecordVersion,
+}
+
+impl RecordBuilder {
+	pub fn new(key: CompleteKey) -> Self {
+		Self {
+			key,
+			record_type: RecordType::Simple,
+			signature_mode: SignatureMode::default(),
+			hash_seed: V256::random256(),
+			data: vec![],
+			version: 0,
+		}
+	}
+
+	pub fn record_type(mut self, record_type: RecordType) -> Self {
+		self.record_type = record_type;
+		self
+	}
+
+	pub fn signature_mode(mut self, signature_mode: SignatureMode) -> Self {
+		self.signature_mode = signature_mode;
+		self
+	}
+
+	pub fn hash_seed(mut self, hash_seed: V256) -> Self {
+		self.hash_seed = hash_seed;
+		self
+	}
+
+	// a zero seed, so anyone holding the data can compute the content hash
+	pub fn content_addressed(self) -> Self {
+		self.hash_seed(V256::zeros_256(0))
+	}
+
+	pub fn data(mut self, data: &[u8]) -> Self {
+		self.data = data.to_vec();
+		self
+	}
+
+	// a deleted key refuses every version up to the deletion's, so it is
+	// created again above that version
+	pub fn version(mut self, version: RecordVersion) -> Self {
+		self.version = version;
+		self
+	}
+
+	// the data as a serialized value, read back with Record::value
+	pub fn value<T: Serialize>(self, value: &T) -> Result<Self, RecordError> {
+		let data =
+			serialize(value).map_err(|_| RecordError::SerializationError)?;
+		Ok(self.data(&data))
+	}
+
+	pub fn build(self) -> Record {
+		let now = Utc::now();
+		Record {
+			record_type: self.record_type,
+			key: self.key,
+			signature_mode: self.signature_mode,
+			is_encrypted: false,
+			hash_seed: self.hash_seed,
+			data: VersionedBytes::new(0, &self.data),
+			version: self.version,
+			created_at: now,
+			updated_at: now,
+		}
+	}
+
+	// encrypt the data for the key's signer and cosigner, the keypair is
+	// either one of them
+	pub fn build_encrypted(
+		mut self,
+		keypair: &Keypair,
+	) -> Result<Record, RecordError> {
+		self.data = Record::cipher(&self.key, keypair)?
+			.encrypt(&self.data)
+			.map_err(RecordError::CipherError)?;
+		let mut record = self.build();
+		record.is_encrypted = true;
+		Ok(record)
+	}
+}
+
+/*
+	SignedRecord
+	A verified record, stored with the exact bytes and signature it was put
+	with so it can be served back unchanged.
+*/
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SignedRecord {
+	pub key: CompleteKey,
+	pub version: RecordVersion,
+	pub record_bytes: Vec<u8>,
+	pub signature: RecordSignature,
+}
+
+impl SignedRecord {
+	pub fn from_put_record_request(
+		routing_key: RoutingKey,
+		request: PutRecordRequest,
+	) -> Result<Self, RecordError> {
+		let (key, record) = request.verify(routing_key)?;
+		Ok(Self {
+			key,
+			version: record.version,
+			record_bytes: request.record_bytes,
+			signature: request.signature,
+		})
+	}
+
+	// a record served by another server, which may be merged
+	pub fn from_get_record_success(
+		success: GetRecordSuccess,
+	) -> Result<Self, RecordError> {
+		let key = success
+			.routing_key
+			.to_complete_key()
+			.map_err(RecordError::SubfieldError)?;
+		let record = Record::from_served_bytes(
+			&key,
+			&success.record_bytes,
+			&success.signature,
+		)?;
+		Ok(Self {
+			key,
+			version: record.version,
+			record_bytes: success.record_bytes,
+			signature: success.signature,
+		})
+	}
+
+	pub fn hash(&self) -> V256 {
+		crypto::hash(&self.record_bytes)
+	}
+
+	// the higher version wins, equal versions are decided by the record hash
+	// so every replica keeps the same record whatever order puts arrive in
+	pub fn cmp_version(&self, other: &SignedRecord) -> std::cmp::Ordering {
+		(self.version, self.hash()).cmp(&(other.version, other.hash()))
+	}
+
+	// the merge of two puts of a log or counter, keeping the signature of
+	// either one if it already holds everything in the other. Otherwise the
+	// signed puts it was merged from are kept with it, leaving out those
+	// another one already holds
+	pub fn merge(&self, other: &SignedRecord) -> Result<Self, RecordError> {
+		let merged = self.record()?.merge(&other.record()?)?;
+		let record_bytes =
+			serialize(&merged).map_err(|_| RecordError::SerializationError)?;
+		for signed in [self, other] {
+			if signed.record_bytes == record_bytes {
+				return Ok(signed.clone());
 			}
-			Err(e) => {
-				return Err(RecordError::InvalidSignature);
+		}
+
+		let mut puts = self.to_put_record_requests();
+		for put in other.to_put_record_requests() {
+			if !puts.iter().any(|p| p.record_bytes == put.record_bytes) {
+				puts.push(put);
 			}
 		}
+		let records = puts
+			.iter()
+			.map(|put| {
+				deserialize::<Record>(&put.record_bytes)
+					.map_err(|_| RecordError::DeserializationError)
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		let mut held = vec![];
+		for (i, record) in records.iter().enumerate() {
+			let mut subsumed = false;
+			for (j, other) in records.iter().enumerate() {
+				if i != j {
+					let merged = serialize(&record.merge(other)?)
+						.map_err(|_| RecordError::SerializationError)?;
+					subsumed |= merged == puts[j].record_bytes;
+				}
+			}
+			held.push(!subsumed);
+		}
+		let puts = puts
+			.into_iter()
+			.zip(held)
+			.filter(|(_, held)| *held)
+			.map(|(put, _)| put)
+			.collect();
 
-		let record = deserialize::<Record>(&success.record_bytes).unwrap();
-		Ok(record)
+		Ok(Self {
+			key: merged.key,
+			version: merged.version,
+			record_bytes,
+			signature: RecordSignature::Merged(puts),
+		})
+	}
+
+	pub fn record(&self) -> Result<Record, RecordError> {
+		deserialize(&self.record_bytes)
+			.map_err(|_| RecordError::DeserializationError)
+	}
+
+	// the put requests that store this record on another server, the signed
+	// puts a merged record was made from, which merge again to it there
+	pub fn to_put_record_requests(&self) -> Vec<PutRecordRequest> {
+		match &self.signature {
+			RecordSignature::Merged(puts) => puts.clone(),
+			signature => vec![PutRecordRequest {
+				record_bytes: self.record_bytes.clone(),
+				signature: signature.clone(),
+			}],
+		}
+	}
+
+	pub fn to_get_record_success(
+		&self,
+		routing_key: RoutingKey,
+	) -> GetRecordSuccess {
+		GetRecordSuccess {
+			routing_key,
+			record_bytes: self.record_bytes.clone(),
+			signature: self.signature.clone(),
+		}
+	}
+}
+
+/*
+	Tombstone
+	A verified deletion, kept in place of the deleted record so a stale
+	replica cannot put it back. Deletions are ordered by version like records,
+	so a put and a deletion of the same key resolve the same way everywhere.
+*/
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tombstone {
+	pub key: CompleteKey,
+	pub version: RecordVersion,
+	pub deleted_at: DateTimeUtc,
+	pub deletion_bytes: Vec<u8>,
+	pub signature: DeleteRecordSignature,
+}
+
+impl Tombstone {
+	pub fn from_delete_record_request(
+		routing_key: RoutingKey,
+		request: DeleteRecordRequest,
+	) -> Result<Self, RecordError> {
+		let (key, deletion) = request.verify(routing_key)?;
+		Ok(Self {
+			key,
+			version: deletion.version,
+			deleted_at: deletion.deleted_at,
+			deletion_bytes: request.deletion_bytes,
+			signature: request.signature,
+		})
+	}
+
+	// the delete request that applies this deletion on another server
+	pub fn to_delete_record_request(&self) -> DeleteRecordRequest {
+		DeleteRecordRequest {
+			deletion_bytes: self.deletion_bytes.clone(),
+			signature: self.signature.clone(),
+		}
+	}
+
+	pub fn hash(&self) -> V256 {
+		crypto::hash(&self.deletion_bytes)
+	}
+
+	// the higher version wins, equal versions are decided by the deletion hash
+	pub fn cmp_version(&self, other: &Tombstone) -> std::cmp::Ordering {
+		(self.version, self.hash()).cmp(&(other.version, other.hash()))
+	}
+
+	// whether the deletion removes the record, any version up to its own
+	pub fn deletes(&self, record: &SignedRecord) -> bool {
+		record.version <= self.version
 	}
 }
@@ -0,0 +1,293 @@
+use crate::*;
+
+/*
+	Record entries
+	Log and counter records hold entries signed on their own, so a replica can
+	merge concurrent puts into a record nobody signed as a whole. The merged
+	record is served with the signed puts it was made from, and verified by
+	merging them again.
+*/
+
+// which part of the key signed an entry
+#[derive(
+	Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum EntryWriter {
+	Signer,
+	Cosigner,
+}
+
+impl EntryWriter {
+	pub fn of(
+		key: &CompleteKey,
+		keypair: &Keypair,
+	) -> Result<Self, RecordError> {
+		let public_key = keypair.public_key().versioned_bytes();
+		if public_key == &key.signer {
+			Ok(EntryWriter::Signer)
+		} else if public_key == &key.cosigner {
+			Ok(EntryWriter::Cosigner)
+		} else {
+			Err(RecordError::KeypairNotSignerOrCosigner)
+		}
+	}
+}
+
+// a value signed together with the key it belongs to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SignedEntry {
+	pub entry_bytes: Vec<u8>,
+	pub writer: EntryWriter,
+	pub signature: Signature,
+}
+
+impl SignedEntry {
+	pub fn new<T: Serialize>(
+		key: &CompleteKey,
+		value: &T,
+		keypair: &Keypair,
+	) -> Result<Self, RecordError> {
+		let writer = EntryWriter::of(key, keypair)?;
+		let entry_bytes = serialize(&(key, value))
+			.map_err(|_| RecordError::SerializationError)?;
+		Ok(Self {
+			signature: keypair.sign(&entry_bytes),
+			entry_bytes,
+			writer,
+		})
+	}
+
+	// the value, if the writer signed it for this key
+	pub fn verify<T: DeserializeOwned>(
+		&self,
+		key: &CompleteKey,
+	) -> Result<T, RecordError> {
+		let public_key = match self.writer {
+			EntryWriter::Signer => &key.signer,
+			EntryWriter::Cosigner => &key.cosigner,
+		};
+		let public_key = crypto::PublicKey::new(public_key.clone());
+		match public_key.verify(&self.entry_bytes, &self.signature) {
+			Ok(true) => {}
+			Ok(false) => return Err(RecordError::InvalidSignature),
+			Err(e) => return Err(RecordError::CryptoKeyError(e)),
+		}
+
+		let (entry_key, value): (CompleteKey, T) =
+			deserialize(&self.entry_bytes)
+				.map_err(|_| RecordError::DeserializationError)?;
+		if &entry_key != key {
+			return Err(RecordError::KeyMismatch);
+		}
+		Ok(value)
+	}
+
+	pub fn hash(&self) -> VHash {
+		crypto::hash(&self.entry_bytes)
+	}
+}
+
+fn encode_entries(entries: &[SignedEntry]) -> Result<Vec<u8>, RecordError> {
+	serialize(entries).map_err(|_| RecordError::SerializationError)
+}
+
+// a record built without data has no entries yet
+fn decode_entries(data: &[u8]) -> Result<Vec<SignedEntry>, RecordError> {
+	if data.is_empty() {
+		return Ok(vec![]);
+	}
+	deserialize(data).map_err(|_| RecordError::DeserializationError)
+}
+
+/*
+	Log
+	An append-only list of entries. Merging keeps the union of both logs,
+	ordered by creation time and then entry hash.
+*/
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+	pub data: Vec<u8>,
+	pub created_at: DateTimeUtc,
+}
+
+// the verified entries of the log in their canonical order
+fn log_entries(
+	key: &CompleteKey,
+	entries: Vec<SignedEntry>,
+) -> Result<Vec<(LogEntry, SignedEntry)>, RecordError> {
+	let mut log = vec![];
+	for signed in entries {
+		log.push((signed.verify::<LogEntry>(key)?, signed));
+	}
+	log.sort_by(|(a, a_signed), (b, b_signed)| {
+		(a.created_at, a_signed.hash()).cmp(&(b.created_at, b_signed.hash()))
+	});
+	log.dedup_by(|(_, a), (_, b)| a.entry_bytes == b.entry_bytes);
+	Ok(log)
+}
+
+pub(crate) fn merge_log(
+	key: &CompleteKey,
+	a: &[u8],
+	b: &[u8],
+) -> Result<Vec<u8>, RecordError> {
+	let mut entries = decode_entries(a)?;
+	entries.extend(decode_entries(b)?);
+	let log = log_entries(key, entries)?;
+	encode_entries(
+		&log.into_iter()
+			.map(|(_, signed)| signed)
+			.collect::<Vec<_>>(),
+	)
+}
+
+/*
+	Counter
+	A grow-only counter with a count per writer, the value is their sum.
+	Merging keeps the highest count of each writer.
+*/
+fn counter_entries(
+	key: &CompleteKey,
+	entries: Vec<SignedEntry>,
+) -> Result<Vec<(u64, SignedEntry)>, RecordError> {
+	let mut counts: Vec<(u64, SignedEntry)> = vec![];
+	for signed in entries {
+		let count = signed.verify::<u64>(key)?;
+		match counts.iter_mut().find(|(_, s)| s.writer == signed.writer) {
+			Some(existing) if existing.0 < count => *existing = (count, signed),
+			Some(_) => {}
+			None => counts.push((count, signed)),
+		}
+	}
+	counts.sort_by_key(|(_, signed)| signed.writer);
+	Ok(counts)
+}
+
+// the sum of the writers' counts, refused if it overflows
+fn counter_total(counts: &[(u64, SignedEntry)]) -> Result<u64, RecordError> {
+	counts.iter().try_fold(0u64, |total, (count, _)| {
+		total.checked_add(*count).ok_or(RecordError::CountOverflow)
+	})
+}
+
+pub(crate) fn merge_counter(
+	key: &CompleteKey,
+	a: &[u8],
+	b: &[u8],
+) -> Result<Vec<u8>, RecordError> {
+	let mut entries = decode_entries(a)?;
+	entries.extend(decode_entries(b)?);
+	let counts = counter_entries(key, entries)?;
+	counter_total(&counts)?;
+	encode_entries(
+		&counts
+			.into_iter()
+			.map(|(_, signed)| signed)
+			.collect::<Vec<_>>(),
+	)
+}
+
+/*
+	Manifest
+	Points to the content-addressed chunks of a value too large for one
+	record. Like simple records the higher version replaces the other.
+*/
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Manifest {
+	pub size: u64,
+	pub chunks: Vec<VHash>,
+}
+
+/*
+	Record accessors
+*/
+impl Record {
+	fn expect_type(&self, record_type: RecordType) -> Result<(), RecordError> {
+		match self.record_type == record_type {
+			true => Ok(()),
+			false => Err(RecordError::RecordTypeMismatch),
+		}
+	}
+
+	// the entries of a log or counter must all be signed for the record's key
+	pub fn verify_entries(&self) -> Result<(), RecordError> {
+		match self.record_type {
+			RecordType::Log => self.log().map(|_| ()),
+			RecordType::Counter => self.count().map(|_| ()),
+			RecordType::Simple | RecordType::Manifest => Ok(()),
+		}
+	}
+
+	/*
+		Log
+	*/
+	pub fn log(&self) -> Result<Vec<LogEntry>, RecordError> {
+		self.expect_type(RecordType::Log)?;
+		let log = log_entries(&self.key, decode_entries(self.data())?)?;
+		Ok(log.into_iter().map(|(entry, _)| entry).collect())
+	}
+
+	// append an entry signed by the keypair as the next version of the log
+	pub fn append(
+		&mut self,
+		data: &[u8],
+		keypair: &Keypair,
+	) -> Result<(), RecordError> {
+		self.expect_type(RecordType::Log)?;
+		let entry = LogEntry {
+			data: data.to_vec(),
+			created_at: Utc::now(),
+		};
+		let mut entries = decode_entries(self.data())?;
+		entries.push(SignedEntry::new(&self.key, &entry, keypair)?);
+
+		let log = log_entries(&self.key, entries)?;
+		let entries: Vec<_> =
+			log.into_iter().map(|(_, signed)| signed).collect();
+		self.update(&encode_entries(&entries)?);
+		Ok(())
+	}
+
+	/*
+		Counter
+	*/
+	pub fn count(&self) -> Result<u64, RecordError> {
+		self.expect_type(RecordType::Counter)?;
+		let counts = counter_entries(&self.key, decode_entries(self.data())?)?;
+		counter_total(&counts)
+	}
+
+	// add to the keypair's own count as the next version of the counter
+	pub fn increment(
+		&mut self,
+		by: u64,
+		keypair: &Keypair,
+	) -> Result<(), RecordError> {
+		self.expect_type(RecordType::Counter)?;
+		let writer = EntryWriter::of(&self.key, keypair)?;
+		let mut counts =
+			counter_entries(&self.key, decode_entries(self.data())?)?;
+		let count = counts
+			.iter()
+			.find(|(_, signed)| signed.writer == writer)
+			.map_or(0, |(count, _)| *count);
+		let count = count.checked_add(by).ok_or(RecordError::CountOverflow)?;
+
+		counts.retain(|(_, signed)| signed.writer != writer);
+		counts.push((count, SignedEntry::new(&self.key, &count, keypair)?));
+		counts.sort_by_key(|(_, signed)| signed.writer);
+		counter_total(&counts)?;
+		let entries: Vec<_> =
+			counts.into_iter().map(|(_, signed)| signed).collect();
+		self.update(&encode_entries(&entries)?);
+		Ok(())
+	}
+
+	/*
+		Manifest
+	*/
+	pub fn manifest(&self) -> Result<Manifest, RecordError> {
+		self.expect_type(RecordType::Manifest)?;
+		self.value()
+	}
+}
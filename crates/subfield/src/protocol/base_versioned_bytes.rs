@@ -110,6 +110,10 @@ impl VersionedBytes {
 		count
 	}
 
+	pub fn xor_distance(&self, other: &Self) -> num_bigint::BigUint {
+		self.bigint() ^ other.bigint()
+	}
+
 	/*
 	Random - workaround for wasm not supporting generics
 	*/
@@ -54,6 +54,15 @@ impl CompleteKey {
 		RoutingKey::Tangent(self.to_partial())
 	}
 
+	// the key's signer, cosigner and tangent routing locations
+	pub fn to_routing_keys(&self) -> [RoutingKey; 3] {
+		[
+			self.to_signer_routing_key(),
+			self.to_cosigner_routing_key(),
+			self.to_tangent_routing_key(),
+		]
+	}
+
 	/*
 	 Hashing
 	*/
@@ -8,7 +8,9 @@ pub type PartialKeyField = Option<CompleteKeyField>;
 
 // this is the base key type
 // all fields are optional, but at least one is expected to be set
-#[derive(Debug, Clone, PartialEq, Eq, Getters, Serialize, Deserialize)]
+#[derive(
+	Debug, Clone, Default, PartialEq, Eq, Getters, Serialize, Deserialize,
+)]
 pub struct PartialKey {
 	pub signer: PartialKeyField,
 	pub cosigner: PartialKeyField,
@@ -39,6 +41,17 @@ impl PartialKey {
 		})
 	}
 
+	// whether every field set in this key is equal in the complete key
+	pub fn matches(&self, key: &CompleteKey) -> bool {
+		[
+			(&self.signer, &key.signer),
+			(&self.cosigner, &key.cosigner),
+			(&self.tangent, &key.tangent),
+		]
+		.iter()
+		.all(|(field, other)| field.as_ref().map_or(true, |f| f == *other))
+	}
+
 	pub fn from_complete(complete: CompleteKey) -> PartialKey {
 		PartialKey {
 			signer: Some(complete.signer),
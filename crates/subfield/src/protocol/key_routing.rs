@@ -24,6 +24,20 @@ impl RoutingKey {
 		}
 	}
 
+	// route a partial key by the first of its signer, cosigner and tangent
+	// that is set
+	pub fn from_partial_key(key: PartialKey) -> Result<Self, SubfieldError> {
+		if key.signer.is_some() {
+			Ok(RoutingKey::Signer(key))
+		} else if key.cosigner.is_some() {
+			Ok(RoutingKey::Cosigner(key))
+		} else if key.tangent.is_some() {
+			Ok(RoutingKey::Tangent(key))
+		} else {
+			Err(SubfieldError::RoutingKeyMissingField)
+		}
+	}
+
 	pub fn is_valid(&self) -> bool {
 		match self {
 			RoutingKey::Signer(key) => key.signer.is_some(),
@@ -41,6 +55,35 @@ impl RoutingKey {
 		}
 	}
 
+	// the same kind of routing key around another key
+	pub fn with_partial_key(&self, key: PartialKey) -> RoutingKey {
+		match self {
+			RoutingKey::Signer(_) => RoutingKey::Signer(key),
+			RoutingKey::Cosigner(_) => RoutingKey::Cosigner(key),
+			RoutingKey::Tangent(_) => RoutingKey::Tangent(key),
+		}
+	}
+
+	// the routing key of every key at the same routing location, with only
+	// the routing field set
+	pub fn to_location(&self) -> Result<RoutingKey, SubfieldError> {
+		let field = Some(self.get_routing_field()?);
+		Ok(match self {
+			RoutingKey::Signer(_) => RoutingKey::Signer(PartialKey {
+				signer: field,
+				..Default::default()
+			}),
+			RoutingKey::Cosigner(_) => RoutingKey::Cosigner(PartialKey {
+				cosigner: field,
+				..Default::default()
+			}),
+			RoutingKey::Tangent(_) => RoutingKey::Tangent(PartialKey {
+				tangent: field,
+				..Default::default()
+			}),
+		})
+	}
+
 	// get the internal key
 	pub fn to_complete_key(&self) -> Result<CompleteKey, SubfieldError> {
 		let partial_key = self.to_partial_key();
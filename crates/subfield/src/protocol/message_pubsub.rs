@@ -3,13 +3,38 @@ use crate::*;
 /*
    Subscribe
 */
+
+// the longest lease in seconds a server grants, subscribers renew before it
+// runs out
+pub const SUBSCRIPTION_MAX_LEASE: u64 = 10 * 60;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SubscribeRequest {
 	pub key: PartialKey,
+	// the seconds the subscription should last
+	pub lease: u64,
 }
 
+// the first response confirms the subscription, every later one is an event
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SubscribeSuccess {}
+pub enum SubscribeSuccess {
+	// the seconds the server granted, at most the lease asked for
+	Subscribed {
+		lease: u64,
+	},
+	// a record was put under a key matching the subscription's key
+	Record(Box<SubscriptionEvent>),
+	// a record was published under a key matching the subscription's key,
+	// it is not stored
+	Message(Box<SubscriptionEvent>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubscriptionEvent {
+	// the key subscribed to
+	pub key: PartialKey,
+	pub record: GetRecordSuccess,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum SubscribeFailure {
@@ -39,3 +64,53 @@ pub enum UnsubscribeFailure {
 }
 
 pub type UnsubscribeResponse = Result<UnsubscribeSuccess, UnsubscribeFailure>;
+
+/*
+   Publish
+*/
+
+// how far in seconds a message's update time may be from a server's clock, an
+// older message is refused so it cannot be replayed
+pub const PUBLISH_MAX_AGE: u64 = 60;
+
+// a signed record delivered to the subscribers of its key and never stored
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublishRequest {
+	pub record_bytes: Vec<u8>,
+	pub signature: RecordSignature,
+}
+
+impl PublishRequest {
+	pub fn verify(
+		&self,
+		routing_key: RoutingKey,
+	) -> Result<(CompleteKey, Record), RecordError> {
+		let key = routing_key
+			.to_complete_key()
+			.map_err(RecordError::SubfieldError)?;
+		let record = Record::from_signed_bytes(
+			&key,
+			&self.record_bytes,
+			&self.signature,
+		)?;
+		Ok((key, record))
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublishSuccess {
+	// the subscriptions the message was delivered to
+	pub delivered: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum PublishFailure {
+	Unknown,
+	Invalid,
+	ServiceError(SubfieldError),
+	RecordError(RecordError),
+	// the message was updated too long before or after the server's clock
+	Expired,
+}
+
+pub type PublishResponse = Result<PublishSuccess, PublishFailure>;
@@ -12,7 +12,7 @@ pub struct GetRecordRequest {
 pub struct GetRecordSuccess {
 	pub routing_key: RoutingKey,
 	pub record_bytes: Vec<u8>,
-	pub signature: crypto::Signature,
+	pub signature: RecordSignature,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,13 +24,58 @@ pub enum GetRecordFailure {
 
 pub type GetRecordResponse = Result<GetRecordSuccess, GetRecordFailure>;
 
+/*
+   ListRecords
+*/
+
+// the most records sent for one ListRecords request
+pub const LIST_RECORDS_MAX_LIMIT: u32 = 256;
+
+// records are listed in the order of their key hashes' base32 strings, not
+// of the hashes' bytes. A cursor is the key hash of the last record listed,
+// the next page starts after it
+pub type ListRecordsCursor = V256;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListRecordsRequest {
+	pub cursor: Option<ListRecordsCursor>,
+	pub limit: u32,
+	// list the tombstones of deleted records instead of the records
+	pub tombstones: bool,
+}
+
+// every matching record or tombstone is sent as its own response, followed by
+// an end carrying the cursor of the next page if there is one
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ListRecordsSuccess {
+	Record(Box<GetRecordSuccess>),
+	Tombstone(Box<ListedTombstone>),
+	End { next: Option<ListRecordsCursor> },
+}
+
+// a deletion held under the routing key, applied again like a delete request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListedTombstone {
+	pub routing_key: RoutingKey,
+	pub request: DeleteRecordRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ListRecordsFailure {
+	Unknown,
+	Invalid,
+	ServiceError(SubfieldError),
+}
+
+pub type ListRecordsResponse = Result<ListRecordsSuccess, ListRecordsFailure>;
+
 /*
    PutRecord
 */
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PutRecordRequest {
 	pub record_bytes: Vec<u8>,
-	pub signature: crypto::Signature,
+	pub signature: RecordSignature,
 }
 
 impl PutRecordRequest {
@@ -40,22 +85,77 @@ impl PutRecordRequest {
 	) -> Result<(CompleteKey, Record), RecordError> {
 		let key = routing_key
 			.to_complete_key()
-			.map_err(|e| RecordError::SubfieldError(e))?;
-		let record: Record = deserialize(&self.record_bytes)
-			.map_err(|e| RecordError::DeserializationError)?;
+			.map_err(RecordError::SubfieldError)?;
+		let record = Record::from_signed_bytes(
+			&key,
+			&self.record_bytes,
+			&self.signature,
+		)?;
+		Ok((key, record))
+	}
+}
 
-		// routing key must be the same as the internal key
-		if key != record.key {
-			return Err(RecordError::KeyMismatch);
+// who signed the record bytes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum RecordSignature {
+	Signer(Signature),
+	Cosigner(Signature),
+	Dual {
+		signer: Signature,
+		cosigner: Signature,
+	},
+	// a log or counter merged by a replica from the signed puts it holds,
+	// verified by merging them again. Only served by a replica, never put or
+	// published
+	Merged(Vec<PutRecordRequest>),
+}
+
+impl RecordSignature {
+	// join the signer's and the cosigner's signatures, in either order
+	pub fn join(self, other: RecordSignature) -> Result<Self, RecordError> {
+		match (self, other) {
+			(
+				RecordSignature::Signer(signer),
+				RecordSignature::Cosigner(cosigner),
+			)
+			| (
+				RecordSignature::Cosigner(cosigner),
+				RecordSignature::Signer(signer),
+			) => Ok(RecordSignature::Dual { signer, cosigner }),
+			_ => Err(RecordError::SignatureRoleMismatch),
 		}
+	}
 
-		// verify the signature
-		let public_key = crypto::PublicKey::new(key.signer.clone());
-		match public_key.verify(&self.record_bytes, &self.signature) {
-			Ok(true) => Ok((key, record)),
-			Ok(false) => Err(RecordError::InvalidSignature),
-			Err(e) => Err(RecordError::CryptoKeyError(e)),
+	// every signature present must be valid for its part of the key
+	pub fn verify(
+		&self,
+		key: &CompleteKey,
+		message: &[u8],
+	) -> Result<(), RecordError> {
+		let signatures = match self {
+			RecordSignature::Signer(signature) => {
+				vec![(&key.signer, signature)]
+			}
+			RecordSignature::Cosigner(signature) => {
+				vec![(&key.cosigner, signature)]
+			}
+			RecordSignature::Dual { signer, cosigner } => {
+				vec![(&key.signer, signer), (&key.cosigner, cosigner)]
+			}
+			RecordSignature::Merged(_) => {
+				return Err(RecordError::InvalidSignature)
+			}
+		};
+
+		for (public_key, signature) in signatures {
+			let public_key = crypto::PublicKey::new(public_key.clone());
+			match public_key.verify(message, signature) {
+				Ok(true) => {}
+				Ok(false) => return Err(RecordError::InvalidSignature),
+				Err(e) => return Err(RecordError::CryptoKeyError(e)),
+			}
 		}
+		Ok(())
 	}
 }
 
@@ -69,6 +169,11 @@ pub enum PutRecordFailure {
 	NoPeersConnected,
 	ServiceError(SubfieldError),
 	RecordError(RecordError),
+	// the key was deleted at the version, the record's or a later one, it can
+	// be created again above it
+	Deleted { version: RecordVersion },
+	// the replica holds a newer version, or the same version winning the tie
+	Stale { version: RecordVersion },
 }
 
 pub type PutRecordResponse = Result<PutRecordSuccess, PutRecordFailure>;
@@ -76,11 +181,98 @@ pub type PutRecordResponse = Result<PutRecordSuccess, PutRecordFailure>;
 /*
    DeleteRecord
 */
+// what the signer or cosigner signs to delete the record under a key, every
+// version up to the deleted one is removed and later versions are kept
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Deletion {
+	pub key: CompleteKey,
+	pub version: RecordVersion,
+	pub deleted_at: DateTimeUtc,
+}
+
+impl Deletion {
+	pub fn new(key: CompleteKey, version: RecordVersion) -> Self {
+		Self {
+			key,
+			version,
+			deleted_at: Utc::now(),
+		}
+	}
+
+	// a delete request for each of the key's routing locations, signed by the
+	// key's signer or cosigner
+	pub fn to_delete_record_requests(
+		&self,
+		keypair: &Keypair,
+	) -> Result<[SubfieldRequest; 3], RecordError> {
+		let deletion_bytes =
+			serialize(self).map_err(|_| RecordError::SerializationError)?;
+		let public_key = keypair.public_key().versioned_bytes();
+		let signature = if public_key == &self.key.signer {
+			DeleteRecordSignature::Signer(keypair.sign(&deletion_bytes))
+		} else if public_key == &self.key.cosigner {
+			DeleteRecordSignature::Cosigner(keypair.sign(&deletion_bytes))
+		} else {
+			return Err(RecordError::KeypairNotSignerOrCosigner);
+		};
+
+		let request = |routing_key| {
+			SubfieldRequest::new(
+				routing_key,
+				SubfieldRequestBody::DeleteRecord(DeleteRecordRequest {
+					deletion_bytes: deletion_bytes.clone(),
+					signature: signature.clone(),
+				}),
+			)
+		};
+		Ok([
+			request(self.key.to_signer_routing_key()),
+			request(self.key.to_cosigner_routing_key()),
+			request(self.key.to_tangent_routing_key()),
+		])
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DeleteRecordRequest {
+	pub deletion_bytes: Vec<u8>,
 	pub signature: DeleteRecordSignature,
 }
 
+impl DeleteRecordRequest {
+	pub fn verify(
+		&self,
+		routing_key: RoutingKey,
+	) -> Result<(CompleteKey, Deletion), RecordError> {
+		let key = routing_key
+			.to_complete_key()
+			.map_err(RecordError::SubfieldError)?;
+		let deletion: Deletion = deserialize(&self.deletion_bytes)
+			.map_err(|_| RecordError::DeserializationError)?;
+
+		// routing key must be the same as the deleted key
+		if key != deletion.key {
+			return Err(RecordError::KeyMismatch);
+		}
+
+		// either the signer or the cosigner may delete
+		let (public_key, signature) = match &self.signature {
+			DeleteRecordSignature::Signer(signature) => {
+				(&key.signer, signature)
+			}
+			DeleteRecordSignature::Cosigner(signature) => {
+				(&key.cosigner, signature)
+			}
+		};
+		let public_key = crypto::PublicKey::new(public_key.clone());
+		match public_key.verify(&self.deletion_bytes, signature) {
+			Ok(true) => Ok((key, deletion)),
+			Ok(false) => Err(RecordError::InvalidSignature),
+			Err(e) => Err(RecordError::CryptoKeyError(e)),
+		}
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum DeleteRecordSignature {
 	Signer(Signature),
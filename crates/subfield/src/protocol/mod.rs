@@ -5,6 +5,8 @@ mod base_error;
 pub use base_error::*;
 mod base_record;
 pub use base_record::*;
+mod base_record_types;
+pub use base_record_types::*;
 mod base_message;
 pub use base_message::*;
 
@@ -18,6 +20,11 @@ pub use message_system::*;
 
 // traits
 mod trait_system;
+pub use trait_system::*;
+mod trait_record;
+pub use trait_record::*;
+mod trait_pubsub;
+pub use trait_pubsub::*;
 
 // keys
 mod key_common;
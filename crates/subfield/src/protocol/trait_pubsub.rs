@@ -0,0 +1,39 @@
+use crate::*;
+use futures::stream::BoxStream;
+
+// the events of a subscription, sent to the subscriber until the stream ends
+pub type SubscriptionStream = BoxStream<'static, SubscribeResponse>;
+
+// an accepted subscription, its events end by the time the lease runs out
+pub struct Subscription {
+	pub lease: u64,
+	pub events: SubscriptionStream,
+}
+
+/*
+	Serves the pubsub requests (Subscribe, Unsubscribe, Publish) for the
+	dispatcher. A subscription stays open until its stream ends. The dispatcher
+	ends an unsubscribed subscription by dropping the stream returned by
+	subscribe, before calling unsubscribe to release what is held for it.
+*/
+#[async_trait]
+pub trait PubsubHandler: Send + Sync {
+	async fn subscribe(
+		&self,
+		peer: PeerId,
+		request: SubscribeRequest,
+	) -> Result<Subscription, SubscribeFailure>;
+
+	async fn unsubscribe(
+		&self,
+		peer: PeerId,
+		request: UnsubscribeRequest,
+	) -> UnsubscribeResponse;
+
+	async fn publish(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: PublishRequest,
+	) -> PublishResponse;
+}
@@ -0,0 +1,40 @@
+use crate::*;
+use futures::stream::BoxStream;
+
+// the responses to a ListRecords request, the last one ends the listing
+pub type ListRecordsStream = BoxStream<'static, ListRecordsResponse>;
+
+/*
+	Serves the record requests (GetRecord, PutRecord, DeleteRecord, ListRecords)
+	for the dispatcher. The routing key is the one the request was sent with.
+*/
+#[async_trait]
+pub trait RecordHandler: Send + Sync {
+	async fn get_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: GetRecordRequest,
+	) -> GetRecordResponse;
+
+	async fn put_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: PutRecordRequest,
+	) -> PutRecordResponse;
+
+	async fn delete_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: DeleteRecordRequest,
+	) -> DeleteRecordResponse;
+
+	async fn list_records(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: ListRecordsRequest,
+	) -> Result<ListRecordsStream, ListRecordsFailure>;
+}
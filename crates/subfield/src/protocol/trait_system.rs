@@ -1,3 +1,29 @@
+use crate::*;
+
+/*
+	Serves the system requests (Ping, Echo) for the dispatcher.
+	The default implementations are sufficient for most servers.
+*/
+#[async_trait]
+pub trait SystemHandler: Send + Sync {
+	async fn ping(&self, peer: PeerId, request: PingRequest) -> PingResponse {
+		Ok(PingSuccess {
+			timestamp: Utc::now(),
+		})
+	}
+
+	async fn echo(&self, peer: PeerId, request: EchoRequest) -> EchoResponse {
+		Ok(EchoSuccess {
+			message: request.message,
+		})
+	}
+}
+
+// the default system handler
+pub struct DefaultSystemHandler;
+
+impl SystemHandler for DefaultSystemHandler {}
+
 /*
 	The system trait is used for internal swarm behaviour.
 */
@@ -0,0 +1,258 @@
+use crate::*;
+use futures::stream::BoxStream;
+
+// the default size of a chunk, well under the codec's frame limit
+pub const BLOB_CHUNK_SIZE: usize = MB as usize;
+
+// the chunks of a blob in order, each one verified against its hash
+pub type BlobStream = BoxStream<'static, Result<Vec<u8>, BlobError>>;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum BlobError {
+	RecordError(RecordError),
+	GetRecordFailure(GetRecordFailure),
+	PutRecordFailure(PutRecordFailure),
+	// a chunk did not hash to the hash it is stored under
+	ChunkHashMismatch,
+	// the chunks did not add up to the size in the manifest
+	SizeMismatch,
+}
+
+/*
+	BlobStore
+	Stores values too large for one record. The value is split into chunks,
+	each put as a record under the blob's signer and cosigner with the chunk's
+	hash as its tangent, then a signed manifest record listing the chunk
+	hashes is put under the blob's own key.
+*/
+#[derive(Clone)]
+pub struct BlobStore {
+	records: Arc<dyn RecordHandler>,
+	// the peer the record requests are made as
+	peer: PeerId,
+	chunk_size: usize,
+}
+
+impl BlobStore {
+	pub fn new(records: Arc<dyn RecordHandler>, peer: PeerId) -> Self {
+		Self {
+			records,
+			peer,
+			chunk_size: BLOB_CHUNK_SIZE,
+		}
+	}
+
+	pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+		self.chunk_size = chunk_size.max(1);
+		self
+	}
+
+	// the key a chunk is stored under
+	pub fn chunk_key(key: &CompleteKey, chunk_hash: &VHash) -> CompleteKey {
+		CompleteKey {
+			signer: key.signer.clone(),
+			cosigner: key.cosigner.clone(),
+			tangent: chunk_hash.clone(),
+		}
+	}
+
+	/*
+		Put
+	*/
+
+	// put the data read from the stream under the key, signed by its signer or
+	// cosigner, replacing any blob already there or one deleted
+	pub async fn put_blob<S: futures::Stream<Item = Vec<u8>> + Unpin + Send>(
+		&self,
+		key: &CompleteKey,
+		keypair: &Keypair,
+		mut data: S,
+	) -> Result<Manifest, BlobError> {
+		// a new manifest, or the next version of the one already there. Only a
+		// manifest is replaced
+		let version = match self.get(key).await {
+			Ok(record) if record.record_type != RecordType::Manifest => {
+				return Err(RecordError::RecordTypeMismatch.into())
+			}
+			Ok(record) => record.version + 1,
+			Err(BlobError::GetRecordFailure(GetRecordFailure::Unknown)) => 0,
+			Err(e) => return Err(e),
+		};
+
+		let mut manifest = Manifest {
+			size: 0,
+			chunks: vec![],
+		};
+		let mut buffer = vec![];
+		while let Some(bytes) = data.next().await {
+			buffer.extend(bytes);
+			// the full chunks are put in place, then dropped together
+			let mut offset = 0;
+			while buffer.len() - offset >= self.chunk_size {
+				let chunk = &buffer[offset..offset + self.chunk_size];
+				self.put_chunk(key, keypair, &mut manifest, chunk).await?;
+				offset += self.chunk_size;
+			}
+			buffer.drain(..offset);
+		}
+		if !buffer.is_empty() {
+			self.put_chunk(key, keypair, &mut manifest, &buffer).await?;
+		}
+
+		self.put_versioned(key, keypair, version, |version| {
+			Ok(Record::builder(key.clone())
+				.record_type(RecordType::Manifest)
+				.value(&manifest)?
+				.version(version)
+				.build())
+		})
+		.await?;
+		Ok(manifest)
+	}
+
+	async fn put_chunk(
+		&self,
+		key: &CompleteKey,
+		keypair: &Keypair,
+		manifest: &mut Manifest,
+		chunk: &[u8],
+	) -> Result<(), BlobError> {
+		let chunk_hash = crypto::hash(chunk);
+		let chunk_key = Self::chunk_key(key, &chunk_hash);
+		let build = |version| {
+			Ok(Record::builder(chunk_key.clone())
+				.content_addressed()
+				.data(chunk)
+				.version(version)
+				.build())
+		};
+		match self.put_versioned(&chunk_key, keypair, 0, build).await {
+			// the chunk is already stored, as every record under its key holds
+			// the same data
+			Ok(())
+			| Err(BlobError::PutRecordFailure(PutRecordFailure::Stale {
+				..
+			})) => {}
+			Err(e) => return Err(e),
+		}
+		manifest.size += chunk.len() as u64;
+		manifest.chunks.push(chunk_hash);
+		Ok(())
+	}
+
+	// put the record built at the version, built again above the deletion if
+	// the key was deleted at or after it
+	async fn put_versioned(
+		&self,
+		key: &CompleteKey,
+		keypair: &Keypair,
+		version: RecordVersion,
+		build: impl Fn(RecordVersion) -> Result<Record, RecordError>,
+	) -> Result<(), BlobError> {
+		match self.put(key, keypair, &build(version)?).await {
+			Err(BlobError::PutRecordFailure(PutRecordFailure::Deleted {
+				version,
+			})) => self.put(key, keypair, &build(version + 1)?).await,
+			result => result,
+		}
+	}
+
+	// put the record at each of the key's routing locations
+	async fn put(
+		&self,
+		key: &CompleteKey,
+		keypair: &Keypair,
+		record: &Record,
+	) -> Result<(), BlobError> {
+		for request in record.to_put_record_requests(key, keypair)? {
+			let SubfieldRequestBody::PutRecord(put) = request.body else {
+				unreachable!()
+			};
+			self.records
+				.put_record(self.peer, request.routing_key, put)
+				.await
+				.map_err(BlobError::PutRecordFailure)?;
+		}
+		Ok(())
+	}
+
+	/*
+		Get
+	*/
+
+	pub async fn get_manifest(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Manifest, BlobError> {
+		Ok(self.get(key).await?.manifest()?)
+	}
+
+	// the blob's chunks, fetched one at a time as the stream is read
+	pub async fn get_blob(
+		&self,
+		key: &CompleteKey,
+	) -> Result<BlobStream, BlobError> {
+		let manifest = self.get_manifest(key).await?;
+		let state = (
+			self.clone(),
+			key.clone(),
+			manifest.chunks.into_iter(),
+			manifest.size,
+		);
+
+		let chunks = stream::unfold(Some(state), |state| async move {
+			let (blobs, key, mut chunks, remaining) = state?;
+			match chunks.next() {
+				Some(chunk_hash) => match blobs
+					.get_chunk(&key, chunk_hash)
+					.await
+				{
+					Ok(chunk) if chunk.len() as u64 <= remaining => {
+						let remaining = remaining - chunk.len() as u64;
+						Some((Ok(chunk), Some((blobs, key, chunks, remaining))))
+					}
+					Ok(_) => Some((Err(BlobError::SizeMismatch), None)),
+					Err(e) => Some((Err(e), None)),
+				},
+				None if remaining != 0 => {
+					Some((Err(BlobError::SizeMismatch), None))
+				}
+				None => None,
+			}
+		});
+		Ok(chunks.boxed())
+	}
+
+	async fn get_chunk(
+		&self,
+		key: &CompleteKey,
+		chunk_hash: VHash,
+	) -> Result<Vec<u8>, BlobError> {
+		let record = self.get(&Self::chunk_key(key, &chunk_hash)).await?;
+		match crypto::hash_verify(record.data(), chunk_hash) {
+			true => Ok(record.data().to_vec()),
+			false => Err(BlobError::ChunkHashMismatch),
+		}
+	}
+
+	// the verified record under the key
+	async fn get(&self, key: &CompleteKey) -> Result<Record, BlobError> {
+		let routing_key = key.to_tangent_routing_key();
+		let response = self
+			.records
+			.get_record(
+				self.peer,
+				routing_key.clone(),
+				GetRecordRequest { routing_key },
+			)
+			.await
+			.map_err(BlobError::GetRecordFailure)?;
+		Ok(Record::from_get_record_response(Ok(response))?)
+	}
+}
+
+impl From<RecordError> for BlobError {
+	fn from(e: RecordError) -> Self {
+		BlobError::RecordError(e)
+	}
+}
@@ -1,5 +1,30 @@
+#[cfg(feature = "gluesql")]
 mod store;
+#[cfg(feature = "gluesql")]
 pub use store::*;
 
 mod config;
 pub use config::*;
+
+mod record_store;
+pub use record_store::*;
+
+#[cfg(all(feature = "gluesql", not(target_arch = "wasm32")))]
+mod record_store_gluesql;
+#[cfg(all(feature = "gluesql", not(target_arch = "wasm32")))]
+pub use record_store_gluesql::*;
+
+mod record_handler;
+pub use record_handler::*;
+
+mod subscriptions;
+pub use subscriptions::*;
+
+mod blob_store;
+pub use blob_store::*;
+
+mod record_reader;
+pub use record_reader::*;
+
+mod replicator;
+pub use replicator::*;
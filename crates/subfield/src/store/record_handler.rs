@@ -0,0 +1,235 @@
+use crate::*;
+use std::cmp::Ordering;
+
+/*
+	StoreRecordHandler
+	Serves the record requests out of a RecordStore, verifying every record
+	and deletion before it touches the store. Only a newer version replaces a
+	stored record, logs and counters are merged with it, and deletions leave
+	tombstones that refuse any version of a record up to theirs. Every record
+	stored is published to the subscribers of its key.
+*/
+#[derive(Clone)]
+pub struct StoreRecordHandler {
+	store: Arc<dyn RecordStore>,
+	subscriptions: SubscriptionTable,
+}
+
+impl StoreRecordHandler {
+	pub fn new(store: Arc<dyn RecordStore>) -> Self {
+		Self {
+			store,
+			subscriptions: SubscriptionTable::new(),
+		}
+	}
+
+	pub fn store(&self) -> &Arc<dyn RecordStore> {
+		&self.store
+	}
+
+	// the pubsub handler every stored record is published to
+	pub fn subscriptions(&self) -> &SubscriptionTable {
+		&self.subscriptions
+	}
+
+	/*
+		Tombstones
+	*/
+
+	// the version deleted, if a deletion of the record's version or a later
+	// one has been seen
+	fn deleted_version(
+		&self,
+		record: &SignedRecord,
+	) -> Result<Option<RecordVersion>, RecordStoreError> {
+		let tombstone = self.store.get_tombstone(&record.key)?;
+		Ok(tombstone
+			.filter(|tombstone| tombstone.deletes(record))
+			.map(|tombstone| tombstone.version))
+	}
+
+	// remove the record if the deletion is newer, and keep the newest tombstone
+	fn apply_deletion(
+		&self,
+		tombstone: Tombstone,
+	) -> Result<(), RecordStoreError> {
+		if let Some(record) = self.store.get(&tombstone.key)? {
+			if tombstone.deletes(&record) {
+				self.store.delete(&tombstone.key)?;
+			}
+		}
+
+		let newer = match self.store.get_tombstone(&tombstone.key)? {
+			Some(existing) => existing.cmp_version(&tombstone).is_lt(),
+			None => true,
+		};
+		if newer {
+			self.store.put_tombstone(tombstone)?;
+		}
+		Ok(())
+	}
+
+	fn open(record: &SignedRecord) -> Result<Record, RecordStoreError> {
+		record
+			.record()
+			.map_err(|_| RecordStoreError::DeserializationFailed)
+	}
+}
+
+#[async_trait]
+impl RecordHandler for StoreRecordHandler {
+	async fn get_record(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		_request: GetRecordRequest,
+	) -> GetRecordResponse {
+		let key = routing_key
+			.to_complete_key()
+			.map_err(|_| GetRecordFailure::Invalid)?;
+
+		match self.store.get(&key) {
+			Ok(Some(record)) => Ok(record.to_get_record_success(routing_key)),
+			Ok(None) => Err(GetRecordFailure::Unknown),
+			Err(_) => {
+				Err(GetRecordFailure::ServiceError(SubfieldError::StoreFailed))
+			}
+		}
+	}
+
+	async fn put_record(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: PutRecordRequest,
+	) -> PutRecordResponse {
+		let record =
+			SignedRecord::from_put_record_request(routing_key, request)
+				.map_err(PutRecordFailure::RecordError)?;
+
+		let store_failed =
+			|_| PutRecordFailure::ServiceError(SubfieldError::StoreFailed);
+		if let Some(version) =
+			self.deleted_version(&record).map_err(store_failed)?
+		{
+			return Err(PutRecordFailure::Deleted { version });
+		}
+		if let Some(existing) =
+			self.store.get(&record.key).map_err(store_failed)?
+		{
+			// logs and counters are merged instead of replaced, unless one
+			// of them is dual signed or encrypted
+			let mergeable =
+				|signed| Self::open(signed).map(|record| record.is_mergeable());
+			if mergeable(&record).map_err(store_failed)?
+				&& mergeable(&existing).map_err(store_failed)?
+			{
+				let merged = existing
+					.merge(&record)
+					.map_err(PutRecordFailure::RecordError)?;
+				if merged.record_bytes != existing.record_bytes {
+					self.store.put(merged.clone()).map_err(store_failed)?;
+					self.subscriptions.publish_record(&merged);
+				}
+				return Ok(PutRecordSuccess {});
+			}
+
+			match record.cmp_version(&existing) {
+				Ordering::Less => {
+					return Err(PutRecordFailure::Stale {
+						version: existing.version,
+					})
+				}
+				// the record is already stored
+				Ordering::Equal => return Ok(PutRecordSuccess {}),
+				Ordering::Greater => {}
+			}
+		}
+		self.store.put(record.clone()).map_err(store_failed)?;
+		self.subscriptions.publish_record(&record);
+		Ok(PutRecordSuccess {})
+	}
+
+	async fn delete_record(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: DeleteRecordRequest,
+	) -> DeleteRecordResponse {
+		let tombstone =
+			Tombstone::from_delete_record_request(routing_key, request)
+				.map_err(|_| DeleteRecordFailure::Invalid)?;
+
+		// the tombstone is kept even if the record has not arrived yet
+		self.apply_deletion(tombstone).map_err(|_| {
+			DeleteRecordFailure::ServiceError(SubfieldError::StoreFailed)
+		})?;
+		Ok(DeleteRecordSuccess {})
+	}
+
+	async fn list_records(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: ListRecordsRequest,
+	) -> Result<ListRecordsStream, ListRecordsFailure> {
+		if !routing_key.is_valid()
+			|| request.limit == 0
+			|| request.limit > LIST_RECORDS_MAX_LIMIT
+		{
+			return Err(ListRecordsFailure::Invalid);
+		}
+
+		// one more than the limit, to know whether there is a next page
+		let limit = request.limit as usize;
+		let key = routing_key.to_partial_key();
+		let cursor = request.cursor.as_ref();
+		let store_failed =
+			|_| ListRecordsFailure::ServiceError(SubfieldError::StoreFailed);
+		let mut listed = match request.tombstones {
+			false => self
+				.store
+				.list(&key, cursor, limit + 1)
+				.map_err(store_failed)?
+				.into_iter()
+				.map(|record| {
+					let key =
+						routing_key.with_partial_key(record.key.to_partial());
+					let success = record.to_get_record_success(key);
+					(record.key, ListRecordsSuccess::Record(Box::new(success)))
+				})
+				.collect::<Vec<_>>(),
+			true => self
+				.store
+				.list_tombstones(&key, cursor, limit + 1)
+				.map_err(store_failed)?
+				.into_iter()
+				.map(|tombstone| {
+					let listed = ListedTombstone {
+						routing_key: routing_key
+							.with_partial_key(tombstone.key.to_partial()),
+						request: tombstone.to_delete_record_request(),
+					};
+					let success =
+						ListRecordsSuccess::Tombstone(Box::new(listed));
+					(tombstone.key, success)
+				})
+				.collect(),
+		};
+
+		let next = match listed.len() > limit {
+			true => {
+				listed.truncate(limit);
+				listed.last().map(|(key, _)| key.hash())
+			}
+			false => None,
+		};
+
+		let responses = listed
+			.into_iter()
+			.map(|(_, success)| Ok(success))
+			.chain(std::iter::once(Ok(ListRecordsSuccess::End { next })))
+			.collect::<Vec<_>>();
+		Ok(stream::iter(responses).boxed())
+	}
+}
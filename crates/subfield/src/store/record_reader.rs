@@ -0,0 +1,243 @@
+use crate::*;
+use futures::stream::FuturesUnordered;
+
+// how many routing locations must return a valid record before a read is
+// answered
+#[derive(
+	Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default,
+)]
+pub enum ReadConsistency {
+	// the first record that verifies
+	FirstValid,
+	// the newer of the first two records that verify
+	#[default]
+	Quorum,
+}
+
+impl ReadConsistency {
+	pub fn required(&self) -> usize {
+		match self {
+			ReadConsistency::FirstValid => 1,
+			ReadConsistency::Quorum => 2,
+		}
+	}
+}
+
+// what one routing location returned for a read
+#[derive(Debug, Clone)]
+pub enum LocationRead {
+	// the version the read was answered with
+	Newest,
+	// an older version, or the same version losing the tie
+	Stale { version: RecordVersion },
+	// the location holds no record under the key
+	Unknown,
+	// the record returned did not verify
+	Invalid(RecordError),
+	Failed(GetRecordFailure),
+	// not waited for, enough locations had already answered
+	Unread,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ReadRecordError {
+	// no location holds the record
+	Unknown,
+	// fewer locations returned a valid record than the consistency requires
+	NoQuorum { valid: usize },
+	RecordError(RecordError),
+}
+
+// the newest record read and what each routing location returned
+#[derive(Debug, Clone)]
+pub struct RecordRead {
+	pub record: Record,
+	pub signed: SignedRecord,
+	pub locations: Vec<(RoutingKey, LocationRead)>,
+	// the locations the newest record was put back to
+	pub repaired: usize,
+}
+
+impl RecordRead {
+	// whether any location that answered returned something other than the
+	// newest record
+	pub fn disagrees(&self) -> bool {
+		self.locations.iter().any(|(_, read)| {
+			!matches!(read, LocationRead::Newest | LocationRead::Unread)
+		})
+	}
+}
+
+/*
+	RecordReader
+	Reads a record from the signer, cosigner and tangent routing locations at
+	once, verifying every response and answering with the newest version once
+	the consistency is met. With read repair it waits for every location and
+	puts the newest record back to the ones that returned an older version,
+	none, or an invalid one.
+*/
+#[derive(Clone)]
+pub struct RecordReader {
+	records: Arc<dyn RecordHandler>,
+	// the peer the record requests are made as
+	peer: PeerId,
+	consistency: ReadConsistency,
+	read_repair: bool,
+}
+
+impl RecordReader {
+	pub fn new(records: Arc<dyn RecordHandler>, peer: PeerId) -> Self {
+		Self {
+			records,
+			peer,
+			consistency: ReadConsistency::default(),
+			read_repair: false,
+		}
+	}
+
+	pub fn with_consistency(mut self, consistency: ReadConsistency) -> Self {
+		self.consistency = consistency;
+		self
+	}
+
+	pub fn with_read_repair(mut self, read_repair: bool) -> Self {
+		self.read_repair = read_repair;
+		self
+	}
+
+	pub async fn read(
+		&self,
+		key: &CompleteKey,
+	) -> Result<RecordRead, ReadRecordError> {
+		let routing_keys = key.to_routing_keys();
+		let mut reads: Vec<Option<Result<SignedRecord, LocationRead>>> =
+			vec![None; routing_keys.len()];
+
+		let mut responses = routing_keys
+			.iter()
+			.enumerate()
+			.map(|(i, routing_key)| async move {
+				let request = GetRecordRequest {
+					routing_key: routing_key.clone(),
+				};
+				let response = self
+					.records
+					.get_record(self.peer, routing_key.clone(), request)
+					.await;
+				(i, response)
+			})
+			.collect::<FuturesUnordered<_>>();
+
+		let mut valid = 0;
+		while let Some((i, response)) = responses.next().await {
+			let read = Self::verify(key, response);
+			valid += read.is_ok() as usize;
+			reads[i] = Some(read);
+			if valid >= self.consistency.required() && !self.read_repair {
+				break;
+			}
+		}
+		drop(responses);
+
+		let newest = reads
+			.iter()
+			.flatten()
+			.flatten()
+			.max_by(|a, b| a.cmp_version(b))
+			.cloned();
+		let Some(newest) = newest else {
+			let unknown = reads
+				.iter()
+				.all(|read| matches!(read, Some(Err(LocationRead::Unknown))));
+			return Err(match unknown {
+				true => ReadRecordError::Unknown,
+				false => ReadRecordError::NoQuorum { valid },
+			});
+		};
+		if valid < self.consistency.required() {
+			return Err(ReadRecordError::NoQuorum { valid });
+		}
+		let record = newest.record().map_err(ReadRecordError::RecordError)?;
+
+		let mut locations = vec![];
+		for (routing_key, read) in routing_keys.into_iter().zip(reads) {
+			let read = match read {
+				None => LocationRead::Unread,
+				Some(Ok(signed)) if signed.hash() == newest.hash() => {
+					LocationRead::Newest
+				}
+				Some(Ok(signed)) => LocationRead::Stale {
+					version: signed.version,
+				},
+				Some(Err(read)) => read,
+			};
+			locations.push((routing_key, read));
+		}
+		let repaired = match self.read_repair {
+			true => self.repair(&newest, &locations).await,
+			false => 0,
+		};
+
+		Ok(RecordRead {
+			record,
+			signed: newest,
+			locations,
+			repaired,
+		})
+	}
+
+	// the record returned for the key, if it verifies
+	fn verify(
+		key: &CompleteKey,
+		response: GetRecordResponse,
+	) -> Result<SignedRecord, LocationRead> {
+		let success = match response {
+			Ok(success) => success,
+			Err(GetRecordFailure::Unknown) => {
+				return Err(LocationRead::Unknown)
+			}
+			Err(e) => return Err(LocationRead::Failed(e)),
+		};
+		let signed = SignedRecord::from_get_record_success(success)
+			.map_err(LocationRead::Invalid)?;
+		match &signed.key == key {
+			true => Ok(signed),
+			false => Err(LocationRead::Invalid(RecordError::KeyMismatch)),
+		}
+	}
+
+	// put the newest record to the locations that returned something older,
+	// returning how many accepted it
+	async fn repair(
+		&self,
+		newest: &SignedRecord,
+		locations: &[(RoutingKey, LocationRead)],
+	) -> usize {
+		let mut repaired = 0;
+		for (routing_key, read) in locations {
+			if !matches!(
+				read,
+				LocationRead::Stale { .. }
+					| LocationRead::Unknown
+					| LocationRead::Invalid(_)
+			) {
+				continue;
+			}
+			let mut accepted = true;
+			for put in newest.to_put_record_requests() {
+				let put = self
+					.records
+					.put_record(self.peer, routing_key.clone(), put)
+					.await;
+				if let Err(e) = put {
+					tracing::debug!("Failed to repair a read: {e:?}");
+					accepted = false;
+				}
+			}
+			if accepted {
+				repaired += 1;
+			}
+		}
+		repaired
+	}
+}
@@ -0,0 +1,193 @@
+use crate::*;
+
+#[derive(
+	Debug, Serialize, Deserialize, Clone, PartialEq, Eq, strum::Display,
+)]
+pub enum RecordStoreError {
+	SerializationFailed,
+	DeserializationFailed,
+	Backend(String),
+}
+
+/*
+	RecordStore
+	Holds verified records and tombstones keyed by their complete key. Callers
+	verify a record or deletion before putting it, the store only keeps it.
+*/
+pub trait RecordStore: Send + Sync {
+	// insert or replace the record under its key
+	fn put(&self, record: SignedRecord) -> Result<(), RecordStoreError>;
+
+	fn get(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<SignedRecord>, RecordStoreError>;
+
+	// remove the record under the key, returning it if there was one
+	fn delete(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<SignedRecord>, RecordStoreError>;
+
+	// every record whose key matches the fields set in the partial key
+	fn find(
+		&self,
+		key: &PartialKey,
+	) -> Result<Vec<SignedRecord>, RecordStoreError>;
+
+	// up to limit records matching the partial key in the order of their key
+	// hashes' base32 strings, starting after the record whose key hash is the
+	// cursor
+	fn list(
+		&self,
+		key: &PartialKey,
+		cursor: Option<&V256>,
+		limit: usize,
+	) -> Result<Vec<SignedRecord>, RecordStoreError> {
+		let records = self
+			.find(key)?
+			.into_iter()
+			.map(|record| (record.key.clone(), record))
+			.collect();
+		Ok(page(records, cursor, limit))
+	}
+
+	fn len(&self) -> Result<usize, RecordStoreError>;
+
+	// insert or replace the tombstone under its key
+	fn put_tombstone(
+		&self,
+		tombstone: Tombstone,
+	) -> Result<(), RecordStoreError>;
+
+	fn get_tombstone(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<Tombstone>, RecordStoreError>;
+
+	// every tombstone whose key matches the fields set in the partial key
+	fn find_tombstones(
+		&self,
+		key: &PartialKey,
+	) -> Result<Vec<Tombstone>, RecordStoreError>;
+
+	// up to limit tombstones matching the partial key, paged like list
+	fn list_tombstones(
+		&self,
+		key: &PartialKey,
+		cursor: Option<&V256>,
+		limit: usize,
+	) -> Result<Vec<Tombstone>, RecordStoreError> {
+		let tombstones = self
+			.find_tombstones(key)?
+			.into_iter()
+			.map(|tombstone| (tombstone.key.clone(), tombstone))
+			.collect();
+		Ok(page(tombstones, cursor, limit))
+	}
+
+	fn is_empty(&self) -> Result<bool, RecordStoreError> {
+		self.len().map(|len| len == 0)
+	}
+}
+
+// up to limit values in the order of their key hashes' base32 strings,
+// starting after the cursor
+fn page<T>(
+	values: Vec<(CompleteKey, T)>,
+	cursor: Option<&V256>,
+	limit: usize,
+) -> Vec<T> {
+	let mut values: Vec<(String, T)> = values
+		.into_iter()
+		.map(|(key, value)| (key.hash().to_string(), value))
+		.collect();
+	values.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+	let cursor = cursor.map(|cursor| cursor.to_string());
+	values
+		.into_iter()
+		.filter(|(hash, _)| !matches!(&cursor, Some(c) if hash <= c))
+		.take(limit)
+		.map(|(_, value)| value)
+		.collect()
+}
+
+/*
+	MemoryRecordStore
+*/
+#[derive(Clone, Default)]
+pub struct MemoryRecordStore {
+	records: Arc<DashMap<CompleteKey, SignedRecord>>,
+	tombstones: Arc<DashMap<CompleteKey, Tombstone>>,
+}
+
+impl MemoryRecordStore {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl RecordStore for MemoryRecordStore {
+	fn put(&self, record: SignedRecord) -> Result<(), RecordStoreError> {
+		self.records.insert(record.key.clone(), record);
+		Ok(())
+	}
+
+	fn get(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<SignedRecord>, RecordStoreError> {
+		Ok(self.records.get(key).map(|record| record.clone()))
+	}
+
+	fn delete(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<SignedRecord>, RecordStoreError> {
+		Ok(self.records.remove(key).map(|(_, record)| record))
+	}
+
+	fn find(
+		&self,
+		key: &PartialKey,
+	) -> Result<Vec<SignedRecord>, RecordStoreError> {
+		Ok(self
+			.records
+			.iter()
+			.filter(|record| key.matches(record.key()))
+			.map(|record| record.value().clone())
+			.collect())
+	}
+
+	fn len(&self) -> Result<usize, RecordStoreError> {
+		Ok(self.records.len())
+	}
+
+	fn put_tombstone(
+		&self,
+		tombstone: Tombstone,
+	) -> Result<(), RecordStoreError> {
+		self.tombstones.insert(tombstone.key.clone(), tombstone);
+		Ok(())
+	}
+
+	fn get_tombstone(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<Tombstone>, RecordStoreError> {
+		Ok(self.tombstones.get(key).map(|tombstone| tombstone.clone()))
+	}
+
+	fn find_tombstones(
+		&self,
+		key: &PartialKey,
+	) -> Result<Vec<Tombstone>, RecordStoreError> {
+		Ok(self
+			.tombstones
+			.iter()
+			.filter(|tombstone| key.matches(tombstone.key()))
+			.map(|tombstone| tombstone.value().clone())
+			.collect())
+	}
+}
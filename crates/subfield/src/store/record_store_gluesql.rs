@@ -0,0 +1,304 @@
+use crate::*;
+use gluesql::core::store::{GStore, GStoreMut};
+use gluesql::prelude::{Glue, Payload, Value};
+use std::sync::mpsc;
+
+// every key field is kept in base32 so a partial key is a plain WHERE clause
+const CREATE_RECORDS_TABLE: &str = "
+	CREATE TABLE IF NOT EXISTS records (
+		key_hash TEXT PRIMARY KEY,
+		signer TEXT NOT NULL,
+		cosigner TEXT NOT NULL,
+		tangent TEXT NOT NULL,
+		signed_record TEXT NOT NULL
+	)";
+
+const CREATE_TOMBSTONES_TABLE: &str = "
+	CREATE TABLE IF NOT EXISTS tombstones (
+		key_hash TEXT PRIMARY KEY,
+		tombstone TEXT NOT NULL
+	)";
+
+// a unit of work run on the store's thread, with the storage to itself
+type Job<T> = Box<dyn FnOnce(&mut Glue<T>) + Send>;
+
+/*
+	GluesqlRecordStore
+	A RecordStore persisting records in a gluesql storage on native. Gluesql
+	futures are not Send, so the storage is owned by a thread of its own that
+	runs every statement, off the async executor. The statements of one call
+	run together, nothing else runs between them.
+*/
+pub struct GluesqlRecordStore<T: GStore + GStoreMut + Send + 'static> {
+	jobs: mpsc::Sender<Job<T>>,
+}
+
+impl<T: GStore + GStoreMut + Send + 'static> GluesqlRecordStore<T> {
+	pub fn new(storage: T) -> Result<Self, RecordStoreError> {
+		let (jobs, received) = mpsc::channel::<Job<T>>();
+		// the thread ends once the store and its sender are dropped
+		std::thread::spawn(move || {
+			let mut glue = Glue::new(storage);
+			for job in received {
+				job(&mut glue);
+			}
+		});
+
+		let store = Self { jobs };
+		store.execute(CREATE_RECORDS_TABLE)?;
+		store.execute(CREATE_TOMBSTONES_TABLE)?;
+		Ok(store)
+	}
+
+	// run the job on the store's thread and wait for its result
+	fn run<R: Send + 'static>(
+		&self,
+		job: impl FnOnce(&mut Glue<T>) -> Result<R, RecordStoreError>
+			+ Send
+			+ 'static,
+	) -> Result<R, RecordStoreError> {
+		let stopped =
+			|| RecordStoreError::Backend("store thread stopped".to_string());
+		let (sender, result) = mpsc::channel();
+		self.jobs
+			.send(Box::new(move |glue| {
+				let _ = sender.send(job(glue));
+			}))
+			.map_err(|_| stopped())?;
+		result.recv().map_err(|_| stopped())?
+	}
+
+	// run a statement on the store's thread, given the storage
+	fn execute_on(
+		glue: &mut Glue<T>,
+		sql: &str,
+	) -> Result<Vec<Payload>, RecordStoreError> {
+		futures::executor::block_on(glue.execute(sql))
+			.map_err(|e| RecordStoreError::Backend(e.to_string()))
+	}
+
+	fn execute(&self, sql: &str) -> Result<Vec<Payload>, RecordStoreError> {
+		let sql = sql.to_string();
+		self.run(move |glue| Self::execute_on(glue, &sql))
+	}
+
+	// replace the row under the key hash in place, inserting it if there is
+	// none, so a failed put leaves the row it would have replaced
+	fn upsert(
+		&self,
+		update: String,
+		insert: String,
+	) -> Result<(), RecordStoreError> {
+		self.run(move |glue| match Self::execute_on(glue, &update)?[..] {
+			[Payload::Update(0)] => Self::execute_on(glue, &insert).map(|_| ()),
+			_ => Ok(()),
+		})
+	}
+
+	fn select<V: DeserializeOwned>(
+		&self,
+		sql: &str,
+	) -> Result<Vec<V>, RecordStoreError> {
+		Self::decode_rows(self.execute(sql)?)
+	}
+
+	// the values in the first column of every row selected
+	fn decode_rows<V: DeserializeOwned>(
+		payloads: Vec<Payload>,
+	) -> Result<Vec<V>, RecordStoreError> {
+		let mut values = vec![];
+		for payload in payloads {
+			if let Payload::Select { rows, .. } = payload {
+				for row in rows {
+					values.push(Self::decode(&row)?);
+				}
+			}
+		}
+		Ok(values)
+	}
+
+	/*
+		Encoding
+	*/
+	fn encode<V: Serialize>(value: &V) -> Result<String, RecordStoreError> {
+		serialize(value)
+			.map(|bytes| arr::to_base32(&bytes))
+			.map_err(|_| RecordStoreError::SerializationFailed)
+	}
+
+	fn decode<V: DeserializeOwned>(
+		row: &[Value],
+	) -> Result<V, RecordStoreError> {
+		let Some(Value::Str(encoded)) = row.first() else {
+			return Err(RecordStoreError::DeserializationFailed);
+		};
+		let bytes = arr::from_base32(encoded)
+			.map_err(|_| RecordStoreError::DeserializationFailed)?;
+		deserialize(&bytes).map_err(|_| RecordStoreError::DeserializationFailed)
+	}
+
+	fn key_filter(key: &PartialKey) -> String {
+		let fields = [
+			("signer", &key.signer),
+			("cosigner", &key.cosigner),
+			("tangent", &key.tangent),
+		];
+		let clauses: Vec<String> = fields
+			.iter()
+			.filter_map(|(column, field)| {
+				field
+					.as_ref()
+					.map(|field| format!("{column} = '{}'", field.to_string()))
+			})
+			.collect();
+
+		match clauses.is_empty() {
+			true => String::new(),
+			false => format!(" WHERE {}", clauses.join(" AND ")),
+		}
+	}
+}
+
+impl<T: GStore + GStoreMut + Send + 'static> RecordStore
+	for GluesqlRecordStore<T>
+{
+	fn put(&self, record: SignedRecord) -> Result<(), RecordStoreError> {
+		let key = &record.key;
+		let key_hash = key.hash().to_string();
+		let encoded = Self::encode(&record)?;
+		self.upsert(
+			format!(
+				"UPDATE records SET signed_record = '{encoded}' \
+				WHERE key_hash = '{key_hash}'"
+			),
+			format!(
+				"INSERT INTO records VALUES ('{}', '{}', '{}', '{}', '{}')",
+				key_hash,
+				key.signer.to_string(),
+				key.cosigner.to_string(),
+				key.tangent.to_string(),
+				encoded,
+			),
+		)
+	}
+
+	fn get(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<SignedRecord>, RecordStoreError> {
+		let records = self.select(&format!(
+			"SELECT signed_record FROM records WHERE key_hash = '{}'",
+			key.hash().to_string()
+		))?;
+		Ok(records.into_iter().next())
+	}
+
+	fn delete(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<SignedRecord>, RecordStoreError> {
+		let key_hash = key.hash().to_string();
+		self.run(move |glue| {
+			let selected = Self::execute_on(
+				glue,
+				&format!(
+					"SELECT signed_record FROM records \
+					WHERE key_hash = '{key_hash}'"
+				),
+			)?;
+			let record = Self::decode_rows(selected)?.into_iter().next();
+			if record.is_some() {
+				Self::execute_on(
+					glue,
+					&format!(
+						"DELETE FROM records WHERE key_hash = '{key_hash}'"
+					),
+				)?;
+			}
+			Ok(record)
+		})
+	}
+
+	fn find(
+		&self,
+		key: &PartialKey,
+	) -> Result<Vec<SignedRecord>, RecordStoreError> {
+		self.select(&format!(
+			"SELECT signed_record FROM records{}",
+			Self::key_filter(key)
+		))
+	}
+
+	fn list(
+		&self,
+		key: &PartialKey,
+		cursor: Option<&V256>,
+		limit: usize,
+	) -> Result<Vec<SignedRecord>, RecordStoreError> {
+		let mut filter = Self::key_filter(key);
+		if let Some(cursor) = cursor {
+			let clause = format!("key_hash > '{}'", cursor.to_string());
+			filter = match filter.is_empty() {
+				true => format!(" WHERE {clause}"),
+				false => format!("{filter} AND {clause}"),
+			};
+		}
+		self.select(&format!(
+			"SELECT signed_record FROM records{filter} ORDER BY key_hash LIMIT {limit}"
+		))
+	}
+
+	fn len(&self) -> Result<usize, RecordStoreError> {
+		for payload in self.execute("SELECT COUNT(*) FROM records")? {
+			if let Payload::Select { rows, .. } = payload {
+				if let Some(Value::I64(count)) =
+					rows.first().and_then(|row| row.first())
+				{
+					return Ok(*count as usize);
+				}
+			}
+		}
+		Err(RecordStoreError::Backend("no count returned".to_string()))
+	}
+
+	fn put_tombstone(
+		&self,
+		tombstone: Tombstone,
+	) -> Result<(), RecordStoreError> {
+		let key_hash = tombstone.key.hash().to_string();
+		let encoded = Self::encode(&tombstone)?;
+		self.upsert(
+			format!(
+				"UPDATE tombstones SET tombstone = '{encoded}' \
+				WHERE key_hash = '{key_hash}'"
+			),
+			format!(
+				"INSERT INTO tombstones VALUES ('{key_hash}', '{encoded}')"
+			),
+		)
+	}
+
+	fn get_tombstone(
+		&self,
+		key: &CompleteKey,
+	) -> Result<Option<Tombstone>, RecordStoreError> {
+		let tombstones = self.select(&format!(
+			"SELECT tombstone FROM tombstones WHERE key_hash = '{}'",
+			key.hash().to_string()
+		))?;
+		Ok(tombstones.into_iter().next())
+	}
+
+	// tombstones are only keyed by hash, so they are matched after reading
+	fn find_tombstones(
+		&self,
+		key: &PartialKey,
+	) -> Result<Vec<Tombstone>, RecordStoreError> {
+		let tombstones: Vec<Tombstone> =
+			self.select("SELECT tombstone FROM tombstones")?;
+		Ok(tombstones
+			.into_iter()
+			.filter(|tombstone| key.matches(&tombstone.key))
+			.collect())
+	}
+}
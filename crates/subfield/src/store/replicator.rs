@@ -0,0 +1,660 @@
+use crate::*;
+use futures::future::join_all;
+use std::time::Duration;
+
+// the servers each routing location of a record is stored on
+pub const REPLICATION_FACTOR: usize = 3;
+
+// how often the records held are checked against their other replicas
+pub const REPAIR_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ReplicationMetrics {
+	// the records held when the last repair ran
+	pub records: usize,
+	// the tombstones held when the last repair ran
+	pub tombstones: usize,
+	// records and tombstones the last repair pushed to replicas missing them
+	pub pushed: usize,
+	// records and tombstones the last repair pulled from other replicas
+	pub pulled: usize,
+	// keys stored on fewer servers than the replication factor at one of
+	// their routing locations
+	pub under_replicated: usize,
+}
+
+// a routing location records or tombstones are held at and the servers it
+// belongs on
+struct Location {
+	routing_key: RoutingKey,
+	replicas: Vec<PeerId>,
+}
+
+// what another replica holds at a routing location, by key hash
+#[derive(Default)]
+struct Listing {
+	records: HashMap<String, SignedRecord>,
+	tombstones: HashMap<String, Tombstone>,
+}
+
+/*
+	Replicator
+	Stores each routing location of a record on the servers closest to it, as
+	many as the replication factor. The closest one pushes every put or
+	deletion that changes its store on to the others, and a periodic repair
+	pushes records and tombstones to replicas that joined or missed them and
+	pulls the ones other replicas hold after one left. Keys found on too few
+	servers are tracked as under-replicated until a repair finds them on
+	enough.
+*/
+pub struct Replicator<O: StreamOpener> {
+	records: StoreRecordHandler,
+	table: Arc<RoutingTable>,
+	opener: Arc<O>,
+	factor: usize,
+	metrics: Arc<std::sync::Mutex<ReplicationMetrics>>,
+	under_replicated: Arc<DashSet<CompleteKey>>,
+}
+
+impl<O: StreamOpener> Replicator<O> {
+	/*
+	Constructors
+	*/
+	pub fn new(
+		records: StoreRecordHandler,
+		table: Arc<RoutingTable>,
+		opener: O,
+	) -> Self {
+		Self {
+			records,
+			table,
+			opener: Arc::new(opener),
+			factor: REPLICATION_FACTOR,
+			metrics: Default::default(),
+			under_replicated: Default::default(),
+		}
+	}
+
+	pub fn with_replication_factor(mut self, factor: usize) -> Self {
+		self.factor = factor.max(1);
+		self
+	}
+
+	/*
+	Getters
+	*/
+	pub fn records(&self) -> &StoreRecordHandler {
+		&self.records
+	}
+
+	pub fn replication_factor(&self) -> usize {
+		self.factor
+	}
+
+	// the last repair's counts, with the keys under-replicated since
+	pub fn metrics(&self) -> ReplicationMetrics {
+		let mut metrics = self.metrics.lock().unwrap().clone();
+		metrics.under_replicated = self.under_replicated.len();
+		metrics
+	}
+
+	pub fn under_replicated_keys(&self) -> Vec<CompleteKey> {
+		self.under_replicated
+			.iter()
+			.map(|key| key.clone())
+			.collect()
+	}
+
+	// the servers a routing location is stored on, closest first
+	pub fn replicas(
+		&self,
+		routing_key: &RoutingKey,
+	) -> Result<Vec<PeerId>, SubfieldError> {
+		let field = routing_key.get_routing_field()?;
+		Ok(self.table.closest_peers(&field, self.factor))
+	}
+
+	// how many servers should hold a routing location, fewer while the ring
+	// is smaller than the replication factor
+	fn expected_replicas(&self) -> usize {
+		self.factor.min(self.table.len() + 1)
+	}
+
+	/*
+	Pushing
+	*/
+
+	// send the requests on to the other replicas if this server is the
+	// closest one, tracking the key if too few of them accepted them all. A
+	// request from another replica that changed nothing is not sent on, so
+	// replicas that disagree on which of them is closest do not echo it
+	// between them
+	async fn replicate(
+		&self,
+		peer: PeerId,
+		key: &CompleteKey,
+		routing_key: RoutingKey,
+		bodies: Vec<SubfieldRequestBody>,
+		changed: bool,
+	) {
+		let Ok(replicas) = self.replicas(&routing_key) else {
+			return;
+		};
+		if replicas.first() != Some(self.table.local_peer_id())
+			|| (!changed && replicas.contains(&peer))
+		{
+			return;
+		}
+
+		let pushes = replicas[1..]
+			.iter()
+			.map(|peer| self.push(*peer, &routing_key, &bodies));
+		let held = join_all(pushes)
+			.await
+			.iter()
+			.filter(|responses| responses.iter().all(Self::is_held))
+			.count();
+		if held + 1 < self.expected_replicas() {
+			self.under_replicated.insert(key.clone());
+		}
+	}
+
+	// send the requests to the peer one after the other, stopping at the
+	// first that fails to send
+	async fn push(
+		&self,
+		peer: PeerId,
+		routing_key: &RoutingKey,
+		bodies: &[SubfieldRequestBody],
+	) -> Vec<Result<SubfieldResponse, SubfieldError>> {
+		let mut responses = vec![];
+		for body in bodies {
+			let request =
+				SubfieldRequest::new_direct(routing_key.clone(), body.clone());
+			let response = self.oneshot(peer, request).await;
+			let failed = response.is_err();
+			responses.push(response);
+			if failed {
+				break;
+			}
+		}
+		responses
+	}
+
+	// whether the replica holds the record, or something newer, after a push
+	fn is_held(response: &Result<SubfieldResponse, SubfieldError>) -> bool {
+		matches!(
+			response,
+			Ok(SubfieldResponse::PutRecord(
+				Ok(_)
+					| Err(PutRecordFailure::Stale { .. })
+					| Err(PutRecordFailure::Deleted { .. })
+			)) | Ok(SubfieldResponse::DeleteRecord(Ok(_)))
+		)
+	}
+
+	// the put requests that store the record on another replica
+	fn put_bodies(record: &SignedRecord) -> Vec<SubfieldRequestBody> {
+		record
+			.to_put_record_requests()
+			.into_iter()
+			.map(SubfieldRequestBody::PutRecord)
+			.collect()
+	}
+
+	/*
+	Repair
+	*/
+
+	// repair once every interval, for as long as the future is polled
+	pub async fn run(&self, interval: Duration) {
+		loop {
+			sleep(interval).await;
+			let metrics = self.repair().await;
+			tracing::debug!(?metrics, "Repaired records");
+		}
+	}
+
+	// check the routing location of every record and tombstone held against
+	// its replicas, pulling what they hold if this server is one of them and
+	// pushing what they miss
+	pub async fn repair(&self) -> ReplicationMetrics {
+		let mut metrics = ReplicationMetrics::default();
+		let locations = match self.held_locations(&mut metrics) {
+			Ok(locations) => locations,
+			Err(e) => {
+				tracing::debug!("Failed to read records to repair: {e}");
+				return self.metrics();
+			}
+		};
+
+		let mut under_replicated = vec![];
+		for location in locations.into_values() {
+			let (pushed, pulled, under) = self.repair_location(&location).await;
+			metrics.pushed += pushed;
+			metrics.pulled += pulled;
+			under_replicated.extend(under);
+		}
+
+		self.under_replicated.clear();
+		for key in under_replicated {
+			self.under_replicated.insert(key);
+		}
+		*self.metrics.lock().unwrap() = metrics;
+		self.metrics()
+	}
+
+	// the routing locations of every record and tombstone held, by location
+	// hash, counting them. The store is read a page at a time
+	fn held_locations(
+		&self,
+		metrics: &mut ReplicationMetrics,
+	) -> Result<HashMap<String, Location>, RecordStoreError> {
+		let store = self.records.store();
+		let all = PartialKey::default();
+		let limit = LIST_RECORDS_MAX_LIMIT as usize;
+		let mut locations = HashMap::new();
+
+		let mut cursor = None;
+		loop {
+			let records = store.list(&all, cursor.as_ref(), limit)?;
+			metrics.records += records.len();
+			self.add_locations(
+				&mut locations,
+				records.iter().map(|record| &record.key),
+			);
+			match records.last() {
+				Some(last) if records.len() == limit => {
+					cursor = Some(last.key.hash())
+				}
+				_ => break,
+			}
+		}
+
+		let mut cursor = None;
+		loop {
+			let tombstones =
+				store.list_tombstones(&all, cursor.as_ref(), limit)?;
+			metrics.tombstones += tombstones.len();
+			self.add_locations(
+				&mut locations,
+				tombstones.iter().map(|tombstone| &tombstone.key),
+			);
+			match tombstones.last() {
+				Some(last) if tombstones.len() == limit => {
+					cursor = Some(last.key.hash())
+				}
+				_ => break,
+			}
+		}
+		Ok(locations)
+	}
+
+	// add the routing locations of the keys, with their replicas
+	fn add_locations<'a>(
+		&self,
+		locations: &mut HashMap<String, Location>,
+		keys: impl Iterator<Item = &'a CompleteKey>,
+	) {
+		for key in keys {
+			for routing_key in key.to_routing_keys() {
+				let Ok(routing_key) = routing_key.to_location() else {
+					continue;
+				};
+				let hash = routing_key.to_partial_key().hash().to_string();
+				if locations.contains_key(&hash) {
+					continue;
+				}
+				let Ok(replicas) = self.replicas(&routing_key) else {
+					continue;
+				};
+				let location = Location {
+					routing_key,
+					replicas,
+				};
+				locations.insert(hash, location);
+			}
+		}
+	}
+
+	// the records and tombstones pushed and pulled, and the keys held by too
+	// few replicas. Everything the other replicas hold is pulled before
+	// comparing, so a deletion one of them missed is pushed rather than the
+	// record it deleted pulled back
+	async fn repair_location(
+		&self,
+		location: &Location,
+	) -> (usize, usize, Vec<CompleteKey>) {
+		let local = *self.table.local_peer_id();
+		let is_replica = location.replicas.contains(&local);
+		let (mut pushed, mut pulled) = (0, 0);
+
+		let mut listings = vec![];
+		for peer in location.replicas.iter().filter(|peer| **peer != local) {
+			let listing = match self.list(*peer, &location.routing_key).await {
+				Ok(listing) => listing,
+				Err(e) => {
+					tracing::debug!(%peer, "Failed to list replica: {e:?}");
+					Listing::default()
+				}
+			};
+			// a server that is no longer a replica only hands its records off
+			if is_replica {
+				for tombstone in listing.tombstones.values() {
+					if self.pull_tombstone(tombstone).await {
+						pulled += 1;
+					}
+				}
+				for record in listing.records.values() {
+					if self.pull(record).await {
+						pulled += 1;
+					}
+				}
+			}
+			listings.push((*peer, listing));
+		}
+
+		let store = self.records.store();
+		let key = location.routing_key.to_partial_key();
+		let records = store.find(&key).unwrap_or_default();
+		let tombstones = store.find_tombstones(&key).unwrap_or_default();
+		// the replicas holding each key, by key hash
+		let mut held: HashMap<String, (CompleteKey, usize)> = records
+			.iter()
+			.map(|record| &record.key)
+			.chain(tombstones.iter().map(|tombstone| &tombstone.key))
+			.map(|key| {
+				let count = usize::from(is_replica);
+				(key.hash().to_string(), (key.clone(), count))
+			})
+			.collect();
+
+		for (peer, listing) in &listings {
+			let mut pushes = vec![];
+			for tombstone in &tombstones {
+				let same = listing
+					.tombstones
+					.get(&tombstone.key.hash().to_string())
+					.map(|remote| remote.hash())
+					== Some(tombstone.hash());
+				let body = SubfieldRequestBody::DeleteRecord(
+					tombstone.to_delete_record_request(),
+				);
+				pushes.push((&tombstone.key, same, vec![body]));
+			}
+			for record in &records {
+				let same = listing
+					.records
+					.get(&record.key.hash().to_string())
+					.map(|remote| &remote.record_bytes)
+					== Some(&record.record_bytes);
+				pushes.push((&record.key, same, Self::put_bodies(record)));
+			}
+
+			for (key, same, bodies) in pushes {
+				if !same {
+					let routing_key =
+						location.routing_key.with_partial_key(key.to_partial());
+					let responses =
+						self.push(*peer, &routing_key, &bodies).await;
+					if !responses.iter().all(Self::is_held) {
+						continue;
+					}
+					if responses.iter().any(|response| {
+						matches!(
+							response,
+							Ok(SubfieldResponse::PutRecord(Ok(_)))
+								| Ok(SubfieldResponse::DeleteRecord(Ok(_)))
+						)
+					}) {
+						pushed += 1;
+					}
+				}
+				if let Some((_, held)) = held.get_mut(&key.hash().to_string()) {
+					*held += 1;
+				}
+			}
+		}
+
+		let expected = self.expected_replicas();
+		let under = held
+			.into_values()
+			.filter(|(_, held)| *held < expected)
+			.map(|(key, _)| key)
+			.collect();
+		(pushed, pulled, under)
+	}
+
+	// put another replica's record through the local handler, returning
+	// whether it changed the record stored
+	async fn pull(&self, record: &SignedRecord) -> bool {
+		let before = self.stored(&record.key);
+		if before.as_ref().map(|before| &before.record_bytes)
+			== Some(&record.record_bytes)
+		{
+			return false;
+		}
+		for put in record.to_put_record_requests() {
+			let _ = self
+				.records
+				.put_record(
+					*self.table.local_peer_id(),
+					record.key.to_tangent_routing_key(),
+					put,
+				)
+				.await;
+		}
+		self.changed(before.as_ref(), &record.key).is_some()
+	}
+
+	// apply another replica's deletion through the local handler, returning
+	// whether it changed the tombstone stored
+	async fn pull_tombstone(&self, tombstone: &Tombstone) -> bool {
+		let hash = |key| self.stored_tombstone(key).map(|stored| stored.hash());
+		let before = hash(&tombstone.key);
+		if before == Some(tombstone.hash()) {
+			return false;
+		}
+		let _ = self
+			.records
+			.delete_record(
+				*self.table.local_peer_id(),
+				tombstone.key.to_tangent_routing_key(),
+				tombstone.to_delete_record_request(),
+			)
+			.await;
+		hash(&tombstone.key) != before
+	}
+
+	/*
+	Helpers
+	*/
+
+	fn stored(&self, key: &CompleteKey) -> Option<SignedRecord> {
+		self.records.store().get(key).ok().flatten()
+	}
+
+	fn stored_tombstone(&self, key: &CompleteKey) -> Option<Tombstone> {
+		self.records.store().get_tombstone(key).ok().flatten()
+	}
+
+	// the record now stored under the key, if it differs from before
+	fn changed(
+		&self,
+		before: Option<&SignedRecord>,
+		key: &CompleteKey,
+	) -> Option<SignedRecord> {
+		self.stored(key).filter(|after| {
+			before.map(|before| &before.record_bytes)
+				!= Some(&after.record_bytes)
+		})
+	}
+
+	async fn oneshot(
+		&self,
+		peer: PeerId,
+		request: SubfieldRequest,
+	) -> Result<SubfieldResponse, SubfieldError> {
+		let mut stream = client_stream(self.opener.open(peer).await?);
+		send_request(&mut stream, request).await?;
+		recv_response(&mut stream).await
+	}
+
+	// every verified record and tombstone the peer holds at the routing
+	// location
+	async fn list(
+		&self,
+		peer: PeerId,
+		location: &RoutingKey,
+	) -> Result<Listing, SubfieldError> {
+		let mut listing = Listing::default();
+		for tombstones in [false, true] {
+			let mut cursor = None;
+			loop {
+				cursor = self
+					.list_page(peer, location, cursor, tombstones, &mut listing)
+					.await?;
+				if cursor.is_none() {
+					break;
+				}
+			}
+		}
+		Ok(listing)
+	}
+
+	// one page of the peer's records or tombstones, returning the cursor of
+	// the next
+	async fn list_page(
+		&self,
+		peer: PeerId,
+		location: &RoutingKey,
+		cursor: Option<ListRecordsCursor>,
+		tombstones: bool,
+		listing: &mut Listing,
+	) -> Result<Option<ListRecordsCursor>, SubfieldError> {
+		let mut stream = client_stream(self.opener.open(peer).await?);
+		let request = SubfieldRequest::new_direct(
+			location.clone(),
+			SubfieldRequestBody::ListRecords(ListRecordsRequest {
+				cursor,
+				limit: LIST_RECORDS_MAX_LIMIT,
+				tombstones,
+			}),
+		);
+		send_request(&mut stream, request).await?;
+
+		// a record or tombstone that fails to verify is left out
+		loop {
+			match recv_response(&mut stream).await? {
+				SubfieldResponse::ListRecords(Ok(
+					ListRecordsSuccess::Record(success),
+				)) => {
+					if let Ok(record) =
+						SignedRecord::from_get_record_success(*success)
+					{
+						listing
+							.records
+							.insert(record.key.hash().to_string(), record);
+					}
+				}
+				SubfieldResponse::ListRecords(Ok(
+					ListRecordsSuccess::Tombstone(listed),
+				)) => {
+					let ListedTombstone {
+						routing_key,
+						request,
+					} = *listed;
+					if let Ok(tombstone) = Tombstone::from_delete_record_request(
+						routing_key,
+						request,
+					) {
+						listing.tombstones.insert(
+							tombstone.key.hash().to_string(),
+							tombstone,
+						);
+					}
+				}
+				SubfieldResponse::ListRecords(Ok(
+					ListRecordsSuccess::End { next },
+				)) => return Ok(next),
+				SubfieldResponse::ListRecords(Err(_)) => {
+					return Err(SubfieldError::RequestFailed)
+				}
+				_ => return Err(SubfieldError::UnexpectedResponseType),
+			}
+		}
+	}
+}
+
+#[async_trait]
+impl<O: StreamOpener> RecordHandler for Replicator<O> {
+	async fn get_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: GetRecordRequest,
+	) -> GetRecordResponse {
+		self.records.get_record(peer, routing_key, request).await
+	}
+
+	async fn put_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: PutRecordRequest,
+	) -> PutRecordResponse {
+		let key = routing_key
+			.to_complete_key()
+			.map_err(|_| PutRecordFailure::Invalid)?;
+		let before = self.stored(&key);
+		let success = self
+			.records
+			.put_record(peer, routing_key.clone(), request)
+			.await?;
+
+		if let Some(record) = self.stored(&key) {
+			let changed = self.changed(before.as_ref(), &key).is_some();
+			let bodies = Self::put_bodies(&record);
+			self.replicate(peer, &key, routing_key, bodies, changed)
+				.await;
+		}
+		Ok(success)
+	}
+
+	async fn delete_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: DeleteRecordRequest,
+	) -> DeleteRecordResponse {
+		let key = routing_key
+			.to_complete_key()
+			.map_err(|_| DeleteRecordFailure::Invalid)?;
+		let before = self
+			.stored_tombstone(&key)
+			.map(|tombstone| tombstone.hash());
+		let success = self
+			.records
+			.delete_record(peer, routing_key.clone(), request)
+			.await?;
+
+		if let Some(after) = self.stored_tombstone(&key) {
+			let changed = Some(after.hash()) != before;
+			let body = SubfieldRequestBody::DeleteRecord(
+				after.to_delete_record_request(),
+			);
+			self.replicate(peer, &key, routing_key, vec![body], changed)
+				.await;
+		}
+		Ok(success)
+	}
+
+	async fn list_records(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: ListRecordsRequest,
+	) -> Result<ListRecordsStream, ListRecordsFailure> {
+		self.records.list_records(peer, routing_key, request).await
+	}
+}
@@ -0,0 +1,208 @@
+use crate::*;
+use futures::channel::mpsc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// a subscriber's end of a subscription, events are pushed into it until the
+// lease runs out
+struct SubscriberEntry {
+	peer: PeerId,
+	key: PartialKey,
+	sender: mpsc::UnboundedSender<SubscribeResponse>,
+	expires_at: DateTimeUtc,
+}
+
+impl SubscriberEntry {
+	fn is_open(&self, now: &DateTimeUtc) -> bool {
+		!self.sender.is_closed() && &self.expires_at > now
+	}
+}
+
+/*
+	SubscriptionTable
+	The subscriptions made to this server, keyed by the hash of the partial key
+	subscribed to. A record put or a message published under a complete key is
+	sent to the subscribers of each of its 7 hash combinations. Subscriptions
+	are dropped once their lease runs out, ending their stream, so subscribers
+	must renew them.
+*/
+#[derive(Clone, Default)]
+pub struct SubscriptionTable {
+	subscribers: Arc<DashMap<V256, Vec<SubscriberEntry>>>,
+	// the hashes of the messages published within the max age, with when
+	// they expire. A message is published to each of its key's routing
+	// locations and may reach a server more than once
+	recent: Arc<Mutex<HashMap<V256, DateTimeUtc>>>,
+}
+
+impl SubscriptionTable {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// the number of open subscriptions
+	pub fn len(&self) -> usize {
+		self.subscribers
+			.iter()
+			.map(|entry| entry.value().len())
+			.sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// drop every subscription whose lease ran out or whose stream closed, run
+	// on every subscribe and every send so none outlives its lease for long
+	pub fn expire(&self) {
+		let now = Utc::now();
+		self.subscribers.retain(|_, subscribers| {
+			subscribers.retain(|subscriber| subscriber.is_open(&now));
+			!subscribers.is_empty()
+		});
+	}
+
+	// push the stored record to everyone subscribed to a partial key matching
+	// it
+	pub fn publish_record(&self, record: &SignedRecord) -> usize {
+		let success =
+			record.to_get_record_success(record.key.to_tangent_routing_key());
+		self.send(&record.key, |key| {
+			SubscribeSuccess::Record(Box::new(SubscriptionEvent {
+				key,
+				record: success.clone(),
+			}))
+		})
+	}
+
+	// send an event to every subscriber of the key's hash combinations,
+	// dropping subscriptions whose lease ran out or whose stream has closed
+	fn send(
+		&self,
+		key: &CompleteKey,
+		event: impl Fn(PartialKey) -> SubscribeSuccess,
+	) -> usize {
+		let Ok(hashes) = key.to_partial().hash_combinations() else {
+			return 0;
+		};
+		self.expire();
+
+		let now = Utc::now();
+		let mut delivered = 0;
+		for hash in hashes {
+			if let Some(mut subscribers) = self.subscribers.get_mut(&hash) {
+				subscribers.retain(|subscriber| {
+					if !subscriber.is_open(&now) {
+						return false;
+					}
+					let event = event(subscriber.key.clone());
+					subscriber.sender.unbounded_send(Ok(event)).is_ok()
+				});
+				delivered += subscribers.len();
+			}
+			self.subscribers
+				.remove_if(&hash, |_, subscribers| subscribers.is_empty());
+		}
+		delivered
+	}
+
+	// whether the message was seen before, remembering it until it is too
+	// old to be published again
+	fn is_repeat(&self, hash: V256, expires_at: DateTimeUtc) -> bool {
+		let now = Utc::now();
+		let mut recent = self.recent.lock().unwrap();
+		recent.retain(|_, expires_at| *expires_at > now);
+		recent.insert(hash, expires_at).is_some()
+	}
+}
+
+#[async_trait]
+impl PubsubHandler for SubscriptionTable {
+	async fn subscribe(
+		&self,
+		peer: PeerId,
+		request: SubscribeRequest,
+	) -> Result<Subscription, SubscribeFailure> {
+		let key = request.key;
+		if (key.signer.is_none()
+			&& key.cosigner.is_none()
+			&& key.tangent.is_none())
+			|| request.lease == 0
+		{
+			return Err(SubscribeFailure::Invalid);
+		}
+		self.expire();
+
+		let lease = request.lease.min(SUBSCRIPTION_MAX_LEASE);
+		let (sender, receiver) = mpsc::unbounded();
+		self.subscribers
+			.entry(key.hash())
+			.or_default()
+			.push(SubscriberEntry {
+				peer,
+				key,
+				sender,
+				expires_at: Utc::now()
+					+ chrono::Duration::seconds(lease as i64),
+			});
+		// the stream ends with the lease, even if nothing is sent after
+		let expired = sleep_send(Duration::from_secs(lease));
+		Ok(Subscription {
+			lease,
+			events: receiver.take_until(expired).boxed(),
+		})
+	}
+
+	// forget the key's subscriptions whose streams were dropped, the
+	// dispatcher drops the ones made on the unsubscribing stream first so
+	// those the peer made on its other streams stay
+	async fn unsubscribe(
+		&self,
+		_peer: PeerId,
+		request: UnsubscribeRequest,
+	) -> UnsubscribeResponse {
+		let hash = request.key.hash();
+		if let Some(mut subscribers) = self.subscribers.get_mut(&hash) {
+			subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+		}
+		self.subscribers
+			.remove_if(&hash, |_, subscribers| subscribers.is_empty());
+		Ok(UnsubscribeSuccess {})
+	}
+
+	// verify the message like a put record, then send it on without storing.
+	// Only messages updated within the max age are sent, each once
+	async fn publish(
+		&self,
+		_peer: PeerId,
+		routing_key: RoutingKey,
+		request: PublishRequest,
+	) -> PublishResponse {
+		let (key, message) = request
+			.verify(routing_key.clone())
+			.map_err(PublishFailure::RecordError)?;
+		let max_age = chrono::Duration::seconds(PUBLISH_MAX_AGE as i64);
+		if (Utc::now() - message.updated_at).abs() > max_age {
+			return Err(PublishFailure::Expired);
+		}
+		let expires_at = message.updated_at + max_age;
+		if self.is_repeat(crypto::hash(&request.record_bytes), expires_at) {
+			return Ok(PublishSuccess { delivered: 0 });
+		}
+
+		let message = GetRecordSuccess {
+			routing_key,
+			record_bytes: request.record_bytes,
+			signature: request.signature,
+		};
+		let delivered = self.send(&key, |key| {
+			SubscribeSuccess::Message(Box::new(SubscriptionEvent {
+				key,
+				record: message.clone(),
+			}))
+		});
+		Ok(PublishSuccess {
+			delivered: delivered as u32,
+		})
+	}
+}
@@ -0,0 +1,182 @@
+use super::chord_simulator::*;
+use crate::*;
+
+fn chord_id(n: u32) -> ChordId {
+	bigint_to_chord_id(num_bigint::BigUint::from(n))
+}
+
+/*
+   Fingers
+*/
+#[test]
+fn test_finger_index() {
+	let n = chord_id(10);
+	assert_eq!(k_to_finger_index(&n, 1), chord_id(11));
+	assert_eq!(k_to_finger_index(&n, 2), chord_id(12));
+	assert_eq!(k_to_finger_index(&n, 4), chord_id(18));
+
+	// finger starts wrap around the keyspace
+	let max = V256::new(0, &[0xff; 32]);
+	assert_eq!(k_to_finger_index(&max, 1), chord_id(0));
+}
+
+#[test]
+fn test_ring_intervals() {
+	let (a, b, c) = (chord_id(1), chord_id(5), chord_id(9));
+	assert!(is_between(&b, &a, &c));
+	assert!(!is_between(&c, &a, &c));
+	assert!(is_between_right_inclusive(&c, &a, &c));
+
+	// intervals wrap around the ring
+	assert!(is_between(&a, &c, &b));
+	assert!(!is_between(&b, &c, &a));
+
+	// an interval from a node to itself is the whole ring but the node
+	assert!(is_between(&b, &a, &a));
+	assert!(!is_between(&a, &a, &a));
+	assert!(is_between_right_inclusive(&a, &a, &a));
+}
+
+#[test]
+fn test_chord_map() {
+	let mut map = ChordMap::new(chord_id(0));
+	assert_eq!(map.fingers().len(), KEYSPACE_SIZE);
+	assert_eq!(
+		map.try_add(chord_id(0)),
+		TryAddChordNodeResult::CannotAddSelf
+	);
+
+	// the first node fills every finger
+	assert_eq!(map.try_add(chord_id(9)), TryAddChordNodeResult::Added);
+	assert_eq!(map.finger(1), Some(&chord_id(9)));
+	assert_eq!(map.finger(KEYSPACE_SIZE), Some(&chord_id(9)));
+
+	// a closer node evicts it from the fingers it precedes
+	assert_eq!(
+		map.try_add(chord_id(3)),
+		TryAddChordNodeResult::AddedWithEviction(chord_id(9))
+	);
+	assert_eq!(map.finger(1), Some(&chord_id(3)));
+	assert_eq!(map.finger(2), Some(&chord_id(3)));
+	assert_eq!(map.finger(3), Some(&chord_id(9)));
+	assert_eq!(map.try_add(chord_id(12)), TryAddChordNodeResult::DidNotAdd);
+
+	assert_eq!(map.closest_preceding_node(&chord_id(7)), chord_id(3));
+	assert_eq!(map.closest_preceding_node(&chord_id(2)), chord_id(0));
+
+	assert!(map.try_remove(&chord_id(3)));
+	assert_eq!(map.finger(1), None);
+	assert_eq!(map.nodes(), vec![chord_id(9)]);
+}
+
+/*
+   Ring
+*/
+
+// the most hops a lookup may take in a ring of the given size
+fn log_hop_bound(nodes: usize) -> usize {
+	2 * (nodes as f64).log2().ceil() as usize
+}
+
+#[tokio::test]
+async fn test_chord_ring() {
+	let mut simulator = ChordSimulator::new(1);
+
+	// a lone node is its own successor
+	let first = simulator.join().await;
+	let key = simulator.random_id();
+	assert_eq!(simulator.node(&first).successor(), first);
+	assert_eq!(
+		simulator.node(&first).find_successor(&key).await.unwrap(),
+		first
+	);
+
+	for _ in 0..4 {
+		simulator.join().await;
+	}
+	simulator.run(5).await;
+	simulator.assert_ring();
+	simulator.assert_lookups(20).await;
+}
+
+#[tokio::test]
+async fn test_chord_simulator_converges() {
+	let mut simulator = ChordSimulator::new(7);
+	for _ in 0..64 {
+		simulator.join().await;
+		simulator.tick().await;
+	}
+	simulator.run(20).await;
+
+	simulator.assert_ring();
+	let max_hops = simulator.assert_lookups(200).await;
+	assert!(
+		max_hops <= log_hop_bound(simulator.len()),
+		"{max_hops} hops"
+	);
+}
+
+#[tokio::test]
+async fn test_chord_simulator_is_deterministic() {
+	let mut a = ChordSimulator::new(42);
+	let mut b = ChordSimulator::new(42);
+	for simulator in [&mut a, &mut b] {
+		for _ in 0..16 {
+			simulator.join().await;
+		}
+		simulator.run(10).await;
+	}
+
+	assert_eq!(a.ids(), b.ids());
+	assert_eq!(a.bus().messages(), b.bus().messages());
+}
+
+#[tokio::test]
+async fn test_chord_simulator_leaves() {
+	let mut simulator = ChordSimulator::new(3);
+	for _ in 0..32 {
+		simulator.join().await;
+	}
+	// joins without ticks in between take longer to stabilize
+	simulator.run(40).await;
+	simulator.assert_ring();
+
+	// a graceful leave repairs the ring without any ticks
+	for _ in 0..8 {
+		simulator.leave().await;
+		simulator.assert_ring();
+	}
+
+	simulator.run(10).await;
+	let max_hops = simulator.assert_lookups(100).await;
+	assert!(
+		max_hops <= log_hop_bound(simulator.len()),
+		"{max_hops} hops"
+	);
+}
+
+#[tokio::test]
+async fn test_chord_simulator_crashes() {
+	let mut simulator = ChordSimulator::new(5);
+	for _ in 0..32 {
+		simulator.join().await;
+	}
+	simulator.run(40).await;
+	simulator.assert_ring();
+
+	// crashes, fewer than the successor list size, with joins in between
+	for _ in 0..4 {
+		simulator.crash();
+		simulator.crash();
+		simulator.join().await;
+		simulator.tick().await;
+	}
+
+	simulator.run(20).await;
+	simulator.assert_ring();
+	let max_hops = simulator.assert_lookups(100).await;
+	assert!(
+		max_hops <= log_hop_bound(simulator.len()),
+		"{max_hops} hops"
+	);
+}
@@ -0,0 +1,302 @@
+use crate::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub type SimulatedNode = ChordNode<ChordBus>;
+
+// the messages chord nodes exchange over the bus
+#[derive(Debug, Clone)]
+pub enum ChordRequest {
+	FindSuccessorStep(ChordId),
+	Predecessor,
+	SuccessorList,
+	Notify(ChordId),
+	Ping,
+	Leave {
+		leaving: ChordId,
+		predecessor: Option<ChordId>,
+		successors: Vec<ChordId>,
+	},
+}
+
+#[derive(Debug, Clone)]
+pub enum ChordResponse {
+	Step(ChordStep),
+	Predecessor(Option<ChordId>),
+	SuccessorList(Vec<ChordId>),
+	Ack,
+}
+
+/*
+	ChordBus
+	An in-process message bus between simulated nodes. A node that has
+	crashed or left is simply no longer on the bus.
+*/
+#[derive(Clone, Default)]
+pub struct ChordBus {
+	nodes: Arc<DashMap<ChordId, Arc<SimulatedNode>>>,
+	messages: Arc<AtomicUsize>,
+}
+
+impl ChordBus {
+	pub fn node(&self, id: &ChordId) -> Option<Arc<SimulatedNode>> {
+		self.nodes.get(id).map(|node| node.clone())
+	}
+
+	// the number of messages sent over the bus
+	pub fn messages(&self) -> usize {
+		self.messages.load(Ordering::Relaxed)
+	}
+
+	pub fn send(
+		&self,
+		to: &ChordId,
+		request: ChordRequest,
+	) -> Result<ChordResponse, ChordError> {
+		self.messages.fetch_add(1, Ordering::Relaxed);
+		let node = self.node(to).ok_or(ChordError::NodeUnreachable)?;
+
+		Ok(match request {
+			ChordRequest::FindSuccessorStep(id) => {
+				ChordResponse::Step(node.find_successor_step(&id))
+			}
+			ChordRequest::Predecessor => {
+				ChordResponse::Predecessor(node.predecessor())
+			}
+			ChordRequest::SuccessorList => {
+				ChordResponse::SuccessorList(node.successor_list())
+			}
+			ChordRequest::Notify(candidate) => {
+				node.notify(&candidate);
+				ChordResponse::Ack
+			}
+			ChordRequest::Ping => ChordResponse::Ack,
+			ChordRequest::Leave {
+				leaving,
+				predecessor,
+				successors,
+			} => {
+				node.handle_leave(&leaving, predecessor, successors);
+				ChordResponse::Ack
+			}
+		})
+	}
+}
+
+#[async_trait]
+impl ChordTransport for ChordBus {
+	async fn find_successor_step(
+		&self,
+		node: &ChordId,
+		id: &ChordId,
+	) -> Result<ChordStep, ChordError> {
+		match self.send(node, ChordRequest::FindSuccessorStep(id.clone()))? {
+			ChordResponse::Step(step) => Ok(step),
+			_ => unreachable!(),
+		}
+	}
+
+	async fn predecessor(
+		&self,
+		node: &ChordId,
+	) -> Result<Option<ChordId>, ChordError> {
+		match self.send(node, ChordRequest::Predecessor)? {
+			ChordResponse::Predecessor(predecessor) => Ok(predecessor),
+			_ => unreachable!(),
+		}
+	}
+
+	async fn successor_list(
+		&self,
+		node: &ChordId,
+	) -> Result<Vec<ChordId>, ChordError> {
+		match self.send(node, ChordRequest::SuccessorList)? {
+			ChordResponse::SuccessorList(successors) => Ok(successors),
+			_ => unreachable!(),
+		}
+	}
+
+	async fn notify(
+		&self,
+		node: &ChordId,
+		candidate: &ChordId,
+	) -> Result<(), ChordError> {
+		self.send(node, ChordRequest::Notify(candidate.clone()))
+			.map(|_| ())
+	}
+
+	async fn ping(&self, node: &ChordId) -> Result<(), ChordError> {
+		self.send(node, ChordRequest::Ping).map(|_| ())
+	}
+
+	async fn leave(
+		&self,
+		node: &ChordId,
+		leaving: &ChordId,
+		predecessor: Option<ChordId>,
+		successors: Vec<ChordId>,
+	) -> Result<(), ChordError> {
+		self.send(
+			node,
+			ChordRequest::Leave {
+				leaving: leaving.clone(),
+				predecessor,
+				successors,
+			},
+		)
+		.map(|_| ())
+	}
+}
+
+/*
+	ChordSimulator
+	Drives a ring of simulated nodes. Every random choice, from node ids to
+	the order nodes tick in, comes from one seeded rng so runs are repeatable.
+*/
+pub struct ChordSimulator {
+	rng: StdRng,
+	bus: ChordBus,
+}
+
+impl ChordSimulator {
+	pub fn new(seed: u64) -> Self {
+		Self {
+			rng: StdRng::seed_from_u64(seed),
+			bus: ChordBus::default(),
+		}
+	}
+
+	pub fn bus(&self) -> &ChordBus {
+		&self.bus
+	}
+
+	pub fn len(&self) -> usize {
+		self.bus.nodes.len()
+	}
+
+	// the live node ids in ring order
+	pub fn ids(&self) -> Vec<ChordId> {
+		let mut ids: Vec<ChordId> = self
+			.bus
+			.nodes
+			.iter()
+			.map(|node| node.key().clone())
+			.collect();
+		ids.sort();
+		ids
+	}
+
+	pub fn node(&self, id: &ChordId) -> Arc<SimulatedNode> {
+		self.bus.node(id).unwrap()
+	}
+
+	pub fn random_id(&mut self) -> ChordId {
+		let mut bytes = [0u8; 32];
+		self.rng.fill_bytes(&mut bytes);
+		V256::new(0, &bytes)
+	}
+
+	fn random_node(&mut self) -> Option<ChordId> {
+		self.ids().choose(&mut self.rng).cloned()
+	}
+
+	/*
+		Membership
+	*/
+
+	// add a node, joining through a random live node if there is one
+	pub async fn join(&mut self) -> ChordId {
+		let id = self.random_id();
+		let bootstrap = self.random_node();
+		let node = Arc::new(ChordNode::new(id.clone(), self.bus.clone()));
+		self.bus.nodes.insert(id.clone(), node.clone());
+
+		// a failed join is retried by the node's next stabilize
+		if let Some(bootstrap) = bootstrap {
+			let _ = node.join(&bootstrap).await;
+		}
+		id
+	}
+
+	// a random node leaves gracefully
+	pub async fn leave(&mut self) -> ChordId {
+		let id = self.random_node().unwrap();
+		self.node(&id).leave().await.unwrap();
+		self.bus.nodes.remove(&id);
+		id
+	}
+
+	// a random node disappears without telling anyone
+	pub fn crash(&mut self) -> ChordId {
+		let id = self.random_node().unwrap();
+		self.bus.nodes.remove(&id);
+		id
+	}
+
+	/*
+		Ticks
+	*/
+
+	// every live node runs its periodic maintenance once, in a random order
+	pub async fn tick(&mut self) {
+		let mut ids = self.ids();
+		ids.shuffle(&mut self.rng);
+
+		for id in ids {
+			// the node may have been removed since the tick began
+			let Some(node) = self.bus.node(&id) else {
+				continue;
+			};
+			node.check_predecessor().await;
+			let _ = node.stabilize().await;
+			let _ = node.fix_fingers().await;
+		}
+	}
+
+	pub async fn run(&mut self, ticks: usize) {
+		for _ in 0..ticks {
+			self.tick().await;
+		}
+	}
+
+	/*
+		Invariants
+	*/
+
+	// every node's successor and predecessor are its ring neighbours
+	pub fn assert_ring(&self) {
+		let ids = self.ids();
+		for (i, id) in ids.iter().enumerate() {
+			let node = self.node(id);
+			let successor = &ids[(i + 1) % ids.len()];
+			let predecessor = &ids[(i + ids.len() - 1) % ids.len()];
+
+			assert_eq!(&node.successor(), successor, "successor of node {i}");
+			if ids.len() > 1 {
+				assert_eq!(
+					node.predecessor().as_ref(),
+					Some(predecessor),
+					"predecessor of node {i}"
+				);
+			}
+		}
+	}
+
+	// look up random keys from random nodes, returning the most hops taken
+	pub async fn assert_lookups(&mut self, lookups: usize) -> usize {
+		let ids = self.ids();
+		let mut max_hops = 0;
+
+		for _ in 0..lookups {
+			let key = self.random_id();
+			let from = self.random_node().unwrap();
+			let expected = ids.iter().find(|id| *id >= &key).unwrap_or(&ids[0]);
+
+			let lookup = self.node(&from).lookup(&key).await.unwrap();
+			assert_eq!(&lookup.successor, expected);
+			max_hops = max_hops.max(lookup.hops);
+		}
+		max_hops
+	}
+}
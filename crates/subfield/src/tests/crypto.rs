@@ -1,3 +1,4 @@
+use super::dht::{pipe, PipeStream};
 use crate::*;
 
 /*
@@ -7,11 +8,215 @@ use crate::*;
 fn test_cipher() {
 	let cipher = Cipher::random();
 	let plaintext = b"hello world";
-	let ciphertext = cipher.encrypt(plaintext);
+	let ciphertext = cipher.encrypt(plaintext).unwrap();
 	let decrypted = cipher.decrypt(&ciphertext).unwrap();
 	assert_eq!(plaintext.to_vec(), decrypted);
 }
 
+#[test]
+fn test_cipher_errors() {
+	let cipher = Cipher::random();
+	let ciphertext = cipher.encrypt(b"hello world").unwrap();
+
+	// any altered byte fails authentication, nonce, data or tag
+	for i in [0, 12, ciphertext.len() - 1] {
+		let mut tampered = ciphertext.clone();
+		tampered[i] ^= 1;
+		let decrypted = cipher.decrypt(&tampered);
+		assert!(matches!(decrypted, Err(CipherError::DecryptionFailed)));
+	}
+	let truncated = cipher.decrypt(&ciphertext[..ciphertext.len() - 1]);
+	assert!(matches!(truncated, Err(CipherError::DecryptionFailed)));
+	let short = cipher.decrypt(&ciphertext[..4]);
+	assert!(matches!(short, Err(CipherError::InvalidNonce)));
+
+	// as does the wrong key
+	let decrypted = Cipher::random().decrypt(&ciphertext);
+	assert!(matches!(decrypted, Err(CipherError::DecryptionFailed)));
+
+	// keys must be 32 bytes
+	let key = Cipher::new(V256::new(0, &[0u8; 16]));
+	assert!(matches!(key, Err(CipherError::InvalidKey)));
+}
+
+#[test]
+fn test_cipher_aad() {
+	let key = CompleteKey::random();
+	let aad = key.hash().to_vec();
+	for algorithm in [
+		CipherAlgorithm::ChaCha20Poly1305,
+		CipherAlgorithm::XChaCha20Poly1305,
+	] {
+		let cipher = Cipher::random().with_algorithm(algorithm);
+		let ciphertext = cipher.encrypt_with_aad(b"hello world", &aad).unwrap();
+		assert_eq!(
+			ciphertext.len(),
+			algorithm.nonce_length() + b"hello world".len() + 16
+		);
+		let decrypted = cipher.decrypt_with_aad(&ciphertext, &aad).unwrap();
+		assert_eq!(decrypted, b"hello world");
+
+		// the ciphertext only decrypts with the data it was bound to
+		let other = CompleteKey::random().hash().to_vec();
+		let decrypted = cipher.decrypt_with_aad(&ciphertext, &other);
+		assert!(matches!(decrypted, Err(CipherError::DecryptionFailed)));
+		let decrypted = cipher.decrypt(&ciphertext);
+		assert!(matches!(decrypted, Err(CipherError::DecryptionFailed)));
+	}
+
+	// the same key under the other algorithm cannot decrypt
+	let cipher = Cipher::random();
+	let ciphertext = cipher.encrypt(b"hello world").unwrap();
+	let x = Cipher::new(cipher.secret().clone())
+		.unwrap()
+		.with_algorithm(CipherAlgorithm::XChaCha20Poly1305);
+	assert!(x.decrypt(&ciphertext).is_err());
+}
+
+// encrypt the data through a stream, written in pieces of the given size
+fn encrypt_stream(cipher: &Cipher, data: &[u8], piece: usize) -> Vec<u8> {
+	let mut encryptor = cipher.stream_encryptor();
+	let mut ciphertext = vec![];
+	for piece in data.chunks(piece) {
+		ciphertext.extend(encryptor.update(piece).unwrap());
+	}
+	ciphertext.extend(encryptor.finish().unwrap());
+	ciphertext
+}
+
+fn decrypt_stream(
+	cipher: &Cipher,
+	ciphertext: &[u8],
+	piece: usize,
+) -> Result<Vec<u8>, CipherError> {
+	let mut decryptor = cipher.stream_decryptor();
+	let mut data = vec![];
+	for piece in ciphertext.chunks(piece) {
+		data.extend(decryptor.update(piece)?);
+	}
+	data.extend(decryptor.finish()?);
+	Ok(data)
+}
+
+#[test]
+fn test_cipher_stream() {
+	let cipher =
+		Cipher::random().with_algorithm(CipherAlgorithm::XChaCha20Poly1305);
+	let chunk = CIPHER_STREAM_CHUNK;
+	for len in [0, 1, chunk - 1, chunk, chunk + 1, 3 * chunk + 7] {
+		let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+		for piece in [1000, chunk, 5 * chunk] {
+			let ciphertext = encrypt_stream(&cipher, &data, piece);
+			let decrypted = decrypt_stream(&cipher, &ciphertext, 777).unwrap();
+			assert_eq!(decrypted, data);
+		}
+	}
+
+	let data = vec![7u8; 3 * chunk + 7];
+	let ciphertext = encrypt_stream(&cipher, &data, chunk);
+	// the header, then three full chunks and the last
+	let header = 19;
+	let sealed = chunk + 16;
+	assert_eq!(ciphertext.len(), header + 3 * sealed + 7 + 16);
+
+	// cut short at a chunk boundary, in a chunk, or before the first
+	let cut = &ciphertext[..header + 2 * sealed];
+	let decrypted = decrypt_stream(&cipher, cut, chunk);
+	assert!(matches!(decrypted, Err(CipherError::DecryptionFailed)));
+	let cut = &ciphertext[..ciphertext.len() - 1];
+	assert!(decrypt_stream(&cipher, cut, chunk).is_err());
+	let cut = &ciphertext[..header + 8];
+	let decrypted = decrypt_stream(&cipher, cut, chunk);
+	assert!(matches!(decrypted, Err(CipherError::StreamTruncated)));
+
+	// chunks swapped or altered
+	let mut swapped = ciphertext[..header].to_vec();
+	swapped.extend(&ciphertext[header + sealed..header + 2 * sealed]);
+	swapped.extend(&ciphertext[header..header + sealed]);
+	swapped.extend(&ciphertext[header + 2 * sealed..]);
+	assert!(decrypt_stream(&cipher, &swapped, chunk).is_err());
+	let mut tampered = ciphertext.clone();
+	tampered[header + sealed + 5] ^= 1;
+	assert!(decrypt_stream(&cipher, &tampered, chunk).is_err());
+
+	// a finished stream takes no more
+	let mut encryptor = cipher.stream_encryptor();
+	encryptor.finish().unwrap();
+	let updated = encryptor.update(b"more");
+	assert!(matches!(updated, Err(CipherError::StreamFinished)));
+}
+
+/*
+   Kdf
+*/
+#[test]
+fn test_derive_key() {
+	let material = b"uniformly random key material";
+	let encryption = derive_key(KdfContext::Encryption, material);
+	assert_eq!(encryption, derive_key(KdfContext::Encryption, material));
+	assert_eq!(encryption.data().len(), 32);
+
+	// each context derives an unrelated key
+	let keys = [
+		KdfContext::Encryption,
+		KdfContext::Mac,
+		KdfContext::RecordId,
+		KdfContext::PrivateKey,
+	]
+	.map(|context| derive_key(context, material).to_string());
+	assert_eq!(keys.iter().unique().count(), keys.len());
+
+	// both sides of a shared secret derive the same cipher key
+	let alice = Keypair::random();
+	let bob = Keypair::random();
+	let key = derive_shared_cipher_key(&alice, bob.public_key());
+	assert_eq!(key, derive_shared_cipher_key(&bob, alice.public_key()));
+	assert!(key != alice.shared_secret(bob.public_key()));
+	let ciphertext = Cipher::new(key.clone()).unwrap().encrypt(b"hi").unwrap();
+	let decrypted = Cipher::new(key).unwrap().decrypt(&ciphertext).unwrap();
+	assert_eq!(decrypted, b"hi");
+}
+
+#[test]
+fn test_password_kdf() {
+	// cheap params, the defaults take a while
+	let params = PasswordKdfParams::new(256, 1, 1);
+	let salt = random_salt();
+	let key = derive_password_key(b"password", &salt, &params).unwrap();
+	assert_eq!(
+		key,
+		derive_password_key(b"password", &salt, &params).unwrap()
+	);
+
+	// a different password, salt or params derive a different key
+	let other = derive_password_key(b"passw0rd", &salt, &params).unwrap();
+	assert!(key != other);
+	let other =
+		derive_password_key(b"password", &random_salt(), &params).unwrap();
+	assert!(key != other);
+	let costlier = PasswordKdfParams::new(256, 2, 1);
+	let other = derive_password_key(b"password", &salt, &costlier).unwrap();
+	assert!(key != other);
+
+	// the private key and cipher key are separate subkeys
+	let private_key =
+		derive_private_key_from_password(b"password", &salt, &params).unwrap();
+	let keypair = Keypair::new(private_key);
+	let signature = keypair.sign(b"hello");
+	assert!(keypair.public_key().verify(b"hello", &signature).unwrap());
+	let cipher_key =
+		derive_cipher_key_from_password(b"password", &salt, &params).unwrap();
+	assert!(&cipher_key != keypair.private_key().versioned_bytes());
+	assert!(cipher_key != key);
+
+	// short salts and impossible params are refused
+	let short = derive_password_key(b"password", b"salt", &params);
+	assert!(matches!(short, Err(KdfError::InvalidSalt)));
+	let invalid = PasswordKdfParams::new(256, 0, 1);
+	let invalid = derive_password_key(b"password", &salt, &invalid);
+	assert!(matches!(invalid, Err(KdfError::InvalidParams)));
+}
+
 /*
    Noise
 */
@@ -39,6 +244,197 @@ fn test_noise() {
 	assert_eq!(data.to_vec(), decrypted);
 }
 
+#[test]
+fn test_noise_errors() {
+	let mut initiator = Noise::initiator();
+	let mut responder = Noise::responder();
+
+	// nothing is encrypted before the handshake, and messages are taken in
+	// turn
+	let encrypted = initiator.encrypt(b"hello world");
+	assert!(matches!(encrypted, Err(NoiseError::InvalidState)));
+	let decrypted = responder.decrypt(b"hello world");
+	assert!(matches!(decrypted, Err(NoiseError::InvalidState)));
+	let written = responder.handshake_step_1();
+	assert!(matches!(written, Err(NoiseError::InvalidState)));
+
+	// a tampered handshake message is refused
+	let mut other = Noise::initiator();
+	let message = other.handshake_step_1().unwrap();
+	let mut message = Noise::responder().handshake_step_2(&message).unwrap();
+	*message.last_mut().unwrap() ^= 1;
+	let read = other.handshake_step_3(&message);
+	assert!(matches!(read, Err(NoiseError::FailedToDecrypt)));
+
+	let message = initiator.handshake_step_1().unwrap();
+	let message = responder.handshake_step_2(&message).unwrap();
+	initiator.handshake_step_3(&message).unwrap();
+
+	// the handshake cannot be run again once finished
+	let written = initiator.handshake_step_1();
+	assert!(matches!(written, Err(NoiseError::InvalidState)));
+
+	// tampered, truncated and replayed messages are refused
+	let encrypted = initiator.encrypt(b"hello world").unwrap();
+	let mut tampered = encrypted.clone();
+	tampered[0] ^= 1;
+	let decrypted = responder.decrypt(&tampered);
+	assert!(matches!(decrypted, Err(NoiseError::FailedToDecrypt)));
+	let decrypted = responder.decrypt(&encrypted[..8]);
+	assert!(matches!(decrypted, Err(NoiseError::FailedToDecrypt)));
+	responder.decrypt(&encrypted).unwrap();
+	let decrypted = responder.decrypt(&encrypted);
+	assert!(matches!(decrypted, Err(NoiseError::FailedToDecrypt)));
+}
+
+// run the handshake to the end, for two or three message patterns
+fn handshake(
+	initiator: &mut Noise,
+	responder: &mut Noise,
+) -> Result<(), NoiseError> {
+	let message = initiator.handshake_step_1()?;
+	let message = responder.handshake_step_2(&message)?;
+	if let Some(message) = initiator.handshake_step_3(&message)? {
+		responder.handshake_step_4(&message)?;
+	}
+	Ok(())
+}
+
+#[test]
+fn test_noise_patterns() {
+	let alice = Keypair::random();
+	let bob = Keypair::random();
+	let noise = |role, pattern, keypair: &Keypair, remote: Option<&Keypair>| {
+		let remote = remote.map(|keypair| keypair.public_key().clone());
+		Noise::new(role, pattern, keypair.clone(), remote).unwrap()
+	};
+	use NoiseRole::{Initiator, Responder};
+
+	// XX learns both keys, IK has the initiator know the responder's
+	// beforehand, KK has both know each other's
+	let sessions = [
+		(NoisePattern::XX, None, None),
+		(NoisePattern::XX, Some(&bob), Some(&alice)),
+		(NoisePattern::IK, Some(&bob), None),
+		(NoisePattern::IK, Some(&bob), Some(&alice)),
+		(NoisePattern::KK, Some(&bob), Some(&alice)),
+	];
+	for (pattern, initiator_remote, responder_remote) in sessions {
+		let mut initiator = noise(Initiator, pattern, &alice, initiator_remote);
+		let mut responder = noise(Responder, pattern, &bob, responder_remote);
+		handshake(&mut initiator, &mut responder).unwrap();
+		assert!(initiator.is_handshake_finished());
+		assert!(responder.is_handshake_finished());
+		assert_eq!(
+			initiator.remote_public_key(),
+			Some(bob.public_key().clone())
+		);
+		assert_eq!(
+			responder.remote_public_key(),
+			Some(alice.public_key().clone())
+		);
+
+		let encrypted = initiator.encrypt(b"hello world").unwrap();
+		let decrypted = responder.decrypt(&encrypted).unwrap();
+		assert_eq!(decrypted, b"hello world");
+	}
+
+	// the remote must hold the key expected
+	let mallory = Keypair::random();
+	let sessions = [
+		(NoisePattern::XX, &mallory, Some(&bob), None),
+		(NoisePattern::XX, &bob, None, Some(&mallory)),
+		(NoisePattern::IK, &mallory, Some(&bob), None),
+		(NoisePattern::IK, &bob, Some(&bob), Some(&mallory)),
+		(NoisePattern::KK, &bob, Some(&bob), Some(&mallory)),
+	];
+	for (pattern, responder_keypair, initiator_remote, responder_remote) in
+		sessions
+	{
+		let mut initiator = noise(Initiator, pattern, &alice, initiator_remote);
+		let mut responder =
+			noise(Responder, pattern, responder_keypair, responder_remote);
+		assert!(handshake(&mut initiator, &mut responder).is_err());
+		assert!(!responder.is_handshake_finished());
+	}
+
+	// patterns that know the remote's key need it given
+	let missing = Noise::new(Initiator, NoisePattern::IK, alice.clone(), None);
+	assert!(matches!(missing, Err(NoiseError::MissingRemoteKey)));
+	let missing = Noise::new(Responder, NoisePattern::KK, bob.clone(), None);
+	assert!(matches!(missing, Err(NoiseError::MissingRemoteKey)));
+}
+
+// a handshake over a pipe, rekeying both directions every interval bytes
+async fn noise_streams(
+	rekey_intervals: (u64, u64),
+) -> (NoiseStream<PipeStream>, NoiseStream<PipeStream>) {
+	let (a, b) = pipe();
+	let alice = Keypair::random();
+	let bob = Keypair::random();
+	let initiator = Noise::new(
+		NoiseRole::Initiator,
+		NoisePattern::XX,
+		alice,
+		Some(bob.public_key().clone()),
+	)
+	.unwrap();
+	let responder =
+		Noise::new(NoiseRole::Responder, NoisePattern::XX, bob, None).unwrap();
+	let (initiator, responder) = tokio::join!(
+		NoiseStream::handshake(a, initiator),
+		NoiseStream::handshake(b, responder),
+	);
+	let initiator = initiator.unwrap().with_rekey_interval(rekey_intervals.0);
+	let responder = responder.unwrap().with_rekey_interval(rekey_intervals.1);
+	assert_eq!(
+		responder.remote_public_key(),
+		Some(initiator.noise().keypair().public_key().clone())
+	);
+	(initiator, responder)
+}
+
+#[tokio::test]
+async fn test_noise_stream() {
+	let (mut initiator, mut responder) = noise_streams((4096, 4096)).await;
+
+	// writes larger than a noise message are split across frames, and frames
+	// written back to back are read apart
+	let large: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+	initiator.write_all(&large).await.unwrap();
+	initiator.write_all(b"short").await.unwrap();
+	initiator.write_all(b"").await.unwrap();
+	initiator.write_all(b"messages").await.unwrap();
+	initiator.flush().await.unwrap();
+	let mut read = vec![0u8; large.len() + 13];
+	responder.read_exact(&mut read).await.unwrap();
+	assert_eq!(&read[..large.len()], &large[..]);
+	assert_eq!(&read[large.len()..], b"shortmessages");
+
+	// both directions keep working across many rekeys
+	for i in 0..100u32 {
+		let message = vec![i as u8; 1000];
+		responder.write_all(&message).await.unwrap();
+		responder.flush().await.unwrap();
+		let mut read = vec![0u8; 1000];
+		initiator.read_exact(&mut read).await.unwrap();
+		assert_eq!(read, message);
+	}
+
+	// the stream ends cleanly between frames
+	initiator.close().await.unwrap();
+	let mut rest = vec![];
+	assert_eq!(responder.read_to_end(&mut rest).await.unwrap(), 0);
+
+	// sides that rekey at different frames cannot read each other
+	let (mut initiator, mut responder) = noise_streams((1024, 4096)).await;
+	initiator.write_all(&[0u8; 2048]).await.unwrap();
+	initiator.write_all(&[0u8; 2048]).await.unwrap();
+	initiator.flush().await.unwrap();
+	let mut read = vec![0u8; 4096];
+	assert!(responder.read_exact(&mut read).await.is_err());
+}
+
 /*
    Keypair
 */
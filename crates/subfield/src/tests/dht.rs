@@ -0,0 +1,803 @@
+use crate::*;
+use asynchronous_codec::{Decoder, Encoder};
+use futures::channel::mpsc;
+use futures::io::Cursor;
+use futures::task::{Context, Poll};
+use std::io;
+
+// an in-memory stream that reads from a fixed buffer and records every write
+// if held open, reads stay pending once the buffer is exhausted
+pub struct MemoryStream {
+	reader: Cursor<Vec<u8>>,
+	pub writer: Vec<u8>,
+	hold_open: bool,
+}
+
+impl MemoryStream {
+	pub fn new(requests: Vec<SubfieldRequest>, hold_open: bool) -> Self {
+		let mut codec = SubfieldRequestCodec::default();
+		let mut buffer = BytesMut::new();
+		for request in requests {
+			codec.encode(request, &mut buffer).unwrap();
+		}
+		Self::from_buffer(buffer, hold_open)
+	}
+
+	// a stream read by the dialing side, as an upstream server
+	pub fn with_responses(
+		responses: Vec<SubfieldResponse>,
+		hold_open: bool,
+	) -> Self {
+		let mut codec = SubfieldResponseCodec::default();
+		let mut buffer = BytesMut::new();
+		for response in responses {
+			codec.encode(response, &mut buffer).unwrap();
+		}
+		Self::from_buffer(buffer, hold_open)
+	}
+
+	fn from_buffer(buffer: BytesMut, hold_open: bool) -> Self {
+		Self {
+			reader: Cursor::new(buffer.to_vec()),
+			writer: Vec::new(),
+			hold_open,
+		}
+	}
+
+	// decode every response written to the stream
+	pub fn responses(&self) -> Vec<SubfieldResponse> {
+		let mut codec = SubfieldRequestCodec::default();
+		let mut buffer = BytesMut::from(self.writer.as_slice());
+		let mut responses = Vec::new();
+		while let Some(response) = codec.decode(&mut buffer).unwrap() {
+			responses.push(response);
+		}
+		responses
+	}
+
+	// decode every request written to the stream
+	pub fn requests(&self) -> Vec<SubfieldRequest> {
+		let mut codec = SubfieldResponseCodec::default();
+		let mut buffer = BytesMut::from(self.writer.as_slice());
+		let mut requests = Vec::new();
+		while let Some(request) = codec.decode(&mut buffer).unwrap() {
+			requests.push(request);
+		}
+		requests
+	}
+}
+
+impl AsyncRead for MemoryStream {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<io::Result<usize>> {
+		let hold_open = self.hold_open;
+		match Pin::new(&mut self.reader).poll_read(cx, buf) {
+			Poll::Ready(Ok(0)) if hold_open => Poll::Pending,
+			poll => poll,
+		}
+	}
+}
+
+impl AsyncWrite for MemoryStream {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		AsyncWrite::poll_write(Pin::new(&mut self.writer), cx, buf)
+	}
+
+	fn poll_flush(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<io::Result<()>> {
+		AsyncWrite::poll_flush(Pin::new(&mut self.writer), cx)
+	}
+
+	fn poll_close(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<io::Result<()>> {
+		AsyncWrite::poll_close(Pin::new(&mut self.writer), cx)
+	}
+}
+
+// one end of an in-memory duplex stream, reads what the other end writes
+pub struct PipeStream {
+	reader: stream::IntoAsyncRead<mpsc::UnboundedReceiver<io::Result<Vec<u8>>>>,
+	writer: mpsc::UnboundedSender<io::Result<Vec<u8>>>,
+}
+
+pub fn pipe() -> (PipeStream, PipeStream) {
+	let (a_writer, a_reader) = mpsc::unbounded();
+	let (b_writer, b_reader) = mpsc::unbounded();
+	let a = PipeStream {
+		reader: b_reader.into_async_read(),
+		writer: a_writer,
+	};
+	let b = PipeStream {
+		reader: a_reader.into_async_read(),
+		writer: b_writer,
+	};
+	(a, b)
+}
+
+impl AsyncRead for PipeStream {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<io::Result<usize>> {
+		Pin::new(&mut self.reader).poll_read(cx, buf)
+	}
+}
+
+impl AsyncWrite for PipeStream {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		let sent = self.writer.unbounded_send(Ok(buf.to_vec()));
+		Poll::Ready(match sent {
+			Ok(()) => Ok(buf.len()),
+			Err(_) => Err(io::ErrorKind::BrokenPipe.into()),
+		})
+	}
+
+	fn poll_flush(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+	) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_close(
+		self: Pin<&mut Self>,
+		_cx: &mut Context<'_>,
+	) -> Poll<io::Result<()>> {
+		self.writer.close_channel();
+		Poll::Ready(Ok(()))
+	}
+}
+
+fn echo_request(message: &str) -> SubfieldRequest {
+	SubfieldRequest::new(
+		RoutingKey::random(),
+		SubfieldRequestBody::Echo(EchoRequest {
+			message: message.to_string(),
+		}),
+	)
+}
+
+/*
+   Codec
+*/
+#[test]
+fn test_codec_round_trip() {
+	let mut client_codec = SubfieldRequestCodec::default();
+	let mut server_codec = SubfieldResponseCodec::default();
+
+	let mut buffer = BytesMut::new();
+	client_codec
+		.encode(echo_request("hello"), &mut buffer)
+		.unwrap();
+	client_codec
+		.encode(echo_request("world"), &mut buffer)
+		.unwrap();
+
+	// a partial frame is not decoded
+	let mut partial = BytesMut::from(&buffer[..buffer.len() / 4]);
+	assert!(server_codec.decode(&mut partial).unwrap().is_none());
+
+	// two concatenated frames decode one at a time
+	for expected in ["hello", "world"] {
+		let request = server_codec.decode(&mut buffer).unwrap().unwrap();
+		match request.body {
+			SubfieldRequestBody::Echo(echo) => {
+				assert_eq!(echo.message, expected)
+			}
+			_ => panic!("unexpected request body"),
+		}
+	}
+	assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_codec_max_frame_size() {
+	let mut client_codec = SubfieldRequestCodec::new(16);
+	let mut buffer = BytesMut::new();
+	assert!(matches!(
+		client_codec.encode(echo_request(&"a".repeat(64)), &mut buffer),
+		Err(SubfieldCodecError::FrameTooLarge(_))
+	));
+
+	// a frame that declares a length over the limit is rejected
+	let mut large_codec = SubfieldRequestCodec::default();
+	large_codec
+		.encode(echo_request(&"a".repeat(64)), &mut buffer)
+		.unwrap();
+	let mut server_codec = SubfieldResponseCodec::new(16);
+	assert!(matches!(
+		server_codec.decode(&mut buffer),
+		Err(SubfieldCodecError::FrameTooLarge(_))
+	));
+}
+
+#[tokio::test]
+async fn test_send_and_recv_request() {
+	let mut client = client_stream(Cursor::new(Vec::new()));
+	send_request(&mut client, echo_request("hello"))
+		.await
+		.unwrap();
+
+	let mut written = client.into_inner();
+	written.set_position(0);
+	let mut server = server_stream(written);
+
+	let request = recv_request(&mut server).await.unwrap();
+	assert!(matches!(request.body, SubfieldRequestBody::Echo(_)));
+
+	// the stream is exhausted
+	assert!(matches!(
+		recv_request(&mut server).await,
+		Err(SubfieldError::FailedToReadStream)
+	));
+}
+
+/*
+   Dispatcher
+*/
+pub struct TestHandler;
+
+#[async_trait]
+impl RecordHandler for TestHandler {
+	async fn get_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: GetRecordRequest,
+	) -> GetRecordResponse {
+		Err(GetRecordFailure::Unknown)
+	}
+
+	async fn put_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: PutRecordRequest,
+	) -> PutRecordResponse {
+		Ok(PutRecordSuccess {})
+	}
+
+	async fn delete_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: DeleteRecordRequest,
+	) -> DeleteRecordResponse {
+		Ok(DeleteRecordSuccess {})
+	}
+
+	async fn list_records(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: ListRecordsRequest,
+	) -> Result<ListRecordsStream, ListRecordsFailure> {
+		Ok(
+			stream::iter(vec![Ok(ListRecordsSuccess::End { next: None })])
+				.boxed(),
+		)
+	}
+}
+
+#[async_trait]
+impl PubsubHandler for TestHandler {
+	async fn subscribe(
+		&self,
+		peer: PeerId,
+		request: SubscribeRequest,
+	) -> Result<Subscription, SubscribeFailure> {
+		// two events, then the subscription ends
+		let event = || SubscribeSuccess::Subscribed {
+			lease: request.lease,
+		};
+		Ok(Subscription {
+			lease: request.lease,
+			events: stream::iter(vec![Ok(event()), Ok(event())]).boxed(),
+		})
+	}
+
+	async fn unsubscribe(
+		&self,
+		peer: PeerId,
+		request: UnsubscribeRequest,
+	) -> UnsubscribeResponse {
+		Ok(UnsubscribeSuccess {})
+	}
+
+	async fn publish(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: PublishRequest,
+	) -> PublishResponse {
+		Ok(PublishSuccess { delivered: 0 })
+	}
+}
+
+fn test_dispatcher() -> Dispatcher {
+	Dispatcher::new(
+		Arc::new(DefaultSystemHandler),
+		Arc::new(TestHandler),
+		Arc::new(TestHandler),
+	)
+}
+
+#[tokio::test]
+async fn test_dispatcher_oneshot() {
+	let dispatcher = test_dispatcher();
+
+	// a oneshot stream is answered once, even if more requests follow
+	let mut stream = MemoryStream::new(
+		vec![echo_request("hello"), echo_request("world")],
+		true,
+	);
+	dispatcher
+		.serve_stream(PeerId::random(), &mut stream)
+		.await
+		.unwrap();
+
+	let responses = stream.responses();
+	assert_eq!(responses.len(), 1);
+	match &responses[0] {
+		SubfieldResponse::Echo(Ok(echo)) => assert_eq!(echo.message, "hello"),
+		_ => panic!("unexpected response"),
+	}
+}
+
+#[tokio::test]
+async fn test_dispatcher_streaming() {
+	let dispatcher = test_dispatcher();
+	let subscribe = SubfieldRequest::new(
+		RoutingKey::random(),
+		SubfieldRequestBody::Subscribe(SubscribeRequest {
+			key: PartialKey::random(),
+			lease: SUBSCRIPTION_MAX_LEASE,
+		}),
+	);
+
+	// a streaming stream stays open after the subscription events are sent
+	let mut stream = MemoryStream::new(vec![subscribe], true);
+	let served = tokio::time::timeout(
+		std::time::Duration::from_millis(100),
+		dispatcher.serve_stream(PeerId::random(), &mut stream),
+	)
+	.await;
+	assert!(served.is_err());
+
+	// the acknowledgement and both events
+	let responses = stream.responses();
+	assert_eq!(responses.len(), 3);
+	assert!(responses.iter().all(|response| matches!(
+		response,
+		SubfieldResponse::Subscribe(Ok(_))
+	)));
+}
+
+/*
+   Forwarding
+*/
+pub fn random_peer_id() -> PeerId {
+	Keypair::random().public_key().to_libp2p_peer_id().unwrap()
+}
+
+// a put record request routed to the given server's id
+fn routed_request(peer: PeerId) -> SubfieldRequest {
+	let mut key = PartialKey::random();
+	key.signer = Some(peer_id_to_v256(peer).unwrap());
+	SubfieldRequest::new(
+		RoutingKey::Signer(key),
+		SubfieldRequestBody::PutRecord(PutRecordRequest {
+			record_bytes: vec![],
+			signature: RecordSignature::Signer(V512::random512()),
+		}),
+	)
+}
+
+#[test]
+fn test_peer_id_to_v256() {
+	let keypair = Keypair::random();
+	let public_key = keypair.public_key();
+	let peer = public_key.to_libp2p_peer_id().unwrap();
+	assert_eq!(
+		&peer_id_to_v256(peer).unwrap(),
+		public_key.versioned_bytes()
+	);
+}
+
+#[test]
+fn test_routing_table_route() {
+	let local = random_peer_id();
+	let remote = random_peer_id();
+	let table = RoutingTable::new(local).unwrap();
+
+	// with no known peers every request is answered locally
+	assert_eq!(table.route(&routed_request(remote)).unwrap(), Route::Local);
+	assert!(matches!(
+		table.closest_peer(&peer_id_to_v256(remote).unwrap(), &[]),
+		Err(SubfieldError::SelfIsClosest)
+	));
+
+	// a request keyed to a known peer is forwarded to it
+	table.add_peer(remote).unwrap();
+	assert_eq!(
+		table.route(&routed_request(remote)).unwrap(),
+		Route::Forward(remote)
+	);
+	assert_eq!(table.route(&routed_request(local)).unwrap(), Route::Local);
+
+	// system requests are never forwarded
+	let mut echo = echo_request("hello");
+	echo.routing_key = routed_request(remote).routing_key;
+	assert_eq!(table.route(&echo).unwrap(), Route::Local);
+
+	// peers that already forwarded the request are skipped
+	let mut request = routed_request(remote);
+	request.hops.push(peer_id_to_v256(remote).unwrap());
+	assert_eq!(table.route(&request).unwrap(), Route::Local);
+}
+
+#[test]
+fn test_routing_table_loop_and_hop_limit() {
+	let local = random_peer_id();
+	let remote = random_peer_id();
+	let table = RoutingTable::new(local).unwrap().with_max_hops(2);
+	table.add_peer(remote).unwrap();
+
+	// a request that already passed through this server is a loop
+	let mut request = routed_request(remote);
+	request.hops.push(table.local_id().clone());
+	assert!(matches!(
+		table.route(&request),
+		Err(SubfieldError::RoutingLoop)
+	));
+
+	// a request that used up its hops is not forwarded any further
+	let mut request = routed_request(remote);
+	request.hops = vec![V256::random256(), V256::random256()];
+	assert!(matches!(
+		table.route(&request),
+		Err(SubfieldError::HopLimitExceeded)
+	));
+
+	// but may still be answered by the closest server
+	let mut request = routed_request(local);
+	request.hops = vec![V256::random256(), V256::random256()];
+	assert_eq!(table.route(&request).unwrap(), Route::Local);
+}
+
+fn test_forwarder(local: PeerId) -> Forwarder {
+	Forwarder::new(
+		Arc::new(RoutingTable::new(local).unwrap()),
+		test_dispatcher(),
+		Behaviour::new().new_control(),
+	)
+}
+
+#[tokio::test]
+async fn test_forwarder_proxy() {
+	let local = random_peer_id();
+	let forwarder = test_forwarder(local);
+
+	let mut stream = MemoryStream::new(vec![], false);
+	let mut upstream = MemoryStream::with_responses(
+		vec![SubfieldResponse::PutRecord(Ok(PutRecordSuccess {}))],
+		false,
+	);
+	forwarder
+		.proxy(
+			server_stream(&mut stream),
+			&mut upstream,
+			routed_request(random_peer_id()),
+		)
+		.await
+		.unwrap();
+
+	// the forwarded request records this server as a hop
+	let requests = upstream.requests();
+	assert_eq!(requests.len(), 1);
+	assert_eq!(requests[0].hops, vec![peer_id_to_v256(local).unwrap()]);
+
+	// the upstream response is relayed back
+	let responses = stream.responses();
+	assert_eq!(responses.len(), 1);
+	assert!(matches!(responses[0], SubfieldResponse::PutRecord(Ok(_))));
+}
+
+#[tokio::test]
+async fn test_forwarder_answers_loops() {
+	let local = random_peer_id();
+	let forwarder = test_forwarder(local);
+
+	let mut request = routed_request(random_peer_id());
+	request.hops.push(peer_id_to_v256(local).unwrap());
+
+	let mut stream = MemoryStream::new(vec![request], false);
+	forwarder
+		.serve_stream(random_peer_id(), &mut stream)
+		.await
+		.unwrap();
+
+	let responses = stream.responses();
+	assert_eq!(responses.len(), 1);
+	assert!(matches!(
+		responses[0],
+		SubfieldResponse::PutRecord(Err(PutRecordFailure::ServiceError(
+			SubfieldError::RoutingLoop
+		)))
+	));
+}
+
+/*
+   Subscriber
+*/
+
+// hands out the given streams in order, then fails to open any more
+struct TestOpener {
+	streams: std::sync::Mutex<Vec<MemoryStream>>,
+	opened: std::sync::Mutex<Vec<PeerId>>,
+}
+
+#[async_trait]
+impl StreamOpener for TestOpener {
+	type Stream = MemoryStream;
+
+	async fn open(&self, peer: PeerId) -> Result<MemoryStream, SubfieldError> {
+		self.opened.lock().unwrap().push(peer);
+		let mut streams = self.streams.lock().unwrap();
+		match streams.is_empty() {
+			true => Err(SubfieldError::FailedToOpenStream),
+			false => Ok(streams.remove(0)),
+		}
+	}
+}
+
+fn subscription_event(key: &PartialKey) -> SubfieldResponse {
+	SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Message(Box::new(
+		SubscriptionEvent {
+			key: key.clone(),
+			record: GetRecordSuccess {
+				routing_key: RoutingKey::random(),
+				record_bytes: vec![],
+				signature: RecordSignature::Signer(V512::random512()),
+			},
+		},
+	))))
+}
+
+#[tokio::test]
+async fn test_subscriber_resubscribes() {
+	let remote = random_peer_id();
+	let table = Arc::new(RoutingTable::new(random_peer_id()).unwrap());
+	table.add_peer(remote).unwrap();
+
+	// the first server sends an event then closes, the second holds open
+	let routing_key = routed_request(remote).routing_key;
+	let key = routing_key.to_partial_key();
+	let subscribed = || {
+		SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed {
+			lease: SUBSCRIPTION_MAX_LEASE,
+		}))
+	};
+	let streams = vec![
+		MemoryStream::with_responses(
+			vec![subscribed(), subscription_event(&key)],
+			false,
+		),
+		MemoryStream::with_responses(
+			vec![subscribed(), subscription_event(&key)],
+			true,
+		),
+	];
+	let opener = TestOpener {
+		streams: std::sync::Mutex::new(streams),
+		opened: std::sync::Mutex::new(vec![]),
+	};
+	let subscriber = Subscriber::new(opener, table)
+		.with_retry_delay(std::time::Duration::from_millis(10));
+
+	let events = tokio::time::timeout(
+		std::time::Duration::from_millis(500),
+		subscriber.subscribe(routing_key).take(3).collect::<Vec<_>>(),
+	)
+	.await
+	.unwrap();
+	assert!(matches!(
+		events[..],
+		[
+			SubscriberEvent::Message(_),
+			SubscriberEvent::Gap,
+			SubscriberEvent::Message(_)
+		]
+	));
+}
+
+#[tokio::test]
+async fn test_subscriber_renews_lease() {
+	let remote = random_peer_id();
+	let table = Arc::new(RoutingTable::new(random_peer_id()).unwrap());
+	table.add_peer(remote).unwrap();
+
+	// a one second lease is renewed with the same server, without a gap
+	let routing_key = routed_request(remote).routing_key;
+	let key = routing_key.to_partial_key();
+	let subscribed = || {
+		SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed {
+			lease: 1,
+		}))
+	};
+	let streams = vec![
+		MemoryStream::with_responses(vec![subscribed()], true),
+		MemoryStream::with_responses(
+			vec![subscribed(), subscription_event(&key)],
+			true,
+		),
+	];
+	let opener = TestOpener {
+		streams: std::sync::Mutex::new(streams),
+		opened: std::sync::Mutex::new(vec![]),
+	};
+	let subscriber = Subscriber::new(opener, table).with_lease(1);
+
+	let events = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		subscriber.subscribe(routing_key).take(1).collect::<Vec<_>>(),
+	)
+	.await
+	.unwrap();
+	assert!(matches!(events[..], [SubscriberEvent::Message(_)]));
+	assert_eq!(
+		*subscriber.opener().opened.lock().unwrap(),
+		vec![remote, remote]
+	);
+}
+
+// hands the server end of every stream opened to the test
+struct PipeOpener {
+	servers: mpsc::UnboundedSender<PipeStream>,
+}
+
+#[async_trait]
+impl StreamOpener for PipeOpener {
+	type Stream = PipeStream;
+
+	async fn open(&self, _peer: PeerId) -> Result<PipeStream, SubfieldError> {
+		let (client, server) = pipe();
+		self.servers
+			.unbounded_send(server)
+			.map_err(|_| SubfieldError::FailedToOpenStream)?;
+		Ok(client)
+	}
+}
+
+#[tokio::test]
+async fn test_subscriber_renewal_overlap() {
+	let remote = random_peer_id();
+	let table = Arc::new(RoutingTable::new(random_peer_id()).unwrap());
+	table.add_peer(remote).unwrap();
+	let routing_key = routed_request(remote).routing_key;
+	let key = routing_key.to_partial_key();
+	let subscribed = |lease| {
+		SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed { lease }))
+	};
+	let message = |data: &[u8]| {
+		let mut event = subscription_event(&key);
+		if let SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Message(
+			event,
+		))) = &mut event
+		{
+			event.record.record_bytes = data.to_vec();
+		}
+		event
+	};
+
+	let (sender, mut servers) = mpsc::unbounded();
+	let subscriber =
+		Subscriber::new(PipeOpener { servers: sender }, table).with_lease(1);
+	let events = tokio::time::timeout(
+		std::time::Duration::from_millis(2000),
+		subscriber.subscribe(routing_key).take(3).collect::<Vec<_>>(),
+	);
+	let serve = async {
+		let mut old = server_stream(servers.next().await.unwrap());
+		recv_request(&mut old).await.unwrap();
+		send_response(&mut old, subscribed(1)).await.unwrap();
+
+		// sent by the old server while the new one accepts the renewal, and
+		// by the new one once it has
+		let mut new = server_stream(servers.next().await.unwrap());
+		recv_request(&mut new).await.unwrap();
+		send_response(&mut old, message(b"both")).await.unwrap();
+		send_response(&mut new, subscribed(SUBSCRIPTION_MAX_LEASE))
+			.await
+			.unwrap();
+		send_response(&mut new, message(b"both")).await.unwrap();
+
+		// the old stream is unsubscribed, and read until it is confirmed
+		let unsubscribe = recv_request(&mut old).await.unwrap();
+		send_response(&mut old, message(b"before")).await.unwrap();
+		let unsubscribed = Ok(UnsubscribeSuccess {});
+		send_response(&mut old, SubfieldResponse::Unsubscribe(unsubscribed))
+			.await
+			.unwrap();
+		send_response(&mut new, message(b"after")).await.unwrap();
+		(unsubscribe, old, new)
+	};
+	let (events, (unsubscribe, _old, _new)) = tokio::join!(events, serve);
+	assert!(matches!(
+		unsubscribe.body,
+		SubfieldRequestBody::Unsubscribe(UnsubscribeRequest { key: ref ended })
+			if *ended == key
+	));
+
+	let mut data: Vec<Vec<u8>> = events
+		.unwrap()
+		.into_iter()
+		.map(|event| match event {
+			SubscriberEvent::Message(event) => event.record.record_bytes,
+			event => panic!("unexpected event {event:?}"),
+		})
+		.collect();
+	data.sort();
+	assert_eq!(
+		data,
+		vec![b"after".to_vec(), b"before".to_vec(), b"both".to_vec()]
+	);
+}
+
+#[tokio::test]
+async fn test_subscriber_unsubscribes() {
+	let remote = random_peer_id();
+	let table = Arc::new(RoutingTable::new(random_peer_id()).unwrap());
+	table.add_peer(remote).unwrap();
+	let routing_key = routed_request(remote).routing_key;
+
+	let (sender, mut servers) = mpsc::unbounded();
+	let subscriber = Subscriber::new(PipeOpener { servers: sender }, table);
+	let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+	let events = subscriber
+		.subscribe_until(routing_key.clone(), async {
+			let _ = stopped.await;
+		})
+		.collect::<Vec<_>>();
+	let serve = async {
+		// a lease longer than any server grants is held to the max
+		let mut server = server_stream(servers.next().await.unwrap());
+		recv_request(&mut server).await.unwrap();
+		let subscribed = SubscribeSuccess::Subscribed { lease: u64::MAX };
+		send_response(&mut server, SubfieldResponse::Subscribe(Ok(subscribed)))
+			.await
+			.unwrap();
+
+		// once stopped the subscription is ended on the stream it was made on
+		stop.send(()).unwrap();
+		recv_request(&mut server).await.unwrap()
+	};
+	let (events, request) = tokio::time::timeout(
+		std::time::Duration::from_millis(500),
+		async { tokio::join!(events, serve) },
+	)
+	.await
+	.unwrap();
+	assert!(events.is_empty());
+	assert!(matches!(
+		request.body,
+		SubfieldRequestBody::Unsubscribe(UnsubscribeRequest { key })
+			if key == routing_key.to_partial_key()
+	));
+}
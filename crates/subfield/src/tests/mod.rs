@@ -1,3 +1,7 @@
 pub mod channels;
+pub mod chord;
+pub mod chord_simulator;
 pub mod crypto;
+pub mod dht;
 pub mod protocol;
+pub mod store;
@@ -38,3 +38,428 @@ fn test_key_hash_combinations() {
 	let hash = map.get(&hashes[0]).unwrap();
 	assert_eq!(hash, &hashes[0]);
 }
+
+/*
+   Record signatures
+*/
+
+// a key whose signer and cosigner are both real keypairs
+fn cosigned_key(signer: &Keypair, cosigner: &Keypair) -> CompleteKey {
+	CompleteKey {
+		signer: signer.public_key().versioned_bytes().clone(),
+		cosigner: cosigner.public_key().versioned_bytes().clone(),
+		tangent: V256::random256(),
+	}
+}
+
+fn put_record_request(request: &SubfieldRequest) -> PutRecordRequest {
+	match &request.body {
+		SubfieldRequestBody::PutRecord(put) => put.clone(),
+		_ => unreachable!(),
+	}
+}
+
+#[test]
+fn test_record_signature_roles() {
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = cosigned_key(&signer, &cosigner);
+	let record = Record::new(RecordType::Simple, key.clone(), b"data");
+
+	// either party may write, and the signature says which one did
+	for (keypair, is_signer) in [(&signer, true), (&cosigner, false)] {
+		let requests = record.to_put_record_requests(&key, keypair).unwrap();
+		for request in &requests {
+			let put = put_record_request(request);
+			assert_eq!(
+				matches!(put.signature, RecordSignature::Signer(_)),
+				is_signer
+			);
+			put.verify(request.routing_key.clone()).unwrap();
+		}
+	}
+	assert!(matches!(
+		record.to_put_record_requests(&key, &Keypair::random()),
+		Err(RecordError::KeypairNotSignerOrCosigner)
+	));
+
+	// a signature claiming the wrong role is refused
+	let mut put = put_record_request(
+		&record.to_put_record_requests(&key, &cosigner).unwrap()[0],
+	);
+	if let RecordSignature::Cosigner(signature) = put.signature {
+		put.signature = RecordSignature::Signer(signature);
+	}
+	assert!(matches!(
+		put.verify(key.to_signer_routing_key()),
+		Err(RecordError::InvalidSignature)
+	));
+}
+
+#[test]
+fn test_dual_signed_record() {
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = cosigned_key(&signer, &cosigner);
+	let mut record = Record::new(RecordType::Simple, key.clone(), b"data");
+	record.signature_mode = SignatureMode::Dual;
+
+	// one signature is not enough
+	assert!(matches!(
+		record.to_put_record_requests(&key, &signer),
+		Err(RecordError::MissingCosignature)
+	));
+	let mut put = PutRecordRequest {
+		record_bytes: serialize(&record).unwrap(),
+		signature: record.sign(&signer).unwrap(),
+	};
+	assert!(matches!(
+		put.verify(key.to_tangent_routing_key()),
+		Err(RecordError::MissingCosignature)
+	));
+
+	// both parties sign independently, then the signatures are joined
+	let signature = record
+		.sign(&cosigner)
+		.unwrap()
+		.join(record.sign(&signer).unwrap())
+		.unwrap();
+	assert!(matches!(signature, RecordSignature::Dual { .. }));
+	let requests = record.to_signed_put_record_requests(signature).unwrap();
+	for request in &requests {
+		put_record_request(request)
+			.verify(request.routing_key.clone())
+			.unwrap();
+	}
+
+	// two signatures of the same role cannot be joined
+	assert!(matches!(
+		record
+			.sign(&signer)
+			.unwrap()
+			.join(record.sign(&signer).unwrap()),
+		Err(RecordError::SignatureRoleMismatch)
+	));
+
+	// and a forged cosignature is refused
+	put.signature = RecordSignature::Dual {
+		signer: signer.sign(&put.record_bytes),
+		cosigner: signer.sign(&put.record_bytes),
+	};
+	assert!(matches!(
+		put.verify(key.to_signer_routing_key()),
+		Err(RecordError::InvalidSignature)
+	));
+}
+
+/*
+   Record encryption
+*/
+#[test]
+fn test_encrypted_record() {
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = cosigned_key(&signer, &cosigner);
+	let mut record = Record::new_encrypted(
+		RecordType::Simple,
+		key.clone(),
+		b"secret",
+		&signer,
+	)
+	.unwrap();
+	assert!(record.is_encrypted);
+	assert_ne!(record.data(), b"secret");
+
+	// stored and fetched like any other record, read by either party
+	let requests = record.to_put_record_requests(&key, &signer).unwrap();
+	let (_, stored) = put_record_request(&requests[2])
+		.verify(requests[2].routing_key.clone())
+		.unwrap();
+	assert_eq!(stored.decrypt(&signer).unwrap(), b"secret");
+	assert_eq!(stored.decrypt(&cosigner).unwrap(), b"secret");
+	assert!(matches!(
+		stored.decrypt(&Keypair::random()),
+		Err(RecordError::KeypairNotSignerOrCosigner)
+	));
+
+	// the cosigner can write the next version
+	record.update_encrypted(b"reply", &cosigner).unwrap();
+	assert_eq!(record.version, 1);
+	assert_eq!(record.decrypt(&signer).unwrap(), b"reply");
+
+	// altered ciphertext does not decrypt
+	let mut tampered = record.data().to_vec();
+	*tampered.last_mut().unwrap() ^= 1;
+	record.update(&tampered);
+	assert!(matches!(
+		record.decrypt(&signer),
+		Err(RecordError::DecryptionFailed)
+	));
+
+	let plain = Record::new(RecordType::Simple, key, b"public");
+	assert!(matches!(
+		plain.decrypt(&signer),
+		Err(RecordError::NotEncrypted)
+	));
+}
+
+/*
+   Record builder
+*/
+#[test]
+fn test_record_builder() {
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = cosigned_key(&signer, &cosigner);
+
+	// a typed value round trips through the data
+	let mut record = Record::builder(key.clone())
+		.signature_mode(SignatureMode::Dual)
+		.value(&("greeting".to_string(), 7u32))
+		.unwrap()
+		.build();
+	assert_eq!(record.version, 0);
+	assert_eq!(record.created_at, record.updated_at);
+	assert_eq!(record.signature_mode, SignatureMode::Dual);
+	assert_eq!(
+		record.value::<(String, u32)>().unwrap(),
+		("greeting".to_string(), 7)
+	);
+	record.set_value(&("farewell".to_string(), 8u32)).unwrap();
+	assert_eq!(record.version, 1);
+	assert_eq!(record.value::<(String, u32)>().unwrap().1, 8);
+
+	// encrypted values are only read with a keypair
+	let encrypted = Record::builder(key.clone())
+		.value(&42u64)
+		.unwrap()
+		.build_encrypted(&signer)
+		.unwrap();
+	assert!(matches!(
+		encrypted.value::<u64>(),
+		Err(RecordError::Encrypted)
+	));
+	assert_eq!(encrypted.decrypt_value::<u64>(&cosigner).unwrap(), 42);
+}
+
+#[test]
+fn test_record_content_hash() {
+	let key = cosigned_key(&Keypair::random(), &Keypair::random());
+
+	// random seeds keep equal data from sharing a content hash
+	let a = Record::builder(key.clone()).data(b"data").build();
+	let b = Record::builder(key.clone()).data(b"data").build();
+	assert_ne!(a.content_hash(), b.content_hash());
+	assert!(a.verify_content(&a.content_hash()));
+
+	// content addressed records hash the same data to the same address
+	let a = Record::builder(key.clone())
+		.content_addressed()
+		.data(b"data")
+		.build();
+	let b = Record::builder(key)
+		.content_addressed()
+		.data(b"data")
+		.build();
+	assert_eq!(a.content_hash(), b.content_hash());
+	assert!(b.verify_content(&a.content_hash()));
+	assert!(!b.verify_content(&V256::random256()));
+}
+
+/*
+   Record types
+*/
+#[test]
+fn test_log_record_merge() {
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = cosigned_key(&signer, &cosigner);
+	let log = Record::builder(key.clone())
+		.record_type(RecordType::Log)
+		.build();
+
+	// both parties append to their own copy of the log
+	let (mut a, mut b) = (log.clone(), log.clone());
+	a.append(b"from signer", &signer).unwrap();
+	b.append(b"from cosigner", &cosigner).unwrap();
+	b.append(b"again", &cosigner).unwrap();
+	assert!(matches!(
+		a.append(b"outsider", &Keypair::random()),
+		Err(RecordError::KeypairNotSignerOrCosigner)
+	));
+
+	// merged in either order and any number of times to the same log
+	let merged = a.merge(&b).unwrap();
+	assert_eq!(
+		serialize(&merged).unwrap(),
+		serialize(&b.merge(&a).unwrap()).unwrap()
+	);
+	assert_eq!(
+		serialize(&merged.merge(&b).unwrap()).unwrap(),
+		serialize(&merged).unwrap()
+	);
+	let data: Vec<Vec<u8>> =
+		merged.log().unwrap().into_iter().map(|e| e.data).collect();
+	assert_eq!(
+		data,
+		vec![
+			b"from signer".to_vec(),
+			b"from cosigner".to_vec(),
+			b"again".to_vec()
+		]
+	);
+	assert_eq!(merged.version, 2);
+
+	// the merged log is served with the signed puts it was merged from
+	let signed = |record: &Record| {
+		let [request, _, _] =
+			record.to_put_record_requests(&key, &signer).unwrap();
+		let routing_key = request.routing_key.clone();
+		SignedRecord::from_put_record_request(
+			routing_key,
+			put_record_request(&request),
+		)
+		.unwrap()
+	};
+	let merged = signed(&a).merge(&signed(&b)).unwrap();
+	let RecordSignature::Merged(puts) = &merged.signature else {
+		panic!("not merged");
+	};
+	assert_eq!(puts.len(), 2);
+	let record = Record::from_served_bytes(
+		&key,
+		&merged.record_bytes,
+		&merged.signature,
+	)
+	.unwrap();
+	assert_eq!(record.log().unwrap().len(), 3);
+
+	// but never put or published
+	assert!(matches!(
+		Record::from_signed_bytes(
+			&key,
+			&merged.record_bytes,
+			&merged.signature
+		),
+		Err(RecordError::InvalidSignature)
+	));
+
+	// puts another one already holds are left out
+	let mut c = b.clone();
+	c.append(b"last", &cosigner).unwrap();
+	let remerged = merged.merge(&signed(&c)).unwrap();
+	let RecordSignature::Merged(puts) = &remerged.signature else {
+		panic!("not merged");
+	};
+	assert_eq!(puts.len(), 2);
+	assert!(puts
+		.iter()
+		.all(|put| put.record_bytes != signed(&b).record_bytes));
+
+	// a merged record must be exactly the merge of its puts
+	let mut forged = merged.record().unwrap();
+	forged.version = u64::MAX;
+	assert!(matches!(
+		Record::from_served_bytes(
+			&key,
+			&serialize(&forged).unwrap(),
+			&merged.signature
+		),
+		Err(RecordError::InvalidSignature)
+	));
+	let empty = Record::builder(key.clone())
+		.record_type(RecordType::Log)
+		.build();
+	for puts in [vec![], merged.to_put_record_requests()[..1].to_vec()] {
+		assert!(matches!(
+			Record::from_served_bytes(
+				&key,
+				&serialize(&empty).unwrap(),
+				&RecordSignature::Merged(puts)
+			),
+			Err(RecordError::InvalidSignature)
+		));
+	}
+
+	// and a simple record cannot pass as merged
+	let simple = Record::new(RecordType::Simple, key.clone(), b"data");
+	let [put, _, _] = simple.to_put_record_requests(&key, &signer).unwrap();
+	let put = put_record_request(&put);
+	assert!(matches!(
+		Record::from_served_bytes(
+			&key,
+			&serialize(&simple).unwrap(),
+			&RecordSignature::Merged(vec![put.clone(), put])
+		),
+		Err(RecordError::NotMergeable)
+	));
+	assert!(matches!(
+		simple.merge(&simple),
+		Err(RecordError::NotMergeable)
+	));
+}
+
+#[test]
+fn test_counter_record_merge() {
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = cosigned_key(&signer, &cosigner);
+	let counter = Record::builder(key.clone())
+		.record_type(RecordType::Counter)
+		.build();
+	assert_eq!(counter.count().unwrap(), 0);
+
+	let (mut a, mut b) = (counter.clone(), counter.clone());
+	a.increment(2, &signer).unwrap();
+	a.increment(3, &signer).unwrap();
+	b.increment(4, &cosigner).unwrap();
+	let mut stale = a.clone();
+	stale.increment(1, &cosigner).unwrap();
+
+	// the highest count of each writer is kept
+	let merged = a.merge(&b).unwrap().merge(&stale).unwrap();
+	assert_eq!(merged.count().unwrap(), 5 + 4);
+	assert_eq!(
+		serialize(&merged).unwrap(),
+		serialize(&stale.merge(&b).unwrap().merge(&a).unwrap()).unwrap()
+	);
+
+	// a count cannot be signed for the other writer
+	let mut forged = b.clone();
+	let entry = SignedEntry {
+		writer: EntryWriter::Signer,
+		..deserialize::<Vec<SignedEntry>>(b.data()).unwrap()[0].clone()
+	};
+	forged.update(&serialize(&vec![entry]).unwrap());
+	assert!(matches!(forged.count(), Err(RecordError::InvalidSignature)));
+	assert!(matches!(
+		a.merge(&forged),
+		Err(RecordError::InvalidSignature)
+	));
+	assert!(matches!(a.log(), Err(RecordError::RecordTypeMismatch)));
+
+	// counts that add up past a u64 are refused rather than wrapped
+	assert!(matches!(
+		a.increment(u64::MAX, &signer),
+		Err(RecordError::CountOverflow)
+	));
+	let mut full = counter.clone();
+	full.increment(u64::MAX, &signer).unwrap();
+	assert!(matches!(full.merge(&b), Err(RecordError::CountOverflow)));
+	b.increment(u64::MAX - 4, &cosigner).unwrap();
+	assert_eq!(b.count().unwrap(), u64::MAX);
+}
+
+#[test]
+fn test_manifest_record() {
+	let keypair = Keypair::random();
+	let key = cosigned_key(&keypair, &Keypair::random());
+	let manifest = Manifest {
+		size: 10,
+		chunks: vec![crypto::hash(b"chunk")],
+	};
+	let record = Record::builder(key)
+		.record_type(RecordType::Manifest)
+		.value(&manifest)
+		.unwrap()
+		.build();
+	assert_eq!(record.manifest().unwrap(), manifest);
+	assert!(matches!(
+		record.merge(&record),
+		Err(RecordError::NotMergeable)
+	));
+}
@@ -0,0 +1,1531 @@
+use super::dht::{pipe, random_peer_id, MemoryStream, PipeStream, TestHandler};
+use crate::*;
+
+// a key signed by the keypair, with random cosigner and tangent
+fn signed_key(keypair: &Keypair) -> CompleteKey {
+	CompleteKey {
+		signer: keypair.public_key().versioned_bytes().clone(),
+		..CompleteKey::random()
+	}
+}
+
+fn put_request(
+	keypair: &Keypair,
+	key: &CompleteKey,
+	data: &[u8],
+) -> (RoutingKey, PutRecordRequest) {
+	let record = Record::new(RecordType::Simple, key.clone(), data);
+	record_put_request(&record, keypair)
+}
+
+// the put request for the record at its signer routing location
+fn record_put_request(
+	record: &Record,
+	keypair: &Keypair,
+) -> (RoutingKey, PutRecordRequest) {
+	let [request, _, _] =
+		record.to_put_record_requests(&record.key, keypair).unwrap();
+	match request.body {
+		SubfieldRequestBody::PutRecord(put) => (request.routing_key, put),
+		_ => unreachable!(),
+	}
+}
+
+fn get_request(routing_key: RoutingKey) -> GetRecordRequest {
+	GetRecordRequest { routing_key }
+}
+
+fn signed_record(keypair: &Keypair, key: &CompleteKey) -> SignedRecord {
+	let (routing_key, request) = put_request(keypair, key, b"data");
+	SignedRecord::from_put_record_request(routing_key, request).unwrap()
+}
+
+fn assert_record_store(store: &dyn RecordStore) {
+	let keypair = Keypair::random();
+	let a = signed_key(&keypair);
+	let b = signed_key(&keypair);
+	let c = CompleteKey {
+		tangent: a.tangent.clone(),
+		..signed_key(&keypair)
+	};
+	for key in [&a, &b, &c] {
+		store.put(signed_record(&keypair, key)).unwrap();
+	}
+	assert_eq!(store.len().unwrap(), 3);
+
+	// putting the same key again replaces the record
+	store.put(signed_record(&keypair, &a)).unwrap();
+	assert_eq!(store.len().unwrap(), 3);
+	assert_eq!(store.get(&a).unwrap().unwrap().key, a);
+
+	// lookups by any combination of key fields
+	let by_signer = PartialKey {
+		signer: Some(a.signer.clone()),
+		..Default::default()
+	};
+	let by_tangent = PartialKey {
+		tangent: Some(a.tangent.clone()),
+		..Default::default()
+	};
+	assert_eq!(store.find(&by_signer).unwrap().len(), 3);
+	assert_eq!(store.find(&by_tangent).unwrap().len(), 2);
+	assert_eq!(store.find(&b.to_partial()).unwrap().len(), 1);
+	assert_eq!(store.find(&PartialKey::default()).unwrap().len(), 3);
+
+	// pages in key hash order
+	let first = store.list(&by_signer, None, 2).unwrap();
+	let cursor = first[1].key.hash();
+	let rest = store.list(&by_signer, Some(&cursor), 2).unwrap();
+	assert_eq!((first.len(), rest.len()), (2, 1));
+	assert!(first[1].key.hash().to_string() < rest[0].key.hash().to_string());
+
+	assert!(store.delete(&b).unwrap().is_some());
+	assert!(store.delete(&b).unwrap().is_none());
+	assert!(store.get(&b).unwrap().is_none());
+	assert_eq!(store.len().unwrap(), 2);
+
+	// tombstones are found and paged by key like the records
+	let record = Record::new(RecordType::Simple, c.clone(), b"data");
+	let deletes =
+		delete_requests(record.to_delete_record_requests(&keypair).unwrap());
+	let (routing_key, delete) = deletes[0].clone();
+	let tombstone =
+		Tombstone::from_delete_record_request(routing_key, delete).unwrap();
+	store.put_tombstone(tombstone).unwrap();
+	assert_eq!(store.find_tombstones(&by_tangent).unwrap().len(), 1);
+	assert!(store.find_tombstones(&b.to_partial()).unwrap().is_empty());
+	let listed = store.list_tombstones(&by_signer, None, 2).unwrap();
+	assert_eq!(listed[0].key, c);
+	let cursor = c.hash();
+	assert!(store
+		.list_tombstones(&by_signer, Some(&cursor), 2)
+		.unwrap()
+		.is_empty());
+}
+
+#[test]
+fn test_memory_record_store() {
+	assert_record_store(&MemoryRecordStore::new());
+}
+
+#[cfg(all(feature = "gluesql", not(target_arch = "wasm32")))]
+#[test]
+fn test_gluesql_record_store() {
+	let storage = gluesql::gluesql_memory_storage::MemoryStorage::default();
+	assert_record_store(&GluesqlRecordStore::new(storage).unwrap());
+}
+
+#[tokio::test]
+async fn test_store_record_handler() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+
+	// a record signed by someone else is refused
+	let (routing_key, mut request) = put_request(&keypair, &key, b"data");
+	request.signature =
+		RecordSignature::Signer(Keypair::random().sign(&request.record_bytes));
+	assert!(matches!(
+		handler.put_record(peer, routing_key, request).await,
+		Err(PutRecordFailure::RecordError(RecordError::InvalidSignature))
+	));
+
+	let (routing_key, request) = put_request(&keypair, &key, b"data");
+	handler
+		.put_record(peer, routing_key, request.clone())
+		.await
+		.unwrap();
+
+	// served back unchanged through any of its routing keys
+	for routing_key in [
+		key.to_signer_routing_key(),
+		key.to_cosigner_routing_key(),
+		key.to_tangent_routing_key(),
+	] {
+		let success = handler
+			.get_record(peer, routing_key.clone(), get_request(routing_key))
+			.await
+			.unwrap();
+		assert_eq!(success.record_bytes, request.record_bytes);
+		let record = Record::from_get_record_response(Ok(success)).unwrap();
+		assert_eq!(record.data(), b"data");
+	}
+
+	let mut partial = key.to_partial();
+	partial.tangent = None;
+	let routing_key = RoutingKey::Signer(partial);
+	assert!(matches!(
+		handler
+			.get_record(peer, routing_key.clone(), get_request(routing_key))
+			.await,
+		Err(GetRecordFailure::Invalid)
+	));
+}
+
+// the records and next cursor of one ListRecords page served by the dispatcher
+async fn list_page(
+	dispatcher: &Dispatcher,
+	routing_key: RoutingKey,
+	request: ListRecordsRequest,
+) -> (Vec<GetRecordSuccess>, Option<ListRecordsCursor>) {
+	let request = SubfieldRequest::new(
+		routing_key,
+		SubfieldRequestBody::ListRecords(request),
+	);
+	let mut stream = MemoryStream::new(vec![request], false);
+	dispatcher
+		.serve_stream(PeerId::random(), &mut stream)
+		.await
+		.unwrap();
+
+	let mut records = vec![];
+	for response in stream.responses() {
+		match response {
+			SubfieldResponse::ListRecords(Ok(ListRecordsSuccess::Record(
+				record,
+			))) => records.push(*record),
+			SubfieldResponse::ListRecords(Ok(ListRecordsSuccess::End {
+				next,
+			})) => return (records, next),
+			response => panic!("unexpected response {response:?}"),
+		}
+	}
+	panic!("the listing did not end")
+}
+
+#[tokio::test]
+async fn test_list_records() {
+	let store = MemoryRecordStore::new();
+	let keypair = Keypair::random();
+	let tangent = V256::random256();
+	for i in 0..5 {
+		let mut key = signed_key(&keypair);
+		// two of the records share a tangent
+		if i < 2 {
+			key.tangent = tangent.clone();
+		}
+		store.put(signed_record(&keypair, &key)).unwrap();
+	}
+	let other = Keypair::random();
+	store
+		.put(signed_record(&other, &signed_key(&other)))
+		.unwrap();
+
+	let dispatcher = Dispatcher::new(
+		Arc::new(DefaultSystemHandler),
+		Arc::new(StoreRecordHandler::new(Arc::new(store))),
+		Arc::new(TestHandler),
+	);
+
+	// every record the signer wrote, two at a time
+	let by_signer = RoutingKey::Signer(PartialKey {
+		signer: Some(keypair.public_key().versioned_bytes().clone()),
+		..Default::default()
+	});
+	let mut cursor = None;
+	let mut pages = vec![];
+	loop {
+		let request = ListRecordsRequest {
+			cursor,
+			limit: 2,
+			tombstones: false,
+		};
+		let (records, next) =
+			list_page(&dispatcher, by_signer.clone(), request).await;
+		for record in &records {
+			assert!(matches!(record.routing_key, RoutingKey::Signer(_)));
+			Record::from_get_record_response(Ok(record.clone())).unwrap();
+		}
+		pages.push(records.len());
+		match next {
+			Some(next) => cursor = Some(next),
+			None => break,
+		}
+	}
+	assert_eq!(pages, vec![2, 2, 1]);
+
+	// the records the signer wrote under one tangent
+	let by_tangent = RoutingKey::Tangent(PartialKey {
+		signer: Some(keypair.public_key().versioned_bytes().clone()),
+		cosigner: None,
+		tangent: Some(tangent),
+	});
+	let request = ListRecordsRequest {
+		cursor: None,
+		limit: LIST_RECORDS_MAX_LIMIT,
+		tombstones: false,
+	};
+	let (records, next) = list_page(&dispatcher, by_tangent, request).await;
+	assert_eq!((records.len(), next), (2, None));
+
+	// a routing key without its routing field set is refused
+	let request = ListRecordsRequest {
+		cursor: None,
+		limit: 2,
+		tombstones: false,
+	};
+	let mut stream = MemoryStream::new(
+		vec![SubfieldRequest::new(
+			RoutingKey::Cosigner(PartialKey::default()),
+			SubfieldRequestBody::ListRecords(request),
+		)],
+		false,
+	);
+	dispatcher
+		.serve_stream(PeerId::random(), &mut stream)
+		.await
+		.unwrap();
+	assert!(matches!(
+		stream.responses()[..],
+		[SubfieldResponse::ListRecords(Err(
+			ListRecordsFailure::Invalid
+		))]
+	));
+}
+
+// the request body of each of the three delete requests
+fn delete_requests(
+	requests: [SubfieldRequest; 3],
+) -> Vec<(RoutingKey, DeleteRecordRequest)> {
+	requests
+		.into_iter()
+		.map(|request| match request.body {
+			SubfieldRequestBody::DeleteRecord(delete) => {
+				(request.routing_key, delete)
+			}
+			_ => unreachable!(),
+		})
+		.collect()
+}
+
+#[tokio::test]
+async fn test_delete_record_tombstones() {
+	let peer = PeerId::random();
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = CompleteKey {
+		cosigner: cosigner.public_key().versioned_bytes().clone(),
+		..signed_key(&signer)
+	};
+	let record = Record::new(RecordType::Simple, key.clone(), b"data");
+	let puts = record.to_put_record_requests(&key, &signer).unwrap();
+	let stale = match &puts[0].body {
+		SubfieldRequestBody::PutRecord(put) => put.clone(),
+		_ => unreachable!(),
+	};
+
+	// one replica per routing location, only the signer's has the record
+	let replicas: Vec<StoreRecordHandler> = (0..3)
+		.map(|_| StoreRecordHandler::new(Arc::new(MemoryRecordStore::new())))
+		.collect();
+	replicas[0]
+		.put_record(peer, key.to_signer_routing_key(), stale.clone())
+		.await
+		.unwrap();
+
+	// only the signer or cosigner may delete
+	assert!(matches!(
+		record.to_delete_record_requests(&Keypair::random()),
+		Err(RecordError::KeypairNotSignerOrCosigner)
+	));
+	let deletes =
+		delete_requests(record.to_delete_record_requests(&cosigner).unwrap());
+	let (routing_key, mut forged) = deletes[0].clone();
+	forged.signature =
+		DeleteRecordSignature::Cosigner(signer.sign(&forged.deletion_bytes));
+	assert!(matches!(
+		replicas[0].delete_record(peer, routing_key, forged).await,
+		Err(DeleteRecordFailure::Invalid)
+	));
+
+	// a deletion is only valid for the key it names
+	let (_, delete) = deletes[0].clone();
+	let other = CompleteKey::random().to_signer_routing_key();
+	assert!(matches!(
+		replicas[0].delete_record(peer, other, delete).await,
+		Err(DeleteRecordFailure::Invalid)
+	));
+
+	for (replica, (routing_key, delete)) in replicas.iter().zip(deletes) {
+		replica
+			.delete_record(peer, routing_key, delete)
+			.await
+			.unwrap();
+	}
+	let routing_key = key.to_signer_routing_key();
+	assert!(matches!(
+		replicas[0]
+			.get_record(peer, routing_key.clone(), get_request(routing_key))
+			.await,
+		Err(GetRecordFailure::Unknown)
+	));
+
+	// every replica refuses the deleted record, even those that never had it
+	for (replica, put) in replicas.iter().zip(puts) {
+		assert!(matches!(
+			replica
+				.put_record(peer, put.routing_key, stale.clone())
+				.await,
+			Err(PutRecordFailure::Deleted { .. })
+		));
+	}
+
+	// a later version that says it was updated before the deletion is kept,
+	// and an earlier one that says it was updated after is refused
+	let mut backdated = record.clone();
+	backdated.update(b"newer");
+	backdated.updated_at = record.updated_at;
+	let mut postdated = Record::new(RecordType::Simple, key.clone(), b"later");
+	postdated.updated_at = Utc::now() + chrono::Duration::days(1);
+	let (routing_key, request) = record_put_request(&postdated, &signer);
+	assert!(matches!(
+		replicas[0].put_record(peer, routing_key, request).await,
+		Err(PutRecordFailure::Deleted { .. })
+	));
+	let (routing_key, request) = record_put_request(&backdated, &signer);
+	replicas[0]
+		.put_record(peer, routing_key.clone(), request)
+		.await
+		.unwrap();
+	let success = replicas[0]
+		.get_record(peer, routing_key.clone(), get_request(routing_key))
+		.await
+		.unwrap();
+	let record = Record::from_get_record_response(Ok(success)).unwrap();
+	assert_eq!(record.data(), b"newer");
+}
+
+#[tokio::test]
+async fn test_recreate_deleted_record() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let mut record = Record::new(RecordType::Simple, key.clone(), b"data");
+	record.update(b"newer");
+	let (routing_key, put) = record_put_request(&record, &keypair);
+	handler
+		.put_record(peer, routing_key.clone(), put)
+		.await
+		.unwrap();
+	let deletes =
+		delete_requests(record.to_delete_record_requests(&keypair).unwrap());
+	let (_, delete) = deletes[0].clone();
+	handler
+		.delete_record(peer, routing_key.clone(), delete)
+		.await
+		.unwrap();
+
+	// a record built from scratch is one of the versions deleted, refused
+	// with the version it can be created again above
+	let fresh = Record::new(RecordType::Simple, key.clone(), b"again");
+	let (_, put) = record_put_request(&fresh, &keypair);
+	assert!(matches!(
+		handler.put_record(peer, routing_key.clone(), put).await,
+		Err(PutRecordFailure::Deleted { version }) if version == record.version
+	));
+
+	// one built above the deleted version creates the key again
+	let again = Record::builder(key)
+		.data(b"again")
+		.version(record.version + 1)
+		.build();
+	let (_, put) = record_put_request(&again, &keypair);
+	handler
+		.put_record(peer, routing_key.clone(), put)
+		.await
+		.unwrap();
+	let success = handler
+		.get_record(peer, routing_key.clone(), get_request(routing_key))
+		.await
+		.unwrap();
+	let stored = Record::from_get_record_response(Ok(success)).unwrap();
+	assert_eq!(stored.data(), b"again");
+}
+
+#[tokio::test]
+async fn test_record_versions() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let mut record =
+		Record::new(RecordType::Simple, signed_key(&keypair), b"first");
+	let (routing_key, first) = record_put_request(&record, &keypair);
+	handler
+		.put_record(peer, routing_key.clone(), first.clone())
+		.await
+		.unwrap();
+
+	record.update(b"second");
+	assert_eq!(record.version, 1);
+	let (_, second) = record_put_request(&record, &keypair);
+	handler
+		.put_record(peer, routing_key.clone(), second.clone())
+		.await
+		.unwrap();
+
+	// an older version arriving late does not overwrite the newer one
+	assert!(matches!(
+		handler.put_record(peer, routing_key.clone(), first).await,
+		Err(PutRecordFailure::Stale { version: 1 })
+	));
+	// putting the stored record again is fine
+	handler
+		.put_record(peer, routing_key.clone(), second)
+		.await
+		.unwrap();
+
+	let success = handler
+		.get_record(peer, routing_key.clone(), get_request(routing_key))
+		.await
+		.unwrap();
+	let stored = Record::from_get_record_response(Ok(success)).unwrap();
+	assert_eq!(stored.data(), b"second");
+}
+
+#[tokio::test]
+async fn test_record_version_ties() {
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+
+	// two writes of the same version, applied in opposite orders
+	let (routing_key, a) = put_request(&keypair, &key, b"a");
+	let (_, b) = put_request(&keypair, &key, b"b");
+	let mut stored = vec![];
+	for puts in [[a.clone(), b.clone()], [b, a]] {
+		let handler =
+			StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+		for put in puts {
+			let _ = handler.put_record(peer, routing_key.clone(), put).await;
+		}
+		let record = handler.store().get(&key).unwrap().unwrap();
+		stored.push(record.record_bytes);
+	}
+
+	// both replicas converge on the same record
+	assert_eq!(stored[0], stored[1]);
+}
+
+#[tokio::test]
+async fn test_concurrent_counter_puts() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let peer = PeerId::random();
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = CompleteKey {
+		cosigner: cosigner.public_key().versioned_bytes().clone(),
+		..signed_key(&signer)
+	};
+	let counter = Record::builder(key.clone())
+		.record_type(RecordType::Counter)
+		.build();
+
+	// both parties increment the same version, neither overwrites the other
+	let (mut a, mut b) = (counter.clone(), counter);
+	a.increment(1, &signer).unwrap();
+	b.increment(2, &cosigner).unwrap();
+	for (record, keypair) in [(&a, &signer), (&b, &cosigner), (&a, &signer)] {
+		let (routing_key, request) = record_put_request(record, keypair);
+		handler
+			.put_record(peer, routing_key, request)
+			.await
+			.unwrap();
+	}
+
+	let routing_key = key.to_tangent_routing_key();
+	let success = handler
+		.get_record(peer, routing_key.clone(), get_request(routing_key))
+		.await
+		.unwrap();
+	assert!(matches!(success.signature, RecordSignature::Merged(_)));
+	let stored = Record::from_get_record_response(Ok(success.clone())).unwrap();
+	assert_eq!(stored.count().unwrap(), 3);
+
+	// a merged record is never accepted as a put or a message, even one whose
+	// puts verify
+	let merged = PutRecordRequest {
+		record_bytes: success.record_bytes,
+		signature: success.signature,
+	};
+	let routing_key = key.to_signer_routing_key();
+	for put in [counter_at(&key, u64::MAX), merged] {
+		assert!(matches!(
+			handler
+				.put_record(peer, routing_key.clone(), put.clone())
+				.await,
+			Err(PutRecordFailure::RecordError(RecordError::InvalidSignature))
+		));
+		let publish = PublishRequest {
+			record_bytes: put.record_bytes,
+			signature: put.signature,
+		};
+		assert!(matches!(
+			handler
+				.subscriptions()
+				.publish(peer, routing_key.clone(), publish)
+				.await,
+			Err(PublishFailure::RecordError(RecordError::InvalidSignature))
+		));
+	}
+	let stored = handler.store().get(&key).unwrap().unwrap();
+	assert_eq!(stored.record().unwrap().count().unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_dual_signed_log_puts() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let peer = PeerId::random();
+	let (signer, cosigner) = (Keypair::random(), Keypair::random());
+	let key = CompleteKey {
+		cosigner: cosigner.public_key().versioned_bytes().clone(),
+		..signed_key(&signer)
+	};
+	let mut log = Record::builder(key.clone())
+		.record_type(RecordType::Log)
+		.signature_mode(SignatureMode::Dual)
+		.build();
+	let dual_put = |log: &Record| {
+		let signature = log
+			.sign(&signer)
+			.unwrap()
+			.join(log.sign(&cosigner).unwrap())
+			.unwrap();
+		let [request, _, _] =
+			log.to_signed_put_record_requests(signature).unwrap();
+		match request.body {
+			SubfieldRequestBody::PutRecord(put) => (request.routing_key, put),
+			_ => unreachable!(),
+		}
+	};
+
+	// a dual signed log cannot be merged, so the newer version replaces it
+	log.append(b"first", &signer).unwrap();
+	let (routing_key, first) = dual_put(&log);
+	handler
+		.put_record(peer, routing_key.clone(), first.clone())
+		.await
+		.unwrap();
+	log.append(b"second", &cosigner).unwrap();
+	let (_, second) = dual_put(&log);
+	handler
+		.put_record(peer, routing_key.clone(), second)
+		.await
+		.unwrap();
+	assert!(matches!(
+		handler.put_record(peer, routing_key, first).await,
+		Err(PutRecordFailure::Stale { version: 2 })
+	));
+
+	let stored = handler.store().get(&key).unwrap().unwrap();
+	assert_eq!(stored.record().unwrap().log().unwrap().len(), 2);
+}
+
+// an unsigned put of an empty counter at the version
+fn counter_at(key: &CompleteKey, version: RecordVersion) -> PutRecordRequest {
+	let mut counter = Record::builder(key.clone())
+		.record_type(RecordType::Counter)
+		.build();
+	counter.version = version;
+	PutRecordRequest {
+		record_bytes: serialize(&counter).unwrap(),
+		signature: RecordSignature::Merged(vec![]),
+	}
+}
+
+#[tokio::test]
+async fn test_blob_store() {
+	let store = Arc::new(MemoryRecordStore::new());
+	let handler = Arc::new(StoreRecordHandler::new(store.clone()));
+	let peer = PeerId::random();
+	let blobs = BlobStore::new(handler.clone(), peer).with_chunk_size(4);
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+
+	// written in pieces that do not line up with the chunks
+	let pieces = vec![b"hello ".to_vec(), b"blob".to_vec(), b" world".to_vec()];
+	let manifest = blobs
+		.put_blob(&key, &keypair, stream::iter(pieces))
+		.await
+		.unwrap();
+	assert_eq!(manifest.size, 16);
+	assert_eq!(manifest.chunks.len(), 4);
+	assert_eq!(manifest.chunks[0], crypto::hash(b"hell"));
+
+	let chunks: Vec<Vec<u8>> = blobs
+		.get_blob(&key)
+		.await
+		.unwrap()
+		.map(|chunk| chunk.unwrap())
+		.collect()
+		.await;
+	assert_eq!(chunks.concat(), b"hello blob world");
+
+	// rewriting the blob reuses the chunks it shares with the old one
+	let manifest = blobs
+		.put_blob(&key, &keypair, stream::iter(vec![b"hello".to_vec()]))
+		.await
+		.unwrap();
+	assert_eq!(manifest.chunks.len(), 2);
+	assert_eq!(blobs.get_manifest(&key).await.unwrap(), manifest);
+
+	// a deleted blob is put again above the deleted version
+	let deletion = Deletion::new(key.clone(), 1);
+	let deletes = deletion.to_delete_record_requests(&keypair).unwrap();
+	for (routing_key, delete) in delete_requests(deletes) {
+		handler.delete_record(peer, routing_key, delete).await.unwrap();
+	}
+	assert!(blobs.get_manifest(&key).await.is_err());
+	let manifest = blobs
+		.put_blob(&key, &keypair, stream::iter(vec![b"hello".to_vec()]))
+		.await
+		.unwrap();
+	assert_eq!(blobs.get_manifest(&key).await.unwrap(), manifest);
+
+	// a key holding another type of record is left alone
+	let other = signed_key(&keypair);
+	let record = Record::new(RecordType::Simple, other.clone(), b"data");
+	let (routing_key, put) = record_put_request(&record, &keypair);
+	handler.put_record(peer, routing_key, put).await.unwrap();
+	let data = stream::iter(vec![b"blob".to_vec()]);
+	assert!(matches!(
+		blobs.put_blob(&other, &keypair, data).await,
+		Err(BlobError::RecordError(RecordError::RecordTypeMismatch))
+	));
+
+	// a chunk whose data does not match its hash is refused
+	let chunk_key = BlobStore::chunk_key(&key, &manifest.chunks[1]);
+	let forged = Record::new(RecordType::Simple, chunk_key.clone(), b"x");
+	let [request, _, _] =
+		forged.to_put_record_requests(&chunk_key, &keypair).unwrap();
+	let SubfieldRequestBody::PutRecord(put) = request.body else {
+		unreachable!()
+	};
+	store
+		.put(
+			SignedRecord::from_put_record_request(request.routing_key, put)
+				.unwrap(),
+		)
+		.unwrap();
+	let results: Vec<_> = blobs.get_blob(&key).await.unwrap().collect().await;
+	assert!(results[0].is_ok());
+	assert!(matches!(results[1], Err(BlobError::ChunkHashMismatch)));
+}
+
+fn subscribe_request(key: PartialKey) -> SubscribeRequest {
+	SubscribeRequest {
+		key,
+		lease: SUBSCRIPTION_MAX_LEASE,
+	}
+}
+
+// the events of a subscription made straight to the table
+async fn subscribe(
+	subscriptions: &SubscriptionTable,
+	peer: PeerId,
+	key: PartialKey,
+) -> SubscriptionStream {
+	let request = subscribe_request(key);
+	subscriptions.subscribe(peer, request).await.unwrap().events
+}
+
+#[tokio::test]
+async fn test_record_subscriptions() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let subscriptions = handler.subscriptions().clone();
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+
+	// one subscription per combination of the key's fields, and one other
+	let mut matching = vec![];
+	for i in 1..8 {
+		let field =
+			|bit: i32, value: &V256| (i & bit != 0).then(|| value.clone());
+		let partial = PartialKey {
+			signer: field(1, &key.signer),
+			cosigner: field(2, &key.cosigner),
+			tangent: field(4, &key.tangent),
+		};
+		matching.push(subscribe(&subscriptions, peer, partial).await);
+	}
+	let mut other =
+		subscribe(&subscriptions, peer, PartialKey::random()).await;
+	let request = subscribe_request(PartialKey::default());
+	assert!(matches!(
+		subscriptions.subscribe(peer, request).await,
+		Err(SubscribeFailure::Invalid)
+	));
+	assert_eq!(subscriptions.len(), 8);
+
+	// a put reaches every matching subscriber once
+	let (routing_key, request) = put_request(&keypair, &key, b"data");
+	handler
+		.put_record(peer, routing_key.clone(), request.clone())
+		.await
+		.unwrap();
+	handler
+		.put_record(peer, routing_key, request)
+		.await
+		.unwrap();
+	for events in &mut matching {
+		match events.next().await {
+			Some(Ok(SubscribeSuccess::Record(event))) => {
+				let record = Record::from_get_record_response(Ok(event.record));
+				assert_eq!(record.unwrap().data(), b"data");
+			}
+			event => panic!("unexpected event {event:?}"),
+		}
+		assert!(events.next().now_or_never().is_none());
+	}
+	assert!(other.next().now_or_never().is_none());
+
+	// unsubscribing forgets the subscriptions to the key whose streams were
+	// dropped, and keeps those still open
+	let request = UnsubscribeRequest {
+		key: key.to_partial(),
+	};
+	let mut open = subscribe(&subscriptions, peer, key.to_partial()).await;
+	matching.pop();
+	subscriptions.unsubscribe(peer, request).await.unwrap();
+	assert_eq!(subscriptions.len(), 8);
+	assert!(open.next().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn test_unsubscribe_on_stream() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let dispatcher = Dispatcher::new(
+		Arc::new(DefaultSystemHandler),
+		Arc::new(handler.clone()),
+		Arc::new(handler.subscriptions().clone()),
+	);
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let request =
+		|body| SubfieldRequest::new(key.to_signer_routing_key(), body);
+	let subscribe = request(SubfieldRequestBody::Subscribe(
+		subscribe_request(key.to_partial()),
+	));
+	let unsubscribe =
+		request(SubfieldRequestBody::Unsubscribe(UnsubscribeRequest {
+			key: key.to_partial(),
+		}));
+
+	// the peer subscribes to the key on two streams, and unsubscribes on one
+	let mut ended =
+		MemoryStream::new(vec![subscribe.clone(), unsubscribe], true);
+	let mut open = MemoryStream::new(vec![subscribe], true);
+	let serve = |stream| {
+		tokio::time::timeout(
+			std::time::Duration::from_millis(200),
+			dispatcher.serve_stream(peer, stream),
+		)
+	};
+	let put = async {
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+		let (routing_key, request) = put_request(&keypair, &key, b"data");
+		handler.put_record(peer, routing_key, request).await.unwrap();
+	};
+	let (ended_served, open_served, _) =
+		tokio::join!(serve(&mut ended), serve(&mut open), put);
+	assert!(ended_served.is_err() && open_served.is_err());
+
+	// the subscription on the other stream is kept
+	assert!(matches!(
+		ended.responses()[..],
+		[
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed {
+				..
+			})),
+			SubfieldResponse::Unsubscribe(Ok(_))
+		]
+	));
+	assert!(matches!(
+		open.responses()[..],
+		[
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed {
+				..
+			})),
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Record(_)))
+		]
+	));
+	assert_eq!(handler.subscriptions().len(), 1);
+}
+
+#[tokio::test]
+async fn test_subscription_stream_events() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let dispatcher = Dispatcher::new(
+		Arc::new(DefaultSystemHandler),
+		Arc::new(handler.clone()),
+		Arc::new(handler.subscriptions().clone()),
+	);
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let subscribe = SubfieldRequest::new(
+		key.to_signer_routing_key(),
+		SubfieldRequestBody::Subscribe(subscribe_request(PartialKey {
+			signer: Some(key.signer.clone()),
+			..Default::default()
+		})),
+	);
+
+	// the record is pushed over the open subscription stream
+	let mut stream = MemoryStream::new(vec![subscribe], true);
+	let serve = tokio::time::timeout(
+		std::time::Duration::from_millis(200),
+		dispatcher.serve_stream(PeerId::random(), &mut stream),
+	);
+	let put = async {
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+		let (routing_key, request) = put_request(&keypair, &key, b"data");
+		handler
+			.put_record(PeerId::random(), routing_key, request)
+			.await
+			.unwrap();
+	};
+	let (served, _) = tokio::join!(serve, put);
+	assert!(served.is_err());
+
+	let responses = stream.responses();
+	assert!(matches!(
+		responses[..],
+		[
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Subscribed {
+				lease: SUBSCRIPTION_MAX_LEASE
+			})),
+			SubfieldResponse::Subscribe(Ok(SubscribeSuccess::Record(_)))
+		]
+	));
+}
+
+#[tokio::test]
+async fn test_publish_messages() {
+	let store = Arc::new(MemoryRecordStore::new());
+	let handler = StoreRecordHandler::new(store.clone());
+	let subscriptions = handler.subscriptions().clone();
+	let dispatcher = Dispatcher::new(
+		Arc::new(DefaultSystemHandler),
+		Arc::new(handler.clone()),
+		Arc::new(subscriptions.clone()),
+	);
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let tangent = PartialKey {
+		tangent: Some(key.tangent.clone()),
+		..Default::default()
+	};
+	let mut events = subscribe(&subscriptions, peer, tangent).await;
+
+	// sent to every routing location, delivered once and never stored
+	let message = Record::new(RecordType::Simple, key.clone(), b"hello");
+	let mut delivered = vec![];
+	for request in message.to_publish_requests(&keypair).unwrap() {
+		match dispatcher.handle_oneshot(peer, request).await {
+			SubfieldResponse::Publish(Ok(success)) => {
+				delivered.push(success.delivered)
+			}
+			response => panic!("unexpected response {response:?}"),
+		}
+	}
+	assert_eq!(delivered, vec![1, 0, 0]);
+	match events.next().await {
+		Some(Ok(SubscribeSuccess::Message(event))) => {
+			let message = Record::from_get_record_response(Ok(event.record));
+			assert_eq!(message.unwrap().data(), b"hello");
+		}
+		event => panic!("unexpected event {event:?}"),
+	}
+	assert!(events.next().now_or_never().is_none());
+	assert!(store.is_empty().unwrap());
+
+	// a message signed by someone else is refused
+	let [mut request, _, _] = message.to_publish_requests(&keypair).unwrap();
+	if let SubfieldRequestBody::Publish(publish) = &mut request.body {
+		publish.signature = RecordSignature::Signer(
+			Keypair::random().sign(&publish.record_bytes),
+		);
+	}
+	assert!(matches!(
+		dispatcher.handle_oneshot(peer, request).await,
+		SubfieldResponse::Publish(Err(PublishFailure::RecordError(
+			RecordError::InvalidSignature
+		)))
+	));
+
+	// a message is only delivered again once it is too old to be accepted
+	let [request, _, _] = message.to_publish_requests(&keypair).unwrap();
+	assert!(matches!(
+		dispatcher.handle_oneshot(peer, request).await,
+		SubfieldResponse::Publish(Ok(PublishSuccess { delivered: 0 }))
+	));
+	let max_age = chrono::Duration::seconds(PUBLISH_MAX_AGE as i64 + 1);
+	for updated_at in [Utc::now() - max_age, Utc::now() + max_age] {
+		let mut message = message.clone();
+		message.updated_at = updated_at;
+		let [request, _, _] = message.to_publish_requests(&keypair).unwrap();
+		assert!(matches!(
+			dispatcher.handle_oneshot(peer, request).await,
+			SubfieldResponse::Publish(Err(PublishFailure::Expired))
+		));
+	}
+	assert!(events.next().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn test_subscription_leases() {
+	let subscriptions = SubscriptionTable::new();
+	let peer = PeerId::random();
+	let key = PartialKey::random();
+
+	// a lease is capped at the most the server grants, and must not be zero
+	let mut request = subscribe_request(key.clone());
+	request.lease = SUBSCRIPTION_MAX_LEASE * 2;
+	let subscription = subscriptions.subscribe(peer, request).await.unwrap();
+	assert_eq!(subscription.lease, SUBSCRIPTION_MAX_LEASE);
+	request = subscribe_request(key.clone());
+	request.lease = 0;
+	assert!(matches!(
+		subscriptions.subscribe(peer, request).await,
+		Err(SubscribeFailure::Invalid)
+	));
+
+	// an expired subscription is dropped, ending its stream
+	request = subscribe_request(key);
+	request.lease = 1;
+	let mut expiring = subscriptions.subscribe(peer, request).await.unwrap();
+	assert_eq!(expiring.lease, 1);
+	assert_eq!(subscriptions.len(), 2);
+	subscriptions.expire();
+	assert_eq!(subscriptions.len(), 2);
+
+	tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+	subscriptions.expire();
+	assert_eq!(subscriptions.len(), 1);
+	assert!(expiring.events.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_subscription_leases_run_out() {
+	let handler = StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+	let subscriptions = handler.subscriptions().clone();
+	let peer = PeerId::random();
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let mut request = subscribe_request(PartialKey {
+		signer: Some(key.signer.clone()),
+		..Default::default()
+	});
+	request.lease = 1;
+	let mut expiring = subscriptions.subscribe(peer, request).await.unwrap();
+	let mut other = subscribe(&subscriptions, peer, PartialKey::random()).await;
+
+	// the stream ends once the lease runs out, with nothing subscribed since
+	let ended = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		expiring.events.next(),
+	)
+	.await;
+	assert!(matches!(ended, Ok(None)));
+
+	// and the subscription is dropped by the next put, whatever its key
+	assert_eq!(subscriptions.len(), 2);
+	let writer = Keypair::random();
+	let (routing_key, request) =
+		put_request(&writer, &signed_key(&writer), b"data");
+	handler.put_record(peer, routing_key, request).await.unwrap();
+	assert_eq!(subscriptions.len(), 1);
+	assert!(other.next().now_or_never().is_none());
+}
+
+/*
+   Replication
+*/
+
+// opens streams to the dispatchers of the servers in a test network
+struct TestNetwork {
+	local: PeerId,
+	servers: Arc<DashMap<PeerId, Dispatcher>>,
+}
+
+#[async_trait]
+impl StreamOpener for TestNetwork {
+	type Stream = PipeStream;
+
+	async fn open(&self, peer: PeerId) -> Result<PipeStream, SubfieldError> {
+		let dispatcher = match self.servers.get(&peer) {
+			Some(dispatcher) => dispatcher.clone(),
+			None => return Err(SubfieldError::FailedToOpenStream),
+		};
+		let (client, server) = pipe();
+		let local = self.local;
+		tokio::spawn(async move { dispatcher.serve_stream(local, server).await });
+		Ok(client)
+	}
+}
+
+struct TestServer {
+	peer: PeerId,
+	table: Arc<RoutingTable>,
+	store: Arc<MemoryRecordStore>,
+	replicator: Arc<Replicator<TestNetwork>>,
+}
+
+// a server storing each routing location on the two closest servers
+fn add_server(servers: &Arc<DashMap<PeerId, Dispatcher>>) -> TestServer {
+	let peer = random_peer_id();
+	let table = Arc::new(RoutingTable::new(peer).unwrap());
+	let store = Arc::new(MemoryRecordStore::new());
+	let handler = StoreRecordHandler::new(store.clone());
+	let network = TestNetwork {
+		local: peer,
+		servers: servers.clone(),
+	};
+	let replicator = Arc::new(
+		Replicator::new(handler.clone(), table.clone(), network)
+			.with_replication_factor(2),
+	);
+	let dispatcher = Dispatcher::new(
+		Arc::new(DefaultSystemHandler),
+		replicator.clone(),
+		Arc::new(handler.subscriptions().clone()),
+	);
+	servers.insert(peer, dispatcher);
+	TestServer {
+		peer,
+		table,
+		store,
+		replicator,
+	}
+}
+
+fn connect(servers: &[TestServer]) {
+	for server in servers {
+		for other in servers {
+			server.table.add_peer(other.peer).unwrap();
+		}
+	}
+}
+
+async fn repair(servers: &[TestServer]) {
+	for server in servers {
+		server.replicator.repair().await;
+	}
+}
+
+// the servers that should hold the key, and the servers that do
+fn replica_sets(
+	servers: &[TestServer],
+	key: &CompleteKey,
+) -> (HashSet<PeerId>, HashSet<PeerId>) {
+	let replicas = key
+		.to_routing_keys()
+		.iter()
+		.flat_map(|routing_key| {
+			servers[0].replicator.replicas(routing_key).unwrap()
+		})
+		.collect();
+	let holders = servers
+		.iter()
+		.filter(|server| server.store.get(key).unwrap().is_some())
+		.map(|server| server.peer)
+		.collect();
+	(replicas, holders)
+}
+
+#[tokio::test]
+async fn test_replication() {
+	let network = Arc::new(DashMap::new());
+	let mut servers: Vec<_> = (0..6).map(|_| add_server(&network)).collect();
+	connect(&servers);
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let mut record = Record::new(RecordType::Simple, key.clone(), b"data");
+
+	// each routing location is put on its closest server, which pushes it on
+	for request in record.to_put_record_requests(&key, &keypair).unwrap() {
+		let SubfieldRequestBody::PutRecord(put) = request.body else {
+			unreachable!()
+		};
+		let replicas = servers[0].replicator.replicas(&request.routing_key);
+		let primary = replicas.unwrap()[0];
+		let server = servers.iter().find(|s| s.peer == primary).unwrap();
+		server
+			.replicator
+			.put_record(PeerId::random(), request.routing_key, put)
+			.await
+			.unwrap();
+	}
+	let (replicas, holders) = replica_sets(&servers, &key);
+	assert_eq!(replicas, holders);
+
+	// servers that join are pushed the records they are now replicas of
+	servers.extend((0..4).map(|_| add_server(&network)));
+	connect(&servers);
+	repair(&servers).await;
+	let (replicas, holders) = replica_sets(&servers, &key);
+	assert!(replicas.is_subset(&holders));
+
+	// as are the servers closest once a replica leaves
+	let left = servers.remove(
+		servers
+			.iter()
+			.position(|server| replicas.contains(&server.peer))
+			.unwrap(),
+	);
+	network.remove(&left.peer);
+	for server in &servers {
+		server.table.remove_peer(&left.peer);
+	}
+	repair(&servers).await;
+	let (replicas, holders) = replica_sets(&servers, &key);
+	assert!(replicas.is_subset(&holders));
+	assert!(servers
+		.iter()
+		.all(|server| server.replicator.metrics().under_replicated == 0));
+
+	// a newer version held by one replica is pulled by the other
+	let location = key.to_signer_routing_key();
+	let [first, second] = servers[0].replicator.replicas(&location).unwrap()[..]
+	else {
+		unreachable!()
+	};
+	let server = |peer| servers.iter().find(|s| s.peer == peer).unwrap();
+	record.update(b"newer");
+	let (routing_key, put) = record_put_request(&record, &keypair);
+	let newer = SignedRecord::from_put_record_request(routing_key, put).unwrap();
+	server(first).store.put(newer.clone()).unwrap();
+	let metrics = server(second).replicator.repair().await;
+	assert!(metrics.pulled >= 1);
+	let pulled = server(second).store.get(&key).unwrap().unwrap();
+	assert_eq!(pulled.record_bytes, newer.record_bytes);
+
+	// a replica that cannot be reached leaves the key under-replicated
+	network.remove(&first);
+	let metrics = server(second).replicator.repair().await;
+	assert_eq!(metrics.under_replicated, 1);
+	assert_eq!(server(second).replicator.under_replicated_keys(), vec![key]);
+}
+
+#[tokio::test]
+async fn test_tombstone_repair() {
+	let network = Arc::new(DashMap::new());
+	let servers: Vec<_> = (0..4).map(|_| add_server(&network)).collect();
+	connect(&servers);
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let record = Record::new(RecordType::Simple, key.clone(), b"data");
+	let server = |peer| servers.iter().find(|s| s.peer == peer).unwrap();
+	let primary = |routing_key: &RoutingKey| {
+		servers[0].replicator.replicas(routing_key).unwrap()[0]
+	};
+
+	for request in record.to_put_record_requests(&key, &keypair).unwrap() {
+		let SubfieldRequestBody::PutRecord(put) = request.body else {
+			unreachable!()
+		};
+		server(primary(&request.routing_key))
+			.replicator
+			.put_record(PeerId::random(), request.routing_key, put)
+			.await
+			.unwrap();
+	}
+	let (replicas, holders) = replica_sets(&servers, &key);
+	assert_eq!(replicas, holders);
+
+	// the record is deleted while a replica of the signer location is down,
+	// through the next closest server where it would have been the primary
+	let location = key.to_signer_routing_key();
+	let down = servers[0].replicator.replicas(&location).unwrap()[1];
+	let dispatcher = network.remove(&down).unwrap().1;
+	let deletes =
+		delete_requests(record.to_delete_record_requests(&keypair).unwrap());
+	for (routing_key, delete) in deletes {
+		let replicas = servers[0].replicator.replicas(&routing_key).unwrap();
+		let up = replicas.into_iter().find(|peer| *peer != down).unwrap();
+		server(up)
+			.replicator
+			.delete_record(PeerId::random(), routing_key, delete)
+			.await
+			.unwrap();
+	}
+	assert!(server(down).store.get(&key).unwrap().is_some());
+	assert!(server(down).store.get_tombstone(&key).unwrap().is_none());
+
+	// once it is back a repair pushes it the tombstone, rather than anyone
+	// pulling the deleted record back from it
+	network.insert(down, dispatcher);
+	repair(&servers).await;
+	assert!(server(down).store.get_tombstone(&key).unwrap().is_some());
+	let (_, holders) = replica_sets(&servers, &key);
+	assert!(holders.is_empty());
+
+	// after which the replicas agree
+	let metrics = server(down).replicator.repair().await;
+	let counts = (metrics.records, metrics.tombstones);
+	assert_eq!((counts, metrics.pushed, metrics.pulled), ((0, 1), 0, 0));
+	assert!(servers
+		.iter()
+		.all(|server| server.replicator.metrics().under_replicated == 0));
+}
+
+/*
+   Quorum reads
+*/
+
+// a separate store for each kind of routing location
+struct LocationHandler {
+	locations: [StoreRecordHandler; 3],
+}
+
+impl LocationHandler {
+	fn new() -> Self {
+		let handler =
+			|| StoreRecordHandler::new(Arc::new(MemoryRecordStore::new()));
+		Self {
+			locations: [handler(), handler(), handler()],
+		}
+	}
+
+	fn location(&self, routing_key: &RoutingKey) -> &StoreRecordHandler {
+		match routing_key {
+			RoutingKey::Signer(_) => &self.locations[0],
+			RoutingKey::Cosigner(_) => &self.locations[1],
+			RoutingKey::Tangent(_) => &self.locations[2],
+		}
+	}
+
+	async fn put(&self, request: SubfieldRequest) {
+		let SubfieldRequestBody::PutRecord(put) = request.body else {
+			unreachable!()
+		};
+		self.location(&request.routing_key)
+			.put_record(PeerId::random(), request.routing_key, put)
+			.await
+			.unwrap();
+	}
+}
+
+#[async_trait]
+impl RecordHandler for LocationHandler {
+	async fn get_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: GetRecordRequest,
+	) -> GetRecordResponse {
+		let location = self.location(&routing_key);
+		location.get_record(peer, routing_key, request).await
+	}
+
+	async fn put_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: PutRecordRequest,
+	) -> PutRecordResponse {
+		let location = self.location(&routing_key);
+		location.put_record(peer, routing_key, request).await
+	}
+
+	async fn delete_record(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: DeleteRecordRequest,
+	) -> DeleteRecordResponse {
+		let location = self.location(&routing_key);
+		location.delete_record(peer, routing_key, request).await
+	}
+
+	async fn list_records(
+		&self,
+		peer: PeerId,
+		routing_key: RoutingKey,
+		request: ListRecordsRequest,
+	) -> Result<ListRecordsStream, ListRecordsFailure> {
+		let location = self.location(&routing_key);
+		location.list_records(peer, routing_key, request).await
+	}
+}
+
+#[tokio::test]
+async fn test_quorum_reads() {
+	let handler = Arc::new(LocationHandler::new());
+	let reader = RecordReader::new(handler.clone(), PeerId::random());
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	assert!(matches!(
+		reader.read(&key).await,
+		Err(ReadRecordError::Unknown)
+	));
+
+	// one location alone is enough for the first valid read only
+	let mut record = Record::new(RecordType::Simple, key.clone(), b"first");
+	let [signer, cosigner, tangent] =
+		record.to_put_record_requests(&key, &keypair).unwrap();
+	handler.put(signer).await;
+	assert!(matches!(
+		reader.read(&key).await,
+		Err(ReadRecordError::NoQuorum { valid: 1 })
+	));
+	let read = reader
+		.clone()
+		.with_consistency(ReadConsistency::FirstValid)
+		.read(&key)
+		.await
+		.unwrap();
+	assert_eq!(read.record.data(), b"first");
+	handler.put(cosigner).await;
+	handler.put(tangent).await;
+
+	// the newest version wins and the older locations are reported
+	record.update(b"second");
+	let [signer, _, _] = record.to_put_record_requests(&key, &keypair).unwrap();
+	handler.put(signer).await;
+	let reader = reader.with_read_repair(true);
+	let read = reader.read(&key).await.unwrap();
+	assert_eq!(read.record.data(), b"second");
+	assert!(read.disagrees());
+	assert!(matches!(
+		read.locations[..],
+		[
+			(RoutingKey::Signer(_), LocationRead::Newest),
+			(RoutingKey::Cosigner(_), LocationRead::Stale { version: 0 }),
+			(RoutingKey::Tangent(_), LocationRead::Stale { version: 0 }),
+		]
+	));
+
+	// and brought up to date by read repair
+	assert_eq!(read.repaired, 2);
+	let read = reader.read(&key).await.unwrap();
+	assert!(!read.disagrees());
+	assert_eq!(read.repaired, 0);
+}
+
+/*
+   Client
+*/
+
+#[tokio::test]
+async fn test_client() {
+	let network = Arc::new(DashMap::new());
+	let servers: Vec<_> = (0..4).map(|_| add_server(&network)).collect();
+	connect(&servers);
+	let peer = random_peer_id();
+	let table = Arc::new(RoutingTable::new(peer).unwrap());
+	for server in &servers {
+		table.add_peer(server.peer).unwrap();
+	}
+	let client = SubfieldClient::new(
+		TestNetwork {
+			local: peer,
+			servers: network.clone(),
+		},
+		table,
+	);
+
+	// system requests go to the peer asked for
+	client.ping(servers[0].peer).await.unwrap();
+	let echoed = client.echo(servers[1].peer, "hello").await.unwrap();
+	assert_eq!(echoed, "hello");
+	let error = client.ping(random_peer_id()).await.unwrap_err();
+	assert!(matches!(error, ClientError::Network(_)));
+
+	// records are put at every routing location and read back by quorum
+	let keypair = Keypair::random();
+	let key = signed_key(&keypair);
+	let mut record = Record::new(RecordType::Simple, key.clone(), b"data");
+	client.put(&record, &keypair).await.unwrap();
+	assert_eq!(client.get(&key).await.unwrap().data(), b"data");
+	let stale = record.clone();
+	record.update(b"newer");
+	client.put(&record, &keypair).await.unwrap();
+	let error = client.put(&stale, &keypair).await.unwrap_err();
+	assert!(matches!(error, ClientError::Stale { .. }));
+	assert_eq!(client.get(&key).await.unwrap().data(), b"newer");
+
+	// subscribers receive the records put under matching keys
+	let partial = PartialKey {
+		signer: Some(key.signer.clone()),
+		..Default::default()
+	};
+	let mut records = Box::pin(client.subscribe(partial.clone()).unwrap());
+	let (received, _) = tokio::join!(records.next(), async {
+		// put once the subscription is made
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+		record.update(b"newest");
+		client.put(&record, &keypair).await.unwrap();
+	});
+	assert_eq!(received.unwrap().data(), b"newest");
+	client.unsubscribe(&partial).unwrap();
+	assert!(records.next().await.is_none());
+
+	// deleted records are no longer found
+	client.delete(&key, record.version, &keypair).await.unwrap();
+	let error = client.get(&key).await.unwrap_err();
+	assert!(matches!(error, ClientError::NotFound));
+
+	// a key can be deleted before its record arrives anywhere
+	let unseen = signed_key(&keypair);
+	client.delete(&unseen, 0, &keypair).await.unwrap();
+	let unseen = Record::new(RecordType::Simple, unseen, b"late");
+	let error = client.put(&unseen, &keypair).await.unwrap_err();
+	assert!(matches!(error, ClientError::Deleted));
+}
+
+#[tokio::test]
+async fn test_client_unsubscribe() {
+	let network = Arc::new(DashMap::new());
+	let servers: Vec<_> = (0..2).map(|_| add_server(&network)).collect();
+	connect(&servers);
+	let peer = random_peer_id();
+	let table = Arc::new(RoutingTable::new(peer).unwrap());
+	table.add_peer(servers[0].peer).unwrap();
+	let client = SubfieldClient::new(
+		TestNetwork {
+			local: peer,
+			servers: network.clone(),
+		},
+		table.clone(),
+	);
+	let subscriptions = |server: &TestServer| {
+		server.replicator.records().subscriptions().len()
+	};
+
+	// the subscription is made once the stream is first polled
+	let partial = CompleteKey::random().to_partial();
+	let mut records = Box::pin(client.subscribe(partial.clone()).unwrap());
+	let polled = tokio::time::timeout(
+		std::time::Duration::from_millis(50),
+		records.next(),
+	);
+	assert!(polled.await.is_err());
+	assert_eq!(subscriptions(&servers[0]), 1);
+
+	// the unsubscribe goes to the server serving it, not the closest one now
+	table.remove_peer(&servers[0].peer);
+	table.add_peer(servers[1].peer).unwrap();
+	client.unsubscribe(&partial).unwrap();
+	assert!(records.next().await.is_none());
+	let ended = async {
+		while subscriptions(&servers[0]) > 0 {
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+	};
+	tokio::time::timeout(std::time::Duration::from_millis(500), ended)
+		.await
+		.unwrap();
+
+	// nothing is left to unsubscribe from
+	let error = client.unsubscribe(&partial).unwrap_err();
+	assert!(matches!(error, ClientError::NotFound));
+
+	// a dropped stream is no longer subscribed
+	let records = Box::pin(client.subscribe(partial.clone()).unwrap());
+	drop(records);
+	let error = client.unsubscribe(&partial).unwrap_err();
+	assert!(matches!(error, ClientError::NotFound));
+}
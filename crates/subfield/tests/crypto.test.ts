@@ -57,3 +57,87 @@ test("keypair - serialization", async () => {
 	let deserialized = hs.Keypair.fromBytes(serialized)
 	expect(keypair.toString()).toBe(deserialized.toString())
 })
+
+test("cipher - errors", async () => {
+	const cipher = hs.Cipher.random()
+	const encrypted = cipher.encrypt(hs.fromString("hello"))
+
+	// altered ciphertexts and the wrong key throw
+	const tampered = encrypted.slice()
+	tampered[tampered.length - 1] ^= 1
+	expect(() => cipher.decrypt(tampered)).toThrow("DecryptionFailed")
+	expect(() => cipher.decrypt(encrypted.slice(0, 4))).toThrow("InvalidNonce")
+	expect(() => hs.Cipher.random().decrypt(encrypted)).toThrow(
+		"DecryptionFailed"
+	)
+})
+
+test("cipher - aad and xchacha", async () => {
+	const cipher = hs.Cipher.random().withAlgorithm(
+		hs.CipherAlgorithm.XChaCha20Poly1305
+	)
+	const aad = hs.fromString("key")
+
+	const encrypted = cipher.encryptWithAad(hs.fromString("hello"), aad)
+	const decrypted = cipher.decryptWithAad(encrypted, aad)
+	expect(hs.toString(decrypted)).toBe("hello")
+
+	// bound to the associated data
+	expect(() =>
+		cipher.decryptWithAad(encrypted, hs.fromString("other"))
+	).toThrow("DecryptionFailed")
+})
+
+test("cipher - stream", async () => {
+	const cipher = hs.Cipher.random()
+	const data = hs.fromString("a".repeat(200 * 1024))
+
+	const encryptor = cipher.streamEncryptor()
+	const parts = [
+		encryptor.update(data.slice(0, 100_000)),
+		encryptor.update(data.slice(100_000)),
+		encryptor.finish(),
+	]
+	const encrypted = new Uint8Array(
+		parts.reduce((len, part) => len + part.length, 0)
+	)
+	let offset = 0
+	for (const part of parts) {
+		encrypted.set(part, offset)
+		offset += part.length
+	}
+
+	const decryptor = cipher.streamDecryptor()
+	const decrypted = [decryptor.update(encrypted), decryptor.finish()]
+	expect(hs.toString(decrypted[0]) + hs.toString(decrypted[1])).toBe(
+		hs.toString(data)
+	)
+
+	// cut short
+	const truncated = cipher.streamDecryptor()
+	truncated.update(encrypted.slice(0, encrypted.length - 100))
+	expect(() => truncated.finish()).toThrow()
+})
+
+test("kdf", async () => {
+	const alice = hs.Keypair.random()
+	const bob = hs.Keypair.random()
+
+	// both sides derive the same cipher key
+	const aliceKey = hs.deriveSharedCipherKey(alice, bob.publicKey)
+	const bobKey = hs.deriveSharedCipherKey(bob, alice.publicKey)
+	const encrypted = new hs.Cipher(aliceKey).encrypt(hs.fromString("hello"))
+	const decrypted = new hs.Cipher(bobKey).decrypt(encrypted)
+	expect(hs.toString(decrypted)).toBe("hello")
+
+	// cheap params, the defaults take a while
+	const params = new hs.PasswordKdfParams(256, 1, 1)
+	const salt = hs.randomSalt()
+	const passwordKey = hs.deriveCipherKeyFromPassword("password", salt, params)
+	const sameKey = hs.deriveCipherKeyFromPassword("password", salt, params)
+	expect(passwordKey.toString()).toBe(sameKey.toString())
+
+	expect(() =>
+		hs.derivePasswordKey("password", hs.fromString("salt"), params)
+	).toThrow("InvalidSalt")
+})
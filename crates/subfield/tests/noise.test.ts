@@ -37,3 +37,55 @@ test("noise", async () => {
 	decrypted = initiator.decrypt(encrypted)
 	expect(hs.toString(decrypted)).toBe(large)
 })
+
+test("noise xx", async () => {
+	const alice = hs.Keypair.random()
+	const bob = hs.Keypair.random()
+
+	// the initiator expects the responder to hold bob's key
+	const initiator = hs.Noise.initiatorWithPattern(
+		hs.NoisePattern.XX,
+		alice,
+		bob.publicKey
+	)
+	const responder = hs.Noise.responderWithPattern(
+		hs.NoisePattern.XX,
+		bob,
+		undefined
+	)
+
+	let message1 = initiator.handshakeStep1()
+	let message2 = responder.handshakeStep2(message1)
+	let message3 = initiator.handshakeStep3(message2)
+	responder.handshakeStep4(message3!)
+
+	expect(initiator.isHandshakeFinished).toBe(true)
+	expect(responder.isHandshakeFinished).toBe(true)
+	expect(responder.remotePublicKey?.toString()).toBe(
+		alice.publicKey.toString()
+	)
+
+	let encrypted = initiator.encrypt(hs.fromString("hello world!"))
+	expect(hs.toString(responder.decrypt(encrypted))).toBe("hello world!")
+})
+
+test("noise - errors", async () => {
+	const initiator = hs.Noise.initiator()
+	const responder = hs.Noise.responder()
+
+	// nothing is encrypted before the handshake
+	expect(() => initiator.encrypt(hs.fromString("hello"))).toThrow(
+		"InvalidState"
+	)
+
+	// the responder does not write first
+	expect(() => responder.handshakeStep1()).toThrow("InvalidState")
+
+	const message = responder.handshakeStep2(initiator.handshakeStep1())
+	initiator.handshakeStep3(message)
+
+	// altered messages throw
+	const encrypted = initiator.encrypt(hs.fromString("hello"))
+	encrypted[0] ^= 1
+	expect(() => responder.decrypt(encrypted)).toThrow("FailedToDecrypt")
+})